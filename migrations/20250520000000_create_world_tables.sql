@@ -0,0 +1,62 @@
+-- Core world tables, previously created ad hoc by db::init_db.
+-- Every stat that the engine treats as a percentage is bounded so a runaway delta
+-- fails loudly instead of silently corrupting the world.
+
+CREATE TABLE world (
+    id INTEGER PRIMARY KEY,
+    tension INTEGER NOT NULL DEFAULT 0 CHECK (tension BETWEEN 0 AND 100),
+    story_phase TEXT NOT NULL
+);
+
+CREATE TABLE locations (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    prosperity INTEGER NOT NULL CHECK (prosperity BETWEEN 0 AND 100),
+    safety INTEGER NOT NULL CHECK (safety BETWEEN 0 AND 100)
+);
+
+CREATE TABLE factions (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    power INTEGER NOT NULL CHECK (power BETWEEN 0 AND 100),
+    relation TEXT NOT NULL CHECK (relation IN ('Friendly', 'Neutral', 'Hostile'))
+);
+
+CREATE TABLE npcs (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    role TEXT NOT NULL,
+    status TEXT NOT NULL,
+    location_id INTEGER NOT NULL,
+    FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+
+CREATE TABLE player (
+    id INTEGER PRIMARY KEY,
+    location_id INTEGER NOT NULL,
+    reputation INTEGER NOT NULL CHECK (reputation BETWEEN -100 AND 100),
+    FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+
+CREATE TABLE story_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    narrative TEXT NOT NULL,
+    state_changes TEXT NOT NULL DEFAULT '{}', -- JSON string of changes applied
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE branch_choices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    state_changes TEXT NOT NULL DEFAULT '{}', -- JSON string of changes
+    is_default BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY (event_id) REFERENCES story_events(id) ON DELETE CASCADE
+);
+
+CREATE TABLE event_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL DEFAULT (datetime('now')),
+    description TEXT NOT NULL,
+    caused_by TEXT NOT NULL
+);
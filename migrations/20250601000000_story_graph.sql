@@ -0,0 +1,12 @@
+-- Link story events into a graph: each event may follow the branch choice that led to it,
+-- and each choice records whether and how it was taken.
+
+ALTER TABLE story_events ADD COLUMN event_id INTEGER REFERENCES events(id);
+ALTER TABLE story_events ADD COLUMN parent_choice_id INTEGER REFERENCES branch_choices(id);
+ALTER TABLE story_events ADD COLUMN choices_expire_at TIMESTAMP;
+
+ALTER TABLE branch_choices ADD COLUMN resolved_by TEXT CHECK (resolved_by IN ('player', 'timeout'));
+ALTER TABLE branch_choices ADD COLUMN resolved_at TIMESTAMP;
+
+CREATE INDEX idx_branch_choices_event ON branch_choices(event_id);
+CREATE INDEX idx_story_events_parent ON story_events(parent_choice_id);
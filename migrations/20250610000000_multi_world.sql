@@ -0,0 +1,97 @@
+-- Worlds become tenants: every entity is owned by exactly one world. Ids stay globally
+-- unique, so existing references (npcs.location_id, state change paths) keep working.
+-- Tables are rebuilt because SQLite cannot add a NOT NULL foreign key in place;
+-- db::run_migrations disables foreign key enforcement while this runs.
+
+ALTER TABLE world ADD COLUMN name TEXT NOT NULL DEFAULT 'Campaign';
+ALTER TABLE world ADD COLUMN seed TEXT NOT NULL DEFAULT 'default';
+
+CREATE TABLE locations_new (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    prosperity INTEGER NOT NULL CHECK (prosperity BETWEEN 0 AND 100),
+    safety INTEGER NOT NULL CHECK (safety BETWEEN 0 AND 100),
+    UNIQUE (world_id, name),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE
+);
+INSERT INTO locations_new (id, world_id, name, prosperity, safety)
+    SELECT id, 1, name, prosperity, safety FROM locations;
+DROP TABLE locations;
+ALTER TABLE locations_new RENAME TO locations;
+
+CREATE TABLE factions_new (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    power INTEGER NOT NULL CHECK (power BETWEEN 0 AND 100),
+    relation TEXT NOT NULL CHECK (relation IN ('Friendly', 'Neutral', 'Hostile')),
+    UNIQUE (world_id, name),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE
+);
+INSERT INTO factions_new (id, world_id, name, power, relation)
+    SELECT id, 1, name, power, relation FROM factions;
+DROP TABLE factions;
+ALTER TABLE factions_new RENAME TO factions;
+
+CREATE TABLE npcs_new (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    role TEXT NOT NULL,
+    status TEXT NOT NULL,
+    location_id INTEGER NOT NULL,
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE,
+    FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+INSERT INTO npcs_new (id, world_id, name, role, status, location_id)
+    SELECT id, 1, name, role, status, location_id FROM npcs;
+DROP TABLE npcs;
+ALTER TABLE npcs_new RENAME TO npcs;
+
+CREATE TABLE player_new (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL UNIQUE,
+    location_id INTEGER NOT NULL,
+    reputation INTEGER NOT NULL CHECK (reputation BETWEEN -100 AND 100),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE,
+    FOREIGN KEY (location_id) REFERENCES locations(id)
+);
+INSERT INTO player_new (id, world_id, location_id, reputation)
+    SELECT id, 1, location_id, reputation FROM player;
+DROP TABLE player;
+ALTER TABLE player_new RENAME TO player;
+
+CREATE TABLE story_events_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    world_id INTEGER NOT NULL,
+    narrative TEXT NOT NULL,
+    state_changes TEXT NOT NULL DEFAULT '{}', -- JSON string of changes applied
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    event_id INTEGER REFERENCES events(id),
+    parent_choice_id INTEGER REFERENCES branch_choices(id),
+    choices_expire_at TIMESTAMP,
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE
+);
+INSERT INTO story_events_new (id, world_id, narrative, state_changes, created_at, event_id, parent_choice_id, choices_expire_at)
+    SELECT id, 1, narrative, state_changes, created_at, event_id, parent_choice_id, choices_expire_at FROM story_events;
+DROP TABLE story_events;
+ALTER TABLE story_events_new RENAME TO story_events;
+CREATE INDEX idx_story_events_parent ON story_events(parent_choice_id);
+CREATE INDEX idx_story_events_world ON story_events(world_id);
+
+CREATE TABLE event_log_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    world_id INTEGER NOT NULL,
+    timestamp TEXT NOT NULL DEFAULT (datetime('now')),
+    description TEXT NOT NULL,
+    caused_by TEXT NOT NULL,
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE
+);
+INSERT INTO event_log_new (id, world_id, timestamp, description, caused_by)
+    SELECT id, 1, timestamp, description, caused_by FROM event_log;
+DROP TABLE event_log;
+ALTER TABLE event_log_new RENAME TO event_log;
+CREATE INDEX idx_event_log_world ON event_log(world_id);
+
+CREATE INDEX idx_events_world ON events(world_id);
@@ -0,0 +1,51 @@
+-- A world holds any number of player characters, optionally grouped into parties, each
+-- carrying their own inventory. `player` becomes `players` now that it has many rows.
+
+CREATE TABLE parties (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    UNIQUE (world_id, name),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE
+);
+
+CREATE TABLE players (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    location_id INTEGER NOT NULL,
+    reputation INTEGER NOT NULL CHECK (reputation BETWEEN -100 AND 100),
+    party_id INTEGER,
+    UNIQUE (world_id, name),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE,
+    FOREIGN KEY (location_id) REFERENCES locations(id),
+    FOREIGN KEY (party_id) REFERENCES parties(id) ON DELETE SET NULL
+);
+INSERT INTO players (id, world_id, name, location_id, reputation)
+    SELECT id, world_id, 'The Knight', location_id, reputation FROM player;
+DROP TABLE player;
+CREATE INDEX idx_players_world ON players(world_id);
+CREATE INDEX idx_players_party ON players(party_id);
+
+CREATE TABLE inventory (
+    player_id INTEGER NOT NULL,
+    item TEXT NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (player_id, item),
+    FOREIGN KEY (player_id) REFERENCES players(id) ON DELETE CASCADE
+);
+
+-- Stored consequences addressed the single player as `player.<field>`; point them at
+-- that world's (only) character instead.
+UPDATE branch_choices SET state_changes = replace(
+    state_changes,
+    '"player.',
+    '"players.' || (SELECT p.id FROM players p JOIN story_events e ON e.world_id = p.world_id WHERE e.id = branch_choices.event_id) || '.'
+)
+WHERE state_changes LIKE '%"player.%';
+UPDATE story_events SET state_changes = replace(
+    state_changes,
+    '"player.',
+    '"players.' || (SELECT p.id FROM players p WHERE p.world_id = story_events.world_id) || '.'
+)
+WHERE state_changes LIKE '%"player.%';
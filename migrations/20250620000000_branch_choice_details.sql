@@ -0,0 +1,4 @@
+-- Branch choices are generated as structured JSON; keep the model's risk estimate and the
+-- entities it said the choice affects (a JSON list of names).
+ALTER TABLE branch_choices ADD COLUMN risk TEXT CHECK (risk IN ('low', 'medium', 'high'));
+ALTER TABLE branch_choices ADD COLUMN affected TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,9 @@
+-- The compressed "story so far" for each world, folded forward one narrated event at a time.
+CREATE TABLE story_summaries (
+    world_id INTEGER PRIMARY KEY,
+    summary TEXT NOT NULL,
+    last_event_id INTEGER,
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE,
+    FOREIGN KEY (last_event_id) REFERENCES events(id)
+);
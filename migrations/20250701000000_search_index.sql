@@ -0,0 +1,58 @@
+-- Keyword index over what happened in each world (narrated events and the game master's
+-- log) and the names of its places and people, for prompt retrieval and event search.
+-- Triggers keep it in step, so a migration that rebuilds one of these tables must
+-- recreate its triggers.
+
+CREATE VIRTUAL TABLE search_index USING fts5(
+    text,
+    kind UNINDEXED,
+    world_id UNINDEXED,
+    source_id UNINDEXED,
+    created_at UNINDEXED,
+    tokenize = 'porter unicode61'
+);
+
+INSERT INTO search_index (text, kind, world_id, source_id, created_at)
+    SELECT description, 'event', world_id, id, CAST(created_at AS TEXT) FROM events;
+INSERT INTO search_index (text, kind, world_id, source_id, created_at)
+    SELECT description, 'log', world_id, id, timestamp FROM event_log;
+INSERT INTO search_index (text, kind, world_id, source_id)
+    SELECT name, 'location', world_id, id FROM locations;
+INSERT INTO search_index (text, kind, world_id, source_id)
+    SELECT name || ', ' || role, 'npc', world_id, id FROM npcs;
+
+CREATE TRIGGER events_search_insert AFTER INSERT ON events BEGIN
+    INSERT INTO search_index (text, kind, world_id, source_id, created_at)
+        VALUES (new.description, 'event', new.world_id, new.id, CAST(new.created_at AS TEXT));
+END;
+CREATE TRIGGER events_search_delete AFTER DELETE ON events BEGIN
+    DELETE FROM search_index WHERE kind = 'event' AND source_id = old.id;
+END;
+
+CREATE TRIGGER event_log_search_insert AFTER INSERT ON event_log BEGIN
+    INSERT INTO search_index (text, kind, world_id, source_id, created_at)
+        VALUES (new.description, 'log', new.world_id, new.id, new.timestamp);
+END;
+CREATE TRIGGER event_log_search_delete AFTER DELETE ON event_log BEGIN
+    DELETE FROM search_index WHERE kind = 'log' AND source_id = old.id;
+END;
+
+CREATE TRIGGER locations_search_insert AFTER INSERT ON locations BEGIN
+    INSERT INTO search_index (text, kind, world_id, source_id) VALUES (new.name, 'location', new.world_id, new.id);
+END;
+CREATE TRIGGER locations_search_update AFTER UPDATE OF name ON locations BEGIN
+    UPDATE search_index SET text = new.name WHERE kind = 'location' AND source_id = old.id;
+END;
+CREATE TRIGGER locations_search_delete AFTER DELETE ON locations BEGIN
+    DELETE FROM search_index WHERE kind = 'location' AND source_id = old.id;
+END;
+
+CREATE TRIGGER npcs_search_insert AFTER INSERT ON npcs BEGIN
+    INSERT INTO search_index (text, kind, world_id, source_id) VALUES (new.name || ', ' || new.role, 'npc', new.world_id, new.id);
+END;
+CREATE TRIGGER npcs_search_update AFTER UPDATE OF name, role ON npcs BEGIN
+    UPDATE search_index SET text = new.name || ', ' || new.role WHERE kind = 'npc' AND source_id = old.id;
+END;
+CREATE TRIGGER npcs_search_delete AFTER DELETE ON npcs BEGIN
+    DELETE FROM search_index WHERE kind = 'npc' AND source_id = old.id;
+END;
@@ -0,0 +1,23 @@
+-- What each NPC remembers of conversations with player characters. Exchanges are also
+-- indexed for retrieval, so later prompts about the NPC or the subject bring them back.
+CREATE TABLE npc_memories (
+    id INTEGER PRIMARY KEY,
+    npc_id INTEGER NOT NULL,
+    player_id INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    reply TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (npc_id) REFERENCES npcs(id) ON DELETE CASCADE,
+    FOREIGN KEY (player_id) REFERENCES players(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_npc_memories_npc ON npc_memories(npc_id);
+
+CREATE TRIGGER npc_memories_search_insert AFTER INSERT ON npc_memories BEGIN
+    INSERT INTO search_index (text, kind, world_id, source_id, created_at)
+        SELECT p.name || ' said to ' || n.name || ': "' || new.message || '" ' || n.name || ' replied: "' || new.reply || '"',
+               'talk', n.world_id, new.id, new.created_at
+        FROM npcs n, players p WHERE n.id = new.npc_id AND p.id = new.player_id;
+END;
+CREATE TRIGGER npc_memories_search_delete AFTER DELETE ON npc_memories BEGIN
+    DELETE FROM search_index WHERE kind = 'talk' AND source_id = old.id;
+END;
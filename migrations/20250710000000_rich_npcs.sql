@@ -0,0 +1,28 @@
+-- NPCs belong to factions, have personalities and goals, feel a certain way about each
+-- player character and about each other.
+ALTER TABLE npcs ADD COLUMN faction_id INTEGER REFERENCES factions(id) ON DELETE SET NULL;
+ALTER TABLE npcs ADD COLUMN traits TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE npcs ADD COLUMN goals TEXT NOT NULL DEFAULT '[]';
+CREATE INDEX idx_npcs_faction ON npcs(faction_id);
+
+-- From -100 (hostile) to 100 (devoted); without a row an NPC goes by the player's reputation.
+CREATE TABLE npc_dispositions (
+    npc_id INTEGER NOT NULL,
+    player_id INTEGER NOT NULL,
+    disposition INTEGER NOT NULL CHECK (disposition BETWEEN -100 AND 100),
+    PRIMARY KEY (npc_id, player_id),
+    FOREIGN KEY (npc_id) REFERENCES npcs(id) ON DELETE CASCADE,
+    FOREIGN KEY (player_id) REFERENCES players(id) ON DELETE CASCADE
+);
+
+-- How `npc_id` sees `other_id`; the other direction is a separate row.
+CREATE TABLE npc_relationships (
+    npc_id INTEGER NOT NULL,
+    other_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    strength INTEGER NOT NULL CHECK (strength BETWEEN -100 AND 100),
+    PRIMARY KEY (npc_id, other_id),
+    CHECK (npc_id <> other_id),
+    FOREIGN KEY (npc_id) REFERENCES npcs(id) ON DELETE CASCADE,
+    FOREIGN KEY (other_id) REFERENCES npcs(id) ON DELETE CASCADE
+);
@@ -0,0 +1,22 @@
+-- How each pair of factions stands with each other, as opposed to `factions.relation`
+-- which is toward the player characters. One row per unordered pair, lower id first.
+CREATE TABLE faction_relations (
+    faction_id INTEGER NOT NULL,
+    other_id INTEGER NOT NULL,
+    standing INTEGER NOT NULL CHECK (standing BETWEEN -100 AND 100),
+    pact TEXT NOT NULL DEFAULT 'none' CHECK (pact IN ('none', 'treaty', 'alliance', 'war')),
+    PRIMARY KEY (faction_id, other_id),
+    CHECK (faction_id < other_id),
+    FOREIGN KEY (faction_id) REFERENCES factions(id) ON DELETE CASCADE,
+    FOREIGN KEY (other_id) REFERENCES factions(id) ON DELETE CASCADE
+);
+
+-- Existing factions that feel the same way about the players get along; friends and
+-- enemies of the players are at war.
+INSERT INTO faction_relations (faction_id, other_id, standing, pact)
+    SELECT a.id, b.id,
+           CASE WHEN a.relation = b.relation THEN 25
+                WHEN 'Neutral' IN (a.relation, b.relation) THEN 0
+                ELSE -50 END,
+           CASE WHEN a.relation <> b.relation AND 'Neutral' NOT IN (a.relation, b.relation) THEN 'war' ELSE 'none' END
+    FROM factions a JOIN factions b ON b.world_id = a.world_id AND a.id < b.id;
@@ -0,0 +1,18 @@
+-- Roads between locations, travelled both ways. One row per unordered pair, lower id first.
+-- Locations without a route between them are only reachable through others.
+CREATE TABLE routes (
+    location_id INTEGER NOT NULL,
+    other_id INTEGER NOT NULL,
+    travel_hours INTEGER NOT NULL CHECK (travel_hours > 0),
+    danger INTEGER NOT NULL CHECK (danger BETWEEN 0 AND 100),
+    PRIMARY KEY (location_id, other_id),
+    CHECK (location_id < other_id),
+    FOREIGN KEY (location_id) REFERENCES locations(id) ON DELETE CASCADE,
+    FOREIGN KEY (other_id) REFERENCES locations(id) ON DELETE CASCADE
+);
+
+-- Existing worlds could be crossed from anywhere to anywhere, so keep every pair of their
+-- locations linked. A road is as dangerous as its ends are unsafe.
+INSERT INTO routes (location_id, other_id, travel_hours, danger)
+    SELECT a.id, b.id, 4, (200 - a.safety - b.safety) / 2
+    FROM locations a JOIN locations b ON b.world_id = a.world_id AND a.id < b.id;
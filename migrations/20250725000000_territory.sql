@@ -0,0 +1,17 @@
+-- How firmly each faction holds each location, from 0 to 100. Without a row a faction has
+-- no hold there. Control is not stored: whoever has the most influence, at least 30 and
+-- more than anyone else, holds the location.
+CREATE TABLE location_influence (
+    location_id INTEGER NOT NULL,
+    faction_id INTEGER NOT NULL,
+    influence INTEGER NOT NULL CHECK (influence BETWEEN 0 AND 100),
+    PRIMARY KEY (location_id, faction_id),
+    FOREIGN KEY (location_id) REFERENCES locations(id) ON DELETE CASCADE,
+    FOREIGN KEY (faction_id) REFERENCES factions(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_location_influence_faction ON location_influence(faction_id);
+
+-- Existing factions hold some sway wherever their living members are.
+INSERT INTO location_influence (location_id, faction_id, influence)
+    SELECT DISTINCT location_id, faction_id, 40 FROM npcs
+    WHERE faction_id IS NOT NULL AND lower(status) <> 'dead';
@@ -0,0 +1,3 @@
+-- Coins each player character carries. Existing characters get the purse new ones start
+-- with (items::STARTING_COINS).
+ALTER TABLE players ADD COLUMN coins INTEGER NOT NULL DEFAULT 50 CHECK (coins >= 0);
@@ -0,0 +1,33 @@
+-- Quests taken up by one player character or a whole party. Rewards go to every holder
+-- once all objectives are done.
+CREATE TABLE quests (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'completed', 'failed')),
+    player_id INTEGER,
+    party_id INTEGER,
+    reward_reputation INTEGER NOT NULL DEFAULT 0,
+    reward_coins INTEGER NOT NULL DEFAULT 0 CHECK (reward_coins >= 0),
+    reward_item TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    CHECK ((player_id IS NULL) <> (party_id IS NULL)),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE,
+    FOREIGN KEY (player_id) REFERENCES players(id) ON DELETE CASCADE,
+    FOREIGN KEY (party_id) REFERENCES parties(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_quests_world ON quests(world_id);
+
+-- The target is a location, faction or NPC id depending on the kind, so it has no foreign
+-- key; objectives whose target disappears fail their quest instead.
+CREATE TABLE quest_objectives (
+    id INTEGER PRIMARY KEY,
+    quest_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('reach', 'defeat', 'talk')),
+    target_id INTEGER NOT NULL,
+    required INTEGER NOT NULL DEFAULT 1 CHECK (required > 0),
+    progress INTEGER NOT NULL DEFAULT 0 CHECK (progress BETWEEN 0 AND required),
+    FOREIGN KEY (quest_id) REFERENCES quests(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_quest_objectives_quest ON quest_objectives(quest_id);
@@ -0,0 +1,20 @@
+-- Character sheets. Existing characters become level 1 Adventurers
+-- (progression::DEFAULT_CLASS) with that class's attributes, hit points and skills.
+ALTER TABLE players ADD COLUMN class TEXT NOT NULL DEFAULT 'Adventurer';
+ALTER TABLE players ADD COLUMN background TEXT;
+ALTER TABLE players ADD COLUMN might INTEGER NOT NULL DEFAULT 12 CHECK (might BETWEEN 1 AND 20);
+ALTER TABLE players ADD COLUMN agility INTEGER NOT NULL DEFAULT 12 CHECK (agility BETWEEN 1 AND 20);
+ALTER TABLE players ADD COLUMN wits INTEGER NOT NULL DEFAULT 12 CHECK (wits BETWEEN 1 AND 20);
+ALTER TABLE players ADD COLUMN presence INTEGER NOT NULL DEFAULT 12 CHECK (presence BETWEEN 1 AND 20);
+ALTER TABLE players ADD COLUMN hp INTEGER NOT NULL DEFAULT 10 CHECK (hp >= 0);
+ALTER TABLE players ADD COLUMN max_hp INTEGER NOT NULL DEFAULT 10 CHECK (max_hp > 0);
+ALTER TABLE players ADD COLUMN xp INTEGER NOT NULL DEFAULT 0 CHECK (xp >= 0);
+ALTER TABLE players ADD COLUMN level INTEGER NOT NULL DEFAULT 1 CHECK (level >= 1);
+
+CREATE TABLE player_skills (
+    player_id INTEGER NOT NULL,
+    skill TEXT NOT NULL,
+    PRIMARY KEY (player_id, skill),
+    FOREIGN KEY (player_id) REFERENCES players(id) ON DELETE CASCADE
+);
+INSERT INTO player_skills (player_id, skill) SELECT id, 'survival' FROM players;
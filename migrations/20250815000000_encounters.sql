@@ -0,0 +1,52 @@
+-- Turn-based fights between player characters and a faction's fighters or a single NPC.
+CREATE TABLE encounters (
+    id INTEGER PRIMARY KEY,
+    world_id INTEGER NOT NULL,
+    location_id INTEGER NOT NULL,
+    faction_id INTEGER,
+    npc_id INTEGER,
+    status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'won', 'lost', 'fled')),
+    round INTEGER NOT NULL DEFAULT 0 CHECK (round >= 0),
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    CHECK ((faction_id IS NULL) <> (npc_id IS NULL)),
+    FOREIGN KEY (world_id) REFERENCES world(id) ON DELETE CASCADE,
+    FOREIGN KEY (location_id) REFERENCES locations(id) ON DELETE CASCADE,
+    FOREIGN KEY (faction_id) REFERENCES factions(id) ON DELETE CASCADE,
+    FOREIGN KEY (npc_id) REFERENCES npcs(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_encounters_world ON encounters(world_id);
+
+-- Everyone taking part, player characters included: their hit points are copied back to
+-- `players` as the fight goes on. Foes with an `npc_id` are named NPCs, the rest are
+-- nameless fighters.
+CREATE TABLE combatants (
+    id INTEGER PRIMARY KEY,
+    encounter_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    side TEXT NOT NULL CHECK (side IN ('players', 'foes')),
+    player_id INTEGER,
+    npc_id INTEGER,
+    initiative INTEGER NOT NULL,
+    hp INTEGER NOT NULL CHECK (hp >= 0),
+    max_hp INTEGER NOT NULL CHECK (max_hp > 0),
+    attack INTEGER NOT NULL,
+    defense INTEGER NOT NULL,
+    damage_die INTEGER NOT NULL CHECK (damage_die > 0),
+    damage_bonus INTEGER NOT NULL DEFAULT 0,
+    defending BOOLEAN NOT NULL DEFAULT 0,
+    fled BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY (encounter_id) REFERENCES encounters(id) ON DELETE CASCADE,
+    FOREIGN KEY (player_id) REFERENCES players(id) ON DELETE CASCADE,
+    FOREIGN KEY (npc_id) REFERENCES npcs(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_combatants_encounter ON combatants(encounter_id);
+
+-- What happened each round, blow by blow, and the narrative provider's account of it.
+CREATE TABLE encounter_rounds (
+    encounter_id INTEGER NOT NULL,
+    round INTEGER NOT NULL,
+    log TEXT NOT NULL,
+    narration TEXT NOT NULL,
+    PRIMARY KEY (encounter_id, round),
+    FOREIGN KEY (encounter_id) REFERENCES encounters(id) ON DELETE CASCADE
+);
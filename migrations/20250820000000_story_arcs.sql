@@ -0,0 +1,4 @@
+-- Campaigns follow a story arc from data/story_arcs.json and count the acts it has run
+-- through. Existing worlds follow the default arc (arcs::DEFAULT_ARC), in its first act.
+ALTER TABLE world ADD COLUMN arc TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE world ADD COLUMN act INTEGER NOT NULL DEFAULT 1 CHECK (act >= 1);
@@ -0,0 +1,278 @@
+//! Story arcs from `data/story_arcs.json`: the phases a campaign moves through, how tension
+//! drifts while it is in each, and the conditions on the world that carry the story from one
+//! phase to the next. A resolution can lead back into the build-up of a new act, so an arc
+//! may run for as many acts as its conditions allow.
+//!
+//! ```json
+//! {"name": "Climax", "narrative": "...", "tension": [5, 15],
+//!  "entry": [{"path": "world.act", "gte": 2}],
+//!  "exits": [{"to": "Resolution", "when": [{"path": "world.tension", "gte": 100}]}]}
+//! ```
+
+use rand::Rng;
+use serde::Deserialize;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use std::collections::HashMap;
+use std::fs;
+use crate::state_changes::{Condition, StateChangeError, STORY_PHASES};
+
+/// The arc worlds follow when their seed names none, and the one worlds from before arcs follow.
+pub const DEFAULT_ARC: &str = "default";
+
+#[derive(Deserialize)]
+pub struct Phase {
+    /// One of [`STORY_PHASES`]; events are drawn for the phase by this name.
+    pub name: String,
+    pub narrative: String,
+    /// How far tension moves on each turn spent in the phase, e.g. `[5, 15]`, or `[-15, -5]`
+    /// for it to ease off.
+    pub tension: [i32; 2],
+    /// Must all hold for the story to move into this phase.
+    #[serde(default)]
+    pub entry: Vec<Condition>,
+    /// Ways on, tried in order; the first whose conditions hold and whose phase can be
+    /// entered is taken. A phase without any is where the story ends.
+    #[serde(default)]
+    pub exits: Vec<Exit>,
+}
+
+#[derive(Deserialize)]
+pub struct Exit {
+    pub to: String,
+    #[serde(default)]
+    pub when: Vec<Condition>,
+    /// Whether taking it starts a new act.
+    #[serde(default)]
+    pub new_act: bool,
+}
+
+#[derive(Deserialize)]
+pub struct StoryArc {
+    /// The story picks up from the first if it strays into a phase not listed.
+    pub phases: Vec<Phase>,
+}
+
+impl StoryArc {
+    pub fn phase(&self, name: &str) -> Option<&Phase> {
+        self.phases.iter().find(|p| p.name == name)
+    }
+}
+
+pub struct StoryArcs(HashMap<String, StoryArc>);
+
+impl StoryArcs {
+    pub fn load(path: &str) -> Result<StoryArcs, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let arcs: HashMap<String, StoryArc> = serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        for (name, arc) in &arcs {
+            if arc.phases.is_empty() {
+                return Err(format!("{}: arc '{}' has no phases", path, name));
+            }
+            for (i, phase) in arc.phases.iter().enumerate() {
+                if !STORY_PHASES.contains(&phase.name.as_str()) {
+                    return Err(format!("{}: arc '{}' has unknown phase '{}', expected one of {:?}", path, name, phase.name, STORY_PHASES));
+                }
+                if arc.phases[..i].iter().any(|other| other.name == phase.name) {
+                    return Err(format!("{}: arc '{}' lists phase {} twice", path, name, phase.name));
+                }
+                let [low, high] = phase.tension;
+                if low > high || !(-100..=100).contains(&low) || !(-100..=100).contains(&high) {
+                    return Err(format!("{}: arc '{}' phase {} needs tension [low, high] from -100 to 100", path, name, phase.name));
+                }
+                if let Some(exit) = phase.exits.iter().find(|e| arc.phase(&e.to).is_none()) {
+                    return Err(format!("{}: arc '{}' phase {} leads to unknown phase '{}'", path, name, phase.name, exit.to));
+                }
+            }
+        }
+        if !arcs.contains_key(DEFAULT_ARC) {
+            return Err(format!("{}: the default arc '{}' is missing", path, DEFAULT_ARC));
+        }
+        Ok(StoryArcs(arcs))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StoryArc> {
+        self.0.get(name)
+    }
+
+    /// The arc named `name`, or the default one for worlds whose arc is gone from the data.
+    fn arc(&self, name: &str) -> &StoryArc {
+        self.0.get(name).unwrap_or_else(|| &self.0[DEFAULT_ARC])
+    }
+
+    /// How the story reads in `phase` of the world's arc.
+    pub fn narrative(&self, arc: &str, phase: &str) -> &str {
+        self.arc(arc).phase(phase).map_or("The story unfolds...", |p| p.narrative.as_str())
+    }
+
+    /// Moves the world's tension by its phase's drift, then takes the first exit that is
+    /// open, describing the turn the story took for the caller to log.
+    pub async fn advance(&self, pool: &SqlitePool, world_id: i32) -> Result<Option<String>, StateChangeError> {
+        let mut tx = pool.begin().await?;
+        let row = sqlx::query("SELECT tension, story_phase, arc, act FROM world WHERE id = ?").bind(world_id).fetch_one(&mut *tx).await?;
+        let (tension, current, arc, act): (i32, String, String, i32) = (row.get(0), row.get(1), row.get(2), row.get(3));
+        let arc = self.arc(&arc);
+        let Some(phase) = arc.phase(&current) else {
+            // Moved by a choice into a phase this arc does not have; the story picks up from the start
+            let start = &arc.phases[0];
+            sqlx::query("UPDATE world SET story_phase = ? WHERE id = ?").bind(&start.name).bind(world_id).execute(&mut *tx).await?;
+            tx.commit().await?;
+            return Ok(Some(format!("The story returns to {}", start.name)));
+        };
+        let [low, high] = phase.tension;
+        let tension = (tension + rand::thread_rng().gen_range(low..=high)).clamp(0, 100);
+        sqlx::query("UPDATE world SET tension = ? WHERE id = ?").bind(tension).bind(world_id).execute(&mut *tx).await?;
+
+        let mut taken = None;
+        for exit in &phase.exits {
+            let next = arc.phase(&exit.to).expect("exits are checked on load");
+            if all_hold(&exit.when, &mut tx, world_id).await? && all_hold(&next.entry, &mut tx, world_id).await? {
+                taken = Some((exit, next));
+                break;
+            }
+        }
+        let line = match taken {
+            Some((exit, next)) => {
+                let act = act + i32::from(exit.new_act);
+                sqlx::query("UPDATE world SET story_phase = ?, act = ? WHERE id = ?").bind(&next.name).bind(act).bind(world_id).execute(&mut *tx).await?;
+                Some(if exit.new_act {
+                    format!("Act {} begins: the story returns to {}", act, next.name)
+                } else {
+                    format!("The story moves from {} to {}", phase.name, next.name)
+                })
+            }
+            None => None,
+        };
+        tx.commit().await?;
+        Ok(line)
+    }
+}
+
+/// Whether every one of `conditions` holds. One about something the world does not have
+/// (an arc naming faction 7 in a world without it) does not.
+async fn all_hold(conditions: &[Condition], tx: &mut Transaction<'_, Sqlite>, world_id: i32) -> Result<bool, StateChangeError> {
+    for condition in conditions {
+        match condition.holds(tx, world_id).await {
+            Ok(true) => {}
+            Ok(false) | Err(StateChangeError::UnknownEntity(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use serde_json::{json, Value as Json};
+
+    fn load(name: &str, arcs: Json) -> Result<StoryArcs, String> {
+        let path = std::env::temp_dir().join(format!("sci_fi_gm-{}-arcs-{}.json", std::process::id(), name));
+        fs::write(&path, arcs.to_string()).unwrap();
+        let loaded = StoryArcs::load(path.to_str().unwrap());
+        fs::remove_file(&path).ok();
+        loaded
+    }
+
+    fn phase(name: &str, exits: Json) -> Json {
+        json!({"name": name, "narrative": "...", "tension": [5, 15], "exits": exits})
+    }
+
+    fn rejected(name: &str, arcs: Json) -> String {
+        match load(name, arcs) {
+            Err(e) => e,
+            Ok(_) => panic!("{} should not load", name),
+        }
+    }
+
+    /// A world from `seed` with its story at `phase`, `tension` and `act`.
+    async fn world_at(seed: &str, phase: &str, tension: i32, act: i32) -> (SqlitePool, i32) {
+        let pool = db::memory_pool().await;
+        let world = db::seed_test_world(&pool, seed).await;
+        sqlx::query("UPDATE world SET story_phase = ?, tension = ?, act = ? WHERE id = ?")
+            .bind(phase).bind(tension).bind(act).bind(world.id).execute(&pool).await.unwrap();
+        (pool, world.id)
+    }
+
+    async fn story(pool: &SqlitePool, world_id: i32) -> (String, i32, i32) {
+        sqlx::query_as("SELECT story_phase, tension, act FROM world WHERE id = ?").bind(world_id).fetch_one(pool).await.unwrap()
+    }
+
+    fn arcs() -> StoryArcs {
+        StoryArcs::load("data/story_arcs.json").unwrap()
+    }
+
+    #[test]
+    fn loads_the_shipped_arcs() {
+        let arcs = arcs();
+        assert!(arcs.get(DEFAULT_ARC).is_some() && arcs.get("frontier").is_some());
+        assert!(arcs.narrative("frontier", "Climax").contains("reckoning"));
+        assert_eq!(arcs.narrative("lost", "Build-Up"), arcs.narrative(DEFAULT_ARC, "Build-Up"));
+    }
+
+    #[test]
+    fn rejects_bad_arcs() {
+        let good = || phase("Build-Up", json!([]));
+        assert!(load("good", json!({"default": {"phases": [good()]}})).is_ok());
+        assert!(rejected("empty", json!({"default": {"phases": []}})).contains("has no phases"));
+        assert!(rejected("unknown", json!({"default": {"phases": [phase("Epilogue", json!([]))]}})).contains("unknown phase 'Epilogue'"));
+        assert!(rejected("twice", json!({"default": {"phases": [good(), good()]}})).contains("twice"));
+        let mut backwards = good();
+        backwards["tension"] = json!([15, 5]);
+        assert!(rejected("backwards", json!({"default": {"phases": [backwards]}})).contains("tension"));
+        let mut wild = good();
+        wild["tension"] = json!([0, 150]);
+        assert!(rejected("wild", json!({"default": {"phases": [wild]}})).contains("tension"));
+        let nowhere = phase("Build-Up", json!([{"to": "Climax"}]));
+        assert!(rejected("nowhere", json!({"default": {"phases": [nowhere]}})).contains("unknown phase 'Climax'"));
+        assert!(rejected("nameless", json!({"frontier": {"phases": [good()]}})).contains("default arc"));
+        let unfair = phase("Build-Up", json!([{"to": "Build-Up", "when": [{"path": "world.story_phase", "gte": 3}]}]));
+        assert!(rejected("unfair", json!({"default": {"phases": [unfair]}})).contains("Failed to parse"));
+    }
+
+    #[tokio::test]
+    async fn builds_tension_until_an_exit_opens() {
+        let (pool, world_id) = world_at("default", "Build-Up", 0, 1).await;
+        assert_eq!(arcs().advance(&pool, world_id).await.unwrap(), None);
+        let (phase, tension, act) = story(&pool, world_id).await;
+        assert_eq!((phase.as_str(), act), ("Build-Up", 1));
+        assert!((5..=15).contains(&tension), "tension {}", tension);
+
+        let (pool, world_id) = world_at("default", "Build-Up", 95, 1).await;
+        assert_eq!(arcs().advance(&pool, world_id).await.unwrap().as_deref(), Some("The story moves from Build-Up to Conflict"));
+        assert_eq!(story(&pool, world_id).await, ("Conflict".to_string(), 100, 1));
+    }
+
+    #[tokio::test]
+    async fn skips_phases_whose_entry_does_not_hold() {
+        // The frontier's climax only comes in its second act, so the first goes straight to the end
+        let (pool, world_id) = world_at("frontier", "Conflict", 90, 1).await;
+        assert_eq!(arcs().advance(&pool, world_id).await.unwrap().as_deref(), Some("The story moves from Conflict to Resolution"));
+
+        let (pool, world_id) = world_at("frontier", "Conflict", 90, 2).await;
+        assert_eq!(arcs().advance(&pool, world_id).await.unwrap().as_deref(), Some("The story moves from Conflict to Climax"));
+    }
+
+    #[tokio::test]
+    async fn eases_tension_in_resolution() {
+        let (pool, world_id) = world_at("default", "Resolution", 80, 1).await;
+        assert_eq!(arcs().advance(&pool, world_id).await.unwrap(), None);
+        let (phase, tension, _) = story(&pool, world_id).await;
+        assert_eq!(phase, "Resolution");
+        assert!((60..=70).contains(&tension), "tension {}", tension);
+    }
+
+    #[tokio::test]
+    async fn starts_a_new_act_once_things_settle() {
+        let (pool, world_id) = world_at("default", "Resolution", 25, 1).await;
+        assert_eq!(arcs().advance(&pool, world_id).await.unwrap().as_deref(), Some("Act 2 begins: the story returns to Build-Up"));
+        let (phase, tension, act) = story(&pool, world_id).await;
+        assert_eq!((phase.as_str(), act), ("Build-Up", 2));
+        assert!((5..=15).contains(&tension), "tension {}", tension);
+
+        // The frontier only runs to a third act
+        let (pool, world_id) = world_at("frontier", "Resolution", 25, 3).await;
+        assert_eq!(arcs().advance(&pool, world_id).await.unwrap(), None);
+        assert_eq!(story(&pool, world_id).await.2, 3);
+    }
+}
@@ -0,0 +1,177 @@
+//! Branch choices requested from the narrative provider as JSON:
+//!
+//! ```json
+//! [{"description": "Hold the gate at Willowbrook", "risk": "high",
+//!   "consequences": {"locations.2.safety": 10, "factions.2.power": -5},
+//!   "affected": ["Willowbrook", "Bandits"]}]
+//! ```
+//!
+//! Consequences use the state change language and become the choice's `state_changes`,
+//! so they must name entities that exist in the world the choices are for, and items
+//! from the catalogue.
+
+use serde_json::Value as Json;
+use crate::db::WorldState;
+use crate::items::Catalogue;
+use crate::models::Risk;
+use crate::state_changes::{Entity, StateChanges};
+
+pub const MIN_CHOICES: usize = 2;
+pub const MAX_CHOICES: usize = 5;
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// A validated choice that has not been stored yet.
+#[derive(Clone, Debug)]
+pub struct ChoiceDraft {
+    pub description: String,
+    pub state_changes: StateChanges,
+    pub risk: Risk,
+    pub affected: Vec<String>,
+}
+
+/// Instructions appended to the branch prompt, including the ids the model may reference.
+pub fn reply_format(state: &WorldState, catalogue: &Catalogue) -> String {
+    let ids = |items: Vec<(i32, &str)>| items.iter().map(|(id, name)| format!("{}={}", id, name)).collect::<Vec<_>>().join(", ");
+    format!(
+        "Reply with only a JSON array of {min} to {max} objects and no other text. Each object has \
+         \"description\" (the decision, under 20 words), \"risk\" (\"low\", \"medium\" or \"high\"), \
+         \"consequences\" (an object mapping a state path to a number to add or a string to set; paths are \
+         world.tension, players.<id>.reputation, players.<id>.coins, locations.<id>.prosperity, locations.<id>.safety, \
+         factions.<id>.power, factions.<id>.relation, npcs.<id>.status, and \
+         locations.<location id>.influence.<faction id> for a faction's hold on a location, and \
+         players.<id>.items.<item name> to grant items or, negative, use them up) and \"affected\" (names of the \
+         locations, factions, NPCs or players involved).\n\
+         Player ids: {players}. Location ids: {locations}. Faction ids: {factions}. NPC ids: {npcs}. Item names: {items}.",
+        min = MIN_CHOICES,
+        max = MAX_CHOICES,
+        players = ids(state.players.iter().map(|p| (p.id, p.name.as_str())).collect()),
+        locations = ids(state.locations.iter().map(|l| (l.id, l.name.as_str())).collect()),
+        factions = ids(state.factions.iter().map(|f| (f.id, f.name.as_str())).collect()),
+        npcs = ids(state.npcs.iter().map(|n| (n.id, n.name.as_str())).collect()),
+        items = catalogue.names().collect::<Vec<_>>().join(", "),
+    )
+}
+
+/// The JSON inside `reply`, tolerating the markdown fences and preambles models like to add.
+pub fn extract_json(reply: &str) -> Option<&str> {
+    let start = reply.find(['[', '{'])?;
+    let end = reply.rfind([']', '}'])?;
+    (start < end).then(|| &reply[start..=end])
+}
+
+fn entity_exists(state: &WorldState, catalogue: &Catalogue, entity: &Entity) -> bool {
+    let id = |id: i64| i32::try_from(id).unwrap_or(-1);
+    match *entity {
+        Entity::World => true,
+        Entity::Player(p) => state.players.iter().any(|x| x.id == id(p)),
+        Entity::Location(l) => state.locations.iter().any(|x| x.id == id(l)),
+        Entity::Faction(f) => state.factions.iter().any(|x| x.id == id(f)),
+        Entity::Npc(n) => state.npcs.iter().any(|x| x.id == id(n)),
+        Entity::Influence(l, f) => state.locations.iter().any(|x| x.id == id(l)) && state.factions.iter().any(|x| x.id == id(f)),
+        Entity::Item(p, ref item) => state.players.iter().any(|x| x.id == id(p)) && catalogue.get(item).is_some(),
+    }
+}
+
+fn parse_choice(raw: &Json, state: &WorldState, catalogue: &Catalogue) -> Result<ChoiceDraft, String> {
+    let description = raw["description"].as_str().map(str::trim).unwrap_or("");
+    if description.is_empty() || description.chars().count() > MAX_DESCRIPTION_CHARS {
+        return Err(format!("choice {} needs a description of 1 to {} characters", raw, MAX_DESCRIPTION_CHARS));
+    }
+    let risk: Risk = serde_json::from_value(raw["risk"].clone()).map_err(|_| format!("'{}': risk must be low, medium or high", description))?;
+    let state_changes = StateChanges::parse(&raw["consequences"]).map_err(|e| format!("'{}': {}", description, e))?;
+    if let Some(path) = state_changes.paths().find(|p| !entity_exists(state, catalogue, &p.entity)) {
+        return Err(format!("'{}': {} does not exist in this world", description, path));
+    }
+    let affected = match &raw["affected"] {
+        Json::Null => Vec::new(),
+        Json::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string).ok_or_else(|| format!("'{}': affected must list names", description)))
+            .collect::<Result<_, _>>()?,
+        other => return Err(format!("'{}': affected must be a list, got {}", description, other)),
+    };
+    Ok(ChoiceDraft { description: description.to_string(), state_changes, risk, affected })
+}
+
+/// Parses and validates a provider reply against `state`.
+pub fn parse_reply(reply: &str, state: &WorldState, catalogue: &Catalogue) -> Result<Vec<ChoiceDraft>, String> {
+    let json: Json = serde_json::from_str(extract_json(reply).ok_or("reply contains no JSON")?)
+        .map_err(|e| format!("reply is not valid JSON: {}", e))?;
+    // Some models wrap the list in an object despite being asked not to.
+    let items = match &json {
+        Json::Array(items) => items,
+        Json::Object(obj) => obj.get("choices").and_then(Json::as_array).ok_or("expected a JSON array of choices")?,
+        _ => return Err("expected a JSON array of choices".to_string()),
+    };
+    if !(MIN_CHOICES..=MAX_CHOICES).contains(&items.len()) {
+        return Err(format!("expected {} to {} choices, got {}", MIN_CHOICES, MAX_CHOICES, items.len()));
+    }
+    items.iter().map(|item| parse_choice(item, state, catalogue)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    /// The default campaign: Capital (1) and Willowbrook (2), the Royal Guard (1) and the Bandits (2).
+    async fn world() -> (WorldState, Catalogue) {
+        let pool = db::memory_pool().await;
+        let world = db::seed_test_world(&pool, "default").await;
+        (db::get_world_state(&pool, world.id).await.unwrap(), Catalogue::load("data/items.json").unwrap())
+    }
+
+    fn choice(description: &str, risk: &str) -> Json {
+        serde_json::json!({"description": description, "risk": risk, "consequences": {"locations.2.safety": 10}, "affected": ["Willowbrook"]})
+    }
+
+    fn reply(choices: &[Json]) -> String {
+        Json::Array(choices.to_vec()).to_string()
+    }
+
+    #[test]
+    fn finds_the_json_among_other_text() {
+        assert_eq!(extract_json("Here you go:\n[1, 2]"), Some("[1, 2]"));
+        assert_eq!(extract_json("```json\n{\"choices\": []}\n```"), Some("{\"choices\": []}"));
+        assert_eq!(extract_json("[1, 2]\nHope that helps!"), Some("[1, 2]"));
+        assert_eq!(extract_json("No choices today."), None);
+        assert_eq!(extract_json("] backwards ["), None);
+    }
+
+    #[tokio::test]
+    async fn parses_replies_wrapped_in_prose_and_fences() {
+        let (state, catalogue) = world().await;
+        let choices = reply(&[choice("Hold the gate", "high"), choice("Slip away", "low")]);
+        for wrapped in [
+            choices.clone(),
+            format!("Sure! Here are the choices:\n{}", choices),
+            format!("```json\n{}\n```", choices),
+            format!("{}\n\nLet me know if you want more.", choices),
+            format!("{{\"choices\": {}}}", choices),
+        ] {
+            let parsed = parse_reply(&wrapped, &state, &catalogue).unwrap_or_else(|e| panic!("{}: {}", wrapped, e));
+            assert_eq!(parsed.len(), 2);
+            assert_eq!((parsed[0].description.as_str(), parsed[0].risk), ("Hold the gate", Risk::High));
+            assert_eq!(parsed[1].affected, ["Willowbrook"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_replies_that_break_the_format() {
+        let (state, catalogue) = world().await;
+        let parse = |reply: &str| parse_reply(reply, &state, &catalogue).err().unwrap_or_else(|| panic!("{} should be rejected", reply));
+        assert!(parse("I cannot help with that.").contains("no JSON"));
+        assert!(parse("[{\"description\": \"Hold\",]").contains("not valid JSON"));
+        assert!(parse(&reply(&[choice("Hold the gate", "extreme"), choice("Slip away", "low")])).contains("risk must be"));
+        assert!(parse(&reply(&[choice("Hold the gate", "high")])).contains("expected 2 to 5 choices, got 1"));
+        let many: Vec<Json> = (0..=MAX_CHOICES).map(|i| choice(&format!("Choice {}", i), "low")).collect();
+        assert!(parse(&reply(&many)).contains("got 6"));
+        let mut nowhere = choice("Rebuild the mill", "low");
+        nowhere["consequences"] = serde_json::json!({"locations.9.prosperity": 10});
+        assert!(parse(&reply(&[nowhere, choice("Slip away", "low")])).contains("does not exist"));
+        let mut magic = choice("Find a relic", "low");
+        magic["consequences"] = serde_json::json!({"players.1.items.Holy Grail": 1});
+        assert!(parse(&reply(&[magic, choice("Slip away", "low")])).contains("does not exist"));
+        assert!(parse(&reply(&[choice("", "low"), choice("Slip away", "low")])).contains("description"));
+    }
+}
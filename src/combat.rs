@@ -0,0 +1,504 @@
+//! Turn-based fights. An encounter pits player characters against a faction's fighters,
+//! joined by its members who are there, or against a single NPC. Everyone acts once a
+//! round in initiative order; fallen fighters cost their faction power, fallen NPCs die,
+//! and the narrative provider tells each round from its blow-by-blow log.
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::cmp::Reverse;
+use crate::db::{self, Combatant, Encounter, Npc, Player, Round, WorldState};
+use crate::dice::{self, Modifier, Outcome};
+use crate::game_master::{add_reputation, GameError};
+use crate::items::Catalogue;
+use crate::models::{EncounterStatus, ItemKind, Side};
+use crate::progression;
+use crate::quests::{self, Deed};
+
+/// Most nameless fighters a faction puts up, however strong it is.
+pub const MAX_FIGHTERS: i32 = 4;
+/// Power a faction loses for every one of its fighters that falls.
+pub const CASUALTY_POWER: i32 = 5;
+/// Experience each player character in the fight gets for winning it.
+pub const VICTORY_XP: i32 = 50;
+
+/// Who the player characters take on.
+#[derive(Clone, Copy, Debug)]
+pub enum Foe {
+    Faction(i32),
+    Npc(i32),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Tactic {
+    Attack,
+    /// Harder to hit for the rest of the round.
+    Defend,
+    Flee,
+}
+
+/// What one player character does this round. `target` is a foe's combatant id and only
+/// matters for attacks; without one they go for the most wounded foe. Player characters
+/// without orders attack.
+#[derive(Serialize, Deserialize)]
+pub struct Order {
+    pub player_id: i32,
+    pub action: Tactic,
+    #[serde(default)]
+    pub target: Option<i32>,
+}
+
+/// A round as it was fought, before it is stored.
+pub struct Fought {
+    pub log: Vec<String>,
+    /// Combatant ids of the foes who fell this round.
+    pub fallen: Vec<i32>,
+}
+
+/// What `act` answers with: the round and where everyone stands after it.
+#[derive(Serialize)]
+pub struct RoundReport {
+    pub encounter_id: i32,
+    pub status: EncounterStatus,
+    pub round: Round,
+    pub combatants: Vec<Combatant>,
+    /// What the round changed in the world beyond the fight.
+    pub aftermath: Vec<String>,
+}
+
+fn d20(rng: &mut impl Rng) -> i32 {
+    rng.gen_range(1..=20)
+}
+
+/// A player character as their sheet and the gear they carry make them.
+fn player_combatant(player: &Player, items: &Catalogue, rng: &mut impl Rng) -> Combatant {
+    let carries = |kind: ItemKind| player.inventory.iter().any(|i| items.get(&i.item).is_some_and(|item| item.kind == kind));
+    let sheet = &player.sheet;
+    let might = progression::modifier(sheet.might);
+    let agility = progression::modifier(sheet.agility);
+    let skill = if sheet.skills.iter().any(|s| s == "combat") { progression::skill_bonus(sheet.level) } else { 0 };
+    Combatant {
+        id: 0,
+        name: player.name.clone(),
+        side: Side::Players,
+        player_id: Some(player.id),
+        npc_id: None,
+        initiative: d20(rng) + agility,
+        hp: sheet.hp,
+        max_hp: sheet.max_hp,
+        attack: might + skill,
+        defense: 10 + agility + if carries(ItemKind::Armor) { 2 } else { 0 },
+        damage_die: if carries(ItemKind::Weapon) { 8 } else { 4 },
+        damage_bonus: might.max(0),
+        defending: false,
+        fled: false,
+    }
+}
+
+/// One of a faction's rank and file, tougher the stronger the faction.
+fn fighter(name: String, power: i32, rng: &mut impl Rng) -> Combatant {
+    Combatant {
+        id: 0,
+        name,
+        side: Side::Foes,
+        player_id: None,
+        npc_id: None,
+        initiative: d20(rng) + power / 25,
+        hp: 4 + power / 10,
+        max_hp: 4 + power / 10,
+        attack: power / 25,
+        defense: 10 + power / 25,
+        damage_die: 6,
+        damage_bonus: 0,
+        defending: false,
+        fled: false,
+    }
+}
+
+/// Named NPCs are a match for any two of the rank and file.
+fn npc_combatant(npc: &Npc, rng: &mut impl Rng) -> Combatant {
+    Combatant {
+        id: 0,
+        name: npc.name.clone(),
+        side: Side::Foes,
+        player_id: None,
+        npc_id: Some(npc.id),
+        initiative: d20(rng) + 2,
+        hp: 16,
+        max_hp: 16,
+        attack: 3,
+        defense: 12,
+        damage_die: 8,
+        damage_bonus: 1,
+        defending: false,
+        fled: false,
+    }
+}
+
+/// Lines `cast` up against `foe` where they stand, in initiative order, returning that
+/// location too, or why they cannot fight.
+pub fn muster(state: &WorldState, name: &str, cast: &[&Player], foe: Foe, items: &Catalogue, rng: &mut impl Rng) -> Result<(i32, Vec<Combatant>), String> {
+    let here = cast[0].location_id;
+    if cast.iter().any(|p| p.location_id != here) {
+        return Err(format!("{} must be in one place to fight together", name));
+    }
+    if let Some(hurt) = cast.iter().find(|p| p.sheet.hp == 0) {
+        return Err(format!("{} is too badly hurt to fight; rest first", hurt.name));
+    }
+    let mut combatants: Vec<Combatant> = cast.iter().map(|p| player_combatant(p, items, rng)).collect();
+    match foe {
+        Foe::Faction(id) => {
+            let faction = state.factions.iter().find(|f| f.id == id)
+                .ok_or_else(|| format!("No faction with id {} in world {}", id, state.world.id))?;
+            if faction.power == 0 {
+                return Err(format!("{} has no one left to fight", faction.name));
+            }
+            for i in 1..=(1 + faction.power / 30).min(MAX_FIGHTERS) {
+                combatants.push(fighter(format!("{} fighter {}", faction.name, i), faction.power, rng));
+            }
+            // Its own people stand with them when they are there
+            for npc in state.npcs.iter().filter(|n| n.faction_id == Some(id) && n.location_id == here && n.status == "Alive") {
+                combatants.push(npc_combatant(npc, rng));
+            }
+        }
+        Foe::Npc(id) => {
+            let npc = state.npcs.iter().find(|n| n.id == id)
+                .ok_or_else(|| format!("No NPC with id {} in world {}", id, state.world.id))?;
+            if npc.status != "Alive" {
+                return Err(format!("{} is {}", npc.name, npc.status.to_lowercase()));
+            }
+            if npc.location_id != here {
+                return Err(format!("{} is not where {} is", npc.name, name));
+            }
+            combatants.push(npc_combatant(npc, rng));
+        }
+    }
+    // Ties go to the player characters, who come first
+    combatants.sort_by_key(|c| Reverse(c.initiative));
+    Ok((here, combatants))
+}
+
+fn standing(c: &Combatant) -> bool {
+    c.hp > 0 && !c.fled
+}
+
+fn still_fighting(combatants: &[Combatant], side: Side) -> impl Iterator<Item = usize> + '_ {
+    combatants.iter().enumerate().filter(move |(_, c)| c.side == side && standing(c)).map(|(i, _)| i)
+}
+
+/// How a fight stands when it is over, if it is.
+fn verdict(combatants: &[Combatant]) -> EncounterStatus {
+    let players = still_fighting(combatants, Side::Players).count();
+    let foes = still_fighting(combatants, Side::Foes).count();
+    match (players, foes) {
+        (_, 0) => EncounterStatus::Won,
+        (0, _) if combatants.iter().any(|c| c.side == Side::Players && c.fled) => EncounterStatus::Fled,
+        (0, _) => EncounterStatus::Lost,
+        _ => EncounterStatus::Active,
+    }
+}
+
+/// Combatant `attacker` swings at `target`, returning what happened.
+fn attack(combatants: &mut [Combatant], attacker: usize, target: usize, rng: &mut impl Rng) -> String {
+    let (a, t) = (&combatants[attacker], &combatants[target]);
+    let defense = t.defense + if t.defending { 2 } else { 0 };
+    let roll = dice::check(defense, vec![Modifier::new("attack", a.attack)], rng);
+    let line = if roll.outcome.succeeded() {
+        let mut damage = (rng.gen_range(1..=a.damage_die) + a.damage_bonus).max(1);
+        if roll.outcome == Outcome::CriticalSuccess {
+            damage *= 2;
+        }
+        let hp = (t.hp - damage).max(0);
+        let line = if hp == 0 { format!("{} struck down {}", a.name, t.name) } else { format!("{} hit {} for {}", a.name, t.name, damage) };
+        combatants[target].hp = hp;
+        line
+    } else {
+        format!("{} missed {}", a.name, t.name)
+    };
+    format!("{} [{}]", line, roll)
+}
+
+/// Fights the next round of `encounter`, updating it in place: everyone standing acts in
+/// initiative order, player characters as `orders` say and foes at whichever player
+/// character is nearest to hand. Errors name orders that cannot be followed.
+pub fn fight_round(encounter: &mut Encounter, orders: &[Order], rng: &mut impl Rng) -> Result<Fought, String> {
+    let combatants = &mut encounter.combatants;
+    for (i, order) in orders.iter().enumerate() {
+        let fighter = combatants.iter().find(|c| c.player_id == Some(order.player_id))
+            .ok_or_else(|| format!("Player {} is not in this fight", order.player_id))?;
+        if !standing(fighter) {
+            return Err(format!("{} is out of the fight", fighter.name));
+        }
+        if orders[..i].iter().any(|o| o.player_id == order.player_id) {
+            return Err(format!("{} can only be given one order a round", fighter.name));
+        }
+        if let Some(target) = order.target.filter(|t| !combatants.iter().any(|c| c.id == *t && c.side == Side::Foes && standing(c))) {
+            return Err(format!("No foe {} is still standing in this fight", target));
+        }
+    }
+    let order_for = |c: &Combatant| orders.iter().find(|o| Some(o.player_id) == c.player_id);
+
+    for c in combatants.iter_mut() {
+        c.defending = c.side == Side::Players && order_for(c).is_some_and(|o| o.action == Tactic::Defend);
+    }
+    let mut log = Vec::new();
+    let mut fallen = Vec::new();
+    for i in 0..combatants.len() {
+        if !standing(&combatants[i]) || verdict(combatants) != EncounterStatus::Active {
+            continue;
+        }
+        let (action, target) = match combatants[i].side {
+            Side::Players => order_for(&combatants[i]).map_or((Tactic::Attack, None), |o| (o.action, o.target)),
+            Side::Foes => (Tactic::Attack, None),
+        };
+        match action {
+            Tactic::Defend => log.push(format!("{} braced for blows", combatants[i].name)),
+            Tactic::Flee => {
+                // The more foes still up, the harder it is to get clear
+                let foes = still_fighting(combatants, Side::Foes).count() as i32;
+                let roll = dice::check(10 + 2 * foes, vec![Modifier::new("defense", combatants[i].defense - 10)], rng);
+                let c = &mut combatants[i];
+                c.fled = roll.outcome.succeeded();
+                let line = if c.fled { format!("{} got away", c.name) } else { format!("{} tried to flee but was cut off", c.name) };
+                log.push(format!("{} [{}]", line, roll));
+            }
+            Tactic::Attack => {
+                let target = match combatants[i].side {
+                    Side::Players => target
+                        .and_then(|t| combatants.iter().position(|c| c.id == t).filter(|t| standing(&combatants[*t])))
+                        .or_else(|| still_fighting(combatants, Side::Foes).min_by_key(|t| combatants[*t].hp)),
+                    Side::Foes => still_fighting(combatants, Side::Players).collect::<Vec<_>>().choose(rng).copied(),
+                };
+                let Some(target) = target else { continue };
+                log.push(attack(combatants, i, target, rng));
+                if combatants[target].hp == 0 && combatants[target].side == Side::Foes {
+                    fallen.push(combatants[target].id);
+                }
+            }
+        }
+    }
+    encounter.round += 1;
+    encounter.status = verdict(&encounter.combatants);
+    Ok(Fought { log, fallen })
+}
+
+/// The faction or NPC the encounter is against.
+pub fn foe_name(state: &WorldState, encounter: &Encounter) -> String {
+    let faction = encounter.faction_id.and_then(|id| state.factions.iter().find(|f| f.id == id)).map(|f| f.name.clone());
+    let npc = encounter.npc_id.and_then(|id| state.npcs.iter().find(|n| n.id == id)).map(|n| n.name.clone());
+    faction.or(npc).unwrap_or_else(|| "their foes".to_string())
+}
+
+fn players_in<'a>(state: &'a WorldState, encounter: &Encounter) -> Vec<&'a Player> {
+    state.players.iter().filter(|p| encounter.combatants.iter().any(|c| c.player_id == Some(p.id))).collect()
+}
+
+fn location_name(state: &WorldState, id: i32) -> &str {
+    state.locations.iter().find(|l| l.id == id).map_or("somewhere unknown", |l| l.name.as_str())
+}
+
+/// Asks the narrative provider to tell a fought round from its blow-by-blow `log`.
+pub fn narration_prompt(state: &WorldState, encounter: &Encounter, log: &[String]) -> String {
+    let players: Vec<&str> = players_in(state, encounter).iter().map(|p| p.name.as_str()).collect();
+    format!(
+        "Narrate round {} of a fight at {} between {} and {} in two or three vivid sentences. \
+         Keep to what happened, blow by blow:\n{}",
+        encounter.round,
+        location_name(state, encounter.location_id),
+        players.join(" and "),
+        foe_name(state, encounter),
+        log.iter().map(|line| format!("- {}", line)).collect::<Vec<_>>().join("\n"),
+    )
+}
+
+/// The round told plainly, for when the narrative provider cannot tell it.
+pub fn plain_account(log: &[String]) -> String {
+    log.iter()
+        .map(|line| line.split(" [").next().unwrap_or(line))
+        .map(|line| format!("{}.", line))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Carries a fought round over into the world: fallen fighters cost their faction power,
+/// fallen NPCs die, and a fight that is over is won or lost for good. Logs and returns
+/// what came of it.
+pub async fn aftermath(pool: &SqlitePool, state: &WorldState, encounter: &Encounter, fallen: &[i32]) -> Result<Vec<String>, GameError> {
+    let world_id = state.world.id;
+    let place = location_name(state, encounter.location_id);
+    let mut lines = Vec::new();
+    let fallen: Vec<&Combatant> = encounter.combatants.iter().filter(|c| fallen.contains(&c.id)).collect();
+    for npc_id in fallen.iter().filter_map(|c| c.npc_id) {
+        let result = sqlx::query("UPDATE npcs SET status = 'Dead' WHERE id = ? AND status = 'Alive'").bind(npc_id).execute(pool).await?;
+        if let (1, Some(npc)) = (result.rows_affected(), state.npcs.iter().find(|n| n.id == npc_id)) {
+            lines.push(format!("{} was killed in the fighting at {}", npc.name, place));
+        }
+    }
+    let casualties = fallen.iter().filter(|c| c.npc_id.is_none()).count() as i32;
+    if let (Some(faction_id), 1..) = (encounter.faction_id, casualties) {
+        sqlx::query("UPDATE factions SET power = MAX(0, power - ?) WHERE id = ?").bind(casualties * CASUALTY_POWER).bind(faction_id).execute(pool).await?;
+    }
+
+    let players = players_in(state, encounter);
+    let names = players.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(" and ");
+    let foe = foe_name(state, encounter);
+    match encounter.status {
+        EncounterStatus::Active => {}
+        EncounterStatus::Won => {
+            if let Some(faction_id) = encounter.faction_id {
+                db::add_influence(pool, encounter.location_id, faction_id, -10).await?;
+                quests::record(pool, state, &players, Deed::Fought(faction_id)).await?;
+            }
+            add_reputation(pool, &players, 3).await?;
+            lines.push(format!("{} defeated {} at {}", names, foe, place));
+            lines.extend(progression::award(pool, world_id, &players, VICTORY_XP).await?);
+        }
+        EncounterStatus::Lost => {
+            // Word of a rout spreads, and the victors grow bolder
+            if let Some(faction_id) = encounter.faction_id {
+                sqlx::query("UPDATE factions SET power = MIN(100, power + 5) WHERE id = ?").bind(faction_id).execute(pool).await?;
+            }
+            add_reputation(pool, &players, -3).await?;
+            lines.push(format!("{} lost the fight against {} at {}", names, foe, place));
+        }
+        EncounterStatus::Fled => lines.push(format!("{} fled from {} at {}", names, foe, place)),
+    }
+    for line in &lines {
+        db::log_event(pool, world_id, line, "Player").await?;
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn combatant(id: i32, side: Side, hp: i32, attack: i32, defense: i32) -> Combatant {
+        Combatant {
+            id,
+            name: format!("Fighter {}", id),
+            side,
+            player_id: (side == Side::Players).then_some(100 + id),
+            npc_id: None,
+            initiative: 0,
+            hp,
+            max_hp: hp,
+            attack,
+            defense,
+            damage_die: 6,
+            damage_bonus: 0,
+            defending: false,
+            fled: false,
+        }
+    }
+
+    fn encounter(combatants: Vec<Combatant>) -> Encounter {
+        Encounter {
+            id: 1,
+            location_id: 1,
+            faction_id: Some(1),
+            npc_id: None,
+            status: EncounterStatus::Active,
+            round: 0,
+            created_at: String::new(),
+            combatants,
+            rounds: Vec::new(),
+        }
+    }
+
+    fn order(player_id: i32, action: Tactic, target: Option<i32>) -> Order {
+        Order { player_id, action, target }
+    }
+
+    /// Fights rounds without orders until the fight is over.
+    fn fight_out(encounter: &mut Encounter, rng: &mut StdRng) -> Vec<i32> {
+        let mut fallen = Vec::new();
+        for _ in 0..50 {
+            fallen.extend(fight_round(encounter, &[], rng).unwrap().fallen);
+            if encounter.status != EncounterStatus::Active {
+                break;
+            }
+        }
+        fallen
+    }
+
+    #[test]
+    fn everyone_acts_in_initiative_order() {
+        let mut fight = encounter(vec![
+            combatant(3, Side::Foes, 1000, 0, 10),
+            combatant(1, Side::Players, 1000, 0, 10),
+            combatant(2, Side::Foes, 1000, 0, 10),
+        ]);
+        let fought = fight_round(&mut fight, &[], &mut StdRng::seed_from_u64(7)).unwrap();
+        let actors: Vec<&str> = fought.log.iter().map(|line| &line[..9]).collect();
+        assert_eq!(actors, ["Fighter 3", "Fighter 1", "Fighter 2"]);
+        assert_eq!(fight.round, 1);
+        assert_eq!(fight.status, EncounterStatus::Active);
+    }
+
+    #[test]
+    fn refuses_orders_that_cannot_be_followed() {
+        let mut fight = encounter(vec![combatant(1, Side::Players, 10, 0, 10), combatant(2, Side::Foes, 10, 0, 10), combatant(3, Side::Players, 0, 0, 10)]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let refused = |fight: &mut Encounter, orders: &[Order], rng: &mut StdRng| fight_round(fight, orders, rng).err().unwrap();
+        assert!(refused(&mut fight, &[order(999, Tactic::Attack, None)], &mut rng).contains("not in this fight"));
+        assert!(refused(&mut fight, &[order(101, Tactic::Attack, None), order(101, Tactic::Defend, None)], &mut rng).contains("one order"));
+        assert!(refused(&mut fight, &[order(101, Tactic::Attack, Some(99))], &mut rng).contains("No foe 99"));
+        assert!(refused(&mut fight, &[order(101, Tactic::Attack, Some(1))], &mut rng).contains("No foe 1"));
+        assert!(refused(&mut fight, &[order(103, Tactic::Attack, None)], &mut rng).contains("out of the fight"));
+        // Nothing happened for any of them
+        assert_eq!(fight.round, 0);
+        assert!(fight.combatants.iter().all(|c| c.hp == c.max_hp));
+    }
+
+    #[test]
+    fn defending_makes_them_harder_to_hit() {
+        let mut fight = encounter(vec![combatant(1, Side::Players, 1000, 0, 10), combatant(2, Side::Foes, 1000, 0, 10)]);
+        let fought = fight_round(&mut fight, &[order(101, Tactic::Defend, None)], &mut StdRng::seed_from_u64(3)).unwrap();
+        assert!(fight.combatants[0].defending);
+        assert_eq!(fought.log[0], "Fighter 1 braced for blows");
+        assert!(fought.log[1].contains(" vs 12: "), "{}", fought.log[1]);
+    }
+
+    #[test]
+    fn getting_away_leaves_the_fight_fled() {
+        let mut got_away = 0;
+        for seed in 0..20 {
+            // Nimble enough that only a natural 1 stops them
+            let mut fight = encounter(vec![combatant(1, Side::Players, 1000, 0, 60), combatant(2, Side::Foes, 1000, 0, 10)]);
+            fight_round(&mut fight, &[order(101, Tactic::Flee, None)], &mut StdRng::seed_from_u64(seed)).unwrap();
+            if fight.combatants[0].fled {
+                got_away += 1;
+                assert_eq!(fight.status, EncounterStatus::Fled);
+            } else {
+                assert_eq!(fight.status, EncounterStatus::Active);
+            }
+        }
+        assert!(got_away > 0);
+    }
+
+    #[test]
+    fn striking_down_every_foe_wins() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut fight = encounter(vec![combatant(1, Side::Players, 1000, 50, 60), combatant(2, Side::Foes, 3, 0, 10), combatant(3, Side::Foes, 3, 0, 10)]);
+        let mut fallen = fight_out(&mut fight, &mut rng);
+        fallen.sort();
+        assert_eq!(fight.status, EncounterStatus::Won);
+        assert_eq!(fallen, [2, 3]);
+    }
+
+    #[test]
+    fn falling_loses() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut fight = encounter(vec![combatant(1, Side::Players, 3, -50, 0), combatant(2, Side::Foes, 1000, 50, 10)]);
+        let fallen = fight_out(&mut fight, &mut rng);
+        assert_eq!(fight.status, EncounterStatus::Lost);
+        assert_eq!(fight.combatants[0].hp, 0);
+        assert!(fallen.is_empty());
+        assert!(fight_round(&mut fight, &[order(101, Tactic::Attack, None)], &mut rng).err().unwrap().contains("out of the fight"));
+    }
+}
@@ -1,88 +1,1142 @@
-use sqlx::{SqlitePool, Row};
-use serde::{Serialize, Deserialize};
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct WorldState {
-    pub locations: Vec<Location>,
-    pub factions: Vec<Faction>,
-    pub npcs: Vec<Npc>,
-    pub player: Player,
-    pub world: World,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Location { pub id: i32, pub name: String, pub prosperity: i32, pub safety: i32 }
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Faction { pub id: i32, pub name: String, pub power: i32, pub relation: String }
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Npc { pub id: i32, pub name: String, pub role: String, pub status: String, pub location_id: i32 }
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Player { pub id: i32, pub location_id: i32, pub reputation: i32 }
-#[derive(Serialize, Deserialize, Clone)]
-pub struct World { pub tension: i32, pub story_phase: String }
-
-pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    //sqlx::query("CREATE TABLE IF NOT EXISTS locations (id INTEGER PRIMARY KEY, name TEXT, prosperity INTEGER, safety INTEGER)").execute(pool).await?;
-    //sqlx::query("CREATE TABLE IF NOT EXISTS factions (id INTEGER PRIMARY KEY, name TEXT, power INTEGER, relation TEXT)").execute(pool).await?;
-    //sqlx::query("CREATE TABLE IF NOT EXISTS npcs (id INTEGER PRIMARY KEY, name TEXT, role TEXT, status TEXT, location_id INTEGER)").execute(pool).await?;
-    //sqlx::query("CREATE TABLE IF NOT EXISTS player (id INTEGER PRIMARY KEY, location_id INTEGER, reputation INTEGER)").execute(pool).await?;
-    //sqlx::query("CREATE TABLE IF NOT EXISTS world (id INTEGER PRIMARY KEY, tension INTEGER, story_phase TEXT)").execute(pool).await?;
-    //sqlx::query("CREATE TABLE IF NOT EXISTS event_log (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp TEXT, description TEXT, caused_by TEXT)").execute(pool).await?;
-    //Ok(())
-    sqlx::query("CREATE TABLE IF NOT EXISTS world (id INTEGER PRIMARY KEY, tension INTEGER, story_phase TEXT)").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS player (id INTEGER PRIMARY KEY, location_id INTEGER, reputation INTEGER)").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS locations (id INTEGER PRIMARY KEY, name TEXT, prosperity INTEGER, safety INTEGER)").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS factions (id INTEGER PRIMARY KEY, name TEXT, power INTEGER, relation TEXT)").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS npcs (id INTEGER PRIMARY KEY, name TEXT, role TEXT, status TEXT, location_id INTEGER)").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS story_events (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        narrative TEXT,
-        state_changes TEXT, -- JSON string of changes applied
-        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-    )").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS branch_choices (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        event_id INTEGER,
-        description TEXT,
-        state_changes TEXT, -- JSON string of changes
-        is_default BOOLEAN,
-        FOREIGN KEY (event_id) REFERENCES story_events(id)
-    )").execute(pool).await?;
-    sqlx::query("CREATE TABLE IF NOT EXISTS event_log (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp TEXT, description TEXT, caused_by TEXT)").execute(pool).await?;
-    Ok(())
-}
-
-pub async fn populate_initial_data(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT OR IGNORE INTO locations (id, name, prosperity, safety) VALUES (1, 'Capital', 80, 90)").execute(pool).await?;
-    sqlx::query("INSERT OR IGNORE INTO locations (id, name, prosperity, safety) VALUES (2, 'Willowbrook', 50, 60)").execute(pool).await?;
-    sqlx::query("INSERT OR IGNORE INTO factions (id, name, power, relation) VALUES (1, 'Royal Guard', 70, 'Friendly')").execute(pool).await?;
-    sqlx::query("INSERT OR IGNORE INTO factions (id, name, power, relation) VALUES (2, 'Bandits', 30, 'Hostile')").execute(pool).await?;
-    sqlx::query("INSERT OR IGNORE INTO npcs (id, name, role, status, location_id) VALUES (1, 'King Alric', 'Ruler', 'Alive', 1)").execute(pool).await?;
-    sqlx::query("INSERT OR IGNORE INTO player (id, location_id, reputation) VALUES (1, 1, 50)").execute(pool).await?;
-    sqlx::query("INSERT OR IGNORE INTO world (id, tension, story_phase) VALUES (1, 20, 'Build-Up')").execute(pool).await?;
-    Ok(())
-}
-
-pub async fn get_world_state(pool: &SqlitePool) -> Result<WorldState, sqlx::Error> {
-    let locations = sqlx::query("SELECT * FROM locations").fetch_all(pool).await?
-        .into_iter().map(|row| Location { id: row.get(0), name: row.get(1), prosperity: row.get(2), safety: row.get(3) }).collect();
-    let factions = sqlx::query("SELECT * FROM factions").fetch_all(pool).await?
-        .into_iter().map(|row| Faction { id: row.get(0), name: row.get(1), power: row.get(2), relation: row.get(3) }).collect();
-    let npcs = sqlx::query("SELECT * FROM npcs").fetch_all(pool).await?
-        .into_iter().map(|row| Npc { id: row.get(0), name: row.get(1), role: row.get(2), status: row.get(3), location_id: row.get(4) }).collect();
-    let player = sqlx::query("SELECT * FROM player").fetch_one(pool).await?;
-    let world = sqlx::query("SELECT * FROM world").fetch_one(pool).await?;
-    Ok(WorldState {
-        locations,
-        factions,
-        npcs,
-        player: Player { id: player.get(0), location_id: player.get(1), reputation: player.get(2) },
-        world: World { tension: world.get(1), story_phase: world.get(2) },
-    })
-}
-
-pub async fn log_event(pool: &SqlitePool, description: &str, caused_by: &str) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO event_log (timestamp, description, caused_by) VALUES (datetime('now'), ?, ?)")
-        .bind(description).bind(caused_by).execute(pool).await?;
-    Ok(())
-}
\ No newline at end of file
+use sqlx::{FromRow, SqlitePool, Row};
+use sqlx::migrate::{MigrateError, Migrator};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs;
+use crate::arcs::{self, StoryArcs};
+use crate::items::{self, Catalogue};
+use crate::models::{EncounterStatus, ObjectiveKind, Pact, QuestStatus, Side};
+use crate::progression::{self, Classes};
+use crate::territory;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldState {
+    pub locations: Vec<Location>,
+    pub factions: Vec<Faction>,
+    pub npcs: Vec<Npc>,
+    pub players: Vec<Player>,
+    pub parties: Vec<Party>,
+    pub faction_relations: Vec<FactionRelation>,
+    pub routes: Vec<Route>,
+    pub quests: Vec<Quest>,
+    pub world: World,
+}
+
+impl WorldState {
+    /// How factions `a` and `b` stand with each other, in either order.
+    pub fn relation_between(&self, a: i32, b: i32) -> Option<&FactionRelation> {
+        self.faction_relations.iter().find(|r| (r.faction_id, r.other_id) == (a.min(b), a.max(b)))
+    }
+
+    /// The road between locations `a` and `b`, in either order.
+    pub fn route_between(&self, a: i32, b: i32) -> Option<&Route> {
+        self.routes.iter().find(|r| (r.location_id, r.other_id) == (a.min(b), a.max(b)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Location {
+    pub id: i32,
+    pub name: String,
+    pub prosperity: i32,
+    pub safety: i32,
+    /// Factions with a hold here, strongest first.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub influence: Vec<Influence>,
+    /// See [`territory::controller`].
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub controlled_by: Option<i32>,
+}
+/// How firmly a faction holds a location, from 0 to 100.
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Influence { pub faction_id: i32, pub influence: i32 }
+/// A road between two locations, travelled both ways, stored once with the lower id first.
+/// `danger` from 0 to 100 drives the chance of an encounter on the way.
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Route { pub location_id: i32, pub other_id: i32, pub travel_hours: i32, pub danger: i32 }
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Faction { pub id: i32, pub name: String, pub power: i32, pub relation: String }
+/// Standing from -100 to 100 between two factions, stored once per pair with the lower id first.
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct FactionRelation { pub faction_id: i32, pub other_id: i32, pub standing: i32, pub pact: Pact }
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Npc {
+    pub id: i32,
+    pub name: String,
+    pub role: String,
+    pub status: String,
+    pub location_id: i32,
+    pub faction_id: Option<i32>,
+    #[sqlx(json)]
+    pub traits: Vec<String>,
+    #[sqlx(json)]
+    pub goals: Vec<String>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub dispositions: Vec<Disposition>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub relationships: Vec<Relationship>,
+}
+/// How an NPC feels about a player character, from -100 (hostile) to 100 (devoted).
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Disposition { pub player_id: i32, pub disposition: i32 }
+/// How an NPC sees another NPC, e.g. `{"other_id": 2, "kind": "rival", "strength": -40}`.
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Relationship { pub other_id: i32, pub kind: String, pub strength: i32 }
+
+impl Npc {
+    pub fn is_alive(&self) -> bool {
+        !self.status.eq_ignore_ascii_case("dead")
+    }
+
+    /// The NPC's disposition toward `player`, falling back to the player's reputation.
+    pub fn disposition_toward(&self, player: &Player) -> i32 {
+        self.dispositions.iter()
+            .find(|d| d.player_id == player.id)
+            .map_or(player.reputation, |d| d.disposition)
+    }
+}
+
+/// The editable parts of an NPC, as created or replaced through the API.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NpcFields {
+    pub name: String,
+    pub role: String,
+    #[serde(default = "NpcFields::default_status")]
+    pub status: String,
+    pub location_id: i32,
+    #[serde(default)]
+    pub faction_id: Option<i32>,
+    #[serde(default)]
+    pub traits: Vec<String>,
+    #[serde(default)]
+    pub goals: Vec<String>,
+}
+
+impl NpcFields {
+    fn default_status() -> String {
+        "Alive".to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Player {
+    pub id: i32,
+    pub name: String,
+    pub location_id: i32,
+    pub reputation: i32,
+    pub coins: i32,
+    pub party_id: Option<i32>,
+    #[sqlx(flatten)]
+    pub sheet: Sheet,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub inventory: Vec<InventoryItem>,
+}
+/// A player character's class, attributes and progress; see [`progression`].
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Sheet {
+    pub class: String,
+    pub background: Option<String>,
+    pub might: i32,
+    pub agility: i32,
+    pub wits: i32,
+    pub presence: i32,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub xp: i32,
+    pub level: i32,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub skills: Vec<String>,
+}
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct InventoryItem { pub item: String, pub quantity: i32 }
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Party { pub id: i32, pub name: String }
+/// A quest held by one player character (`player_id`) or a whole party (`party_id`).
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Quest {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    pub status: QuestStatus,
+    pub player_id: Option<i32>,
+    pub party_id: Option<i32>,
+    #[sqlx(flatten)]
+    pub reward: Reward,
+    pub created_at: String,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+}
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Objective { pub id: i32, pub kind: ObjectiveKind, pub target_id: i32, pub required: i32, pub progress: i32 }
+/// What every holder of a quest gets on completing it.
+#[derive(Serialize, Deserialize, Clone, Default, FromRow)]
+pub struct Reward {
+    #[sqlx(rename = "reward_reputation")]
+    #[serde(default)]
+    pub reputation: i32,
+    #[sqlx(rename = "reward_coins")]
+    #[serde(default)]
+    pub coins: i32,
+    /// One of this catalogue item each.
+    #[sqlx(rename = "reward_item")]
+    #[serde(default)]
+    pub item: Option<String>,
+}
+/// A fight against a faction (`faction_id`) or a single NPC (`npc_id`) where
+/// `location_id` is; see [`crate::combat`].
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Encounter {
+    pub id: i32,
+    pub location_id: i32,
+    pub faction_id: Option<i32>,
+    pub npc_id: Option<i32>,
+    pub status: EncounterStatus,
+    /// Rounds fought so far.
+    pub round: i32,
+    pub created_at: String,
+    /// In initiative order.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub combatants: Vec<Combatant>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub rounds: Vec<Round>,
+}
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Combatant {
+    pub id: i32,
+    pub name: String,
+    pub side: Side,
+    pub player_id: Option<i32>,
+    pub npc_id: Option<i32>,
+    pub initiative: i32,
+    pub hp: i32,
+    pub max_hp: i32,
+    /// Added to the d20 when attacking.
+    pub attack: i32,
+    /// What an attack on them has to roll.
+    pub defense: i32,
+    pub damage_die: i32,
+    pub damage_bonus: i32,
+    /// Braced this round, making them harder to hit.
+    pub defending: bool,
+    pub fled: bool,
+}
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct Round {
+    pub round: i32,
+    #[sqlx(json)]
+    pub log: Vec<String>,
+    pub narration: String,
+}
+#[derive(Serialize, Deserialize, Clone, FromRow)]
+pub struct World { pub id: i32, pub name: String, pub seed: String, pub tension: i32, pub story_phase: String, pub arc: String, pub act: i32 }
+
+/// Starting content for a new campaign, keyed by seed name in `data/world_seeds.json`.
+/// Entities refer to locations by name since ids are only assigned on insert.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldSeed {
+    pub tension: i32,
+    pub story_phase: String,
+    /// Key in `data/story_arcs.json`.
+    #[serde(default = "WorldSeed::default_arc")]
+    pub arc: String,
+    pub locations: Vec<SeedLocation>,
+    pub factions: Vec<SeedFaction>,
+    pub npcs: Vec<SeedNpc>,
+    pub players: Vec<SeedPlayer>,
+    /// Pairs left out start from [`default_relation`].
+    #[serde(default)]
+    pub faction_relations: Vec<SeedFactionRelation>,
+    /// Roads between locations by name. Without any, every pair is linked by [`default_route`].
+    #[serde(default)]
+    pub routes: Vec<SeedRoute>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeedLocation {
+    pub name: String,
+    pub prosperity: i32,
+    pub safety: i32,
+    /// Influence by faction name. Left out, factions hold [`DEFAULT_INFLUENCE`] wherever
+    /// their members start.
+    #[serde(default)]
+    pub influence: HashMap<String, i32>,
+}
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeedFaction { pub name: String, pub power: i32, pub relation: String }
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeedNpc {
+    pub name: String,
+    pub role: String,
+    pub status: String,
+    pub location: String,
+    #[serde(default)]
+    pub faction: Option<String>,
+    #[serde(default)]
+    pub traits: Vec<String>,
+    #[serde(default)]
+    pub goals: Vec<String>,
+    /// Other NPCs of the seed by name.
+    #[serde(default)]
+    pub relationships: Vec<SeedRelationship>,
+}
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeedFactionRelation { pub between: [String; 2], pub standing: i32, pub pact: Pact }
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeedRoute { pub between: [String; 2], pub travel_hours: i32, pub danger: i32 }
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeedRelationship { pub npc: String, pub kind: String, pub strength: i32 }
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeedPlayer {
+    pub name: String,
+    pub location: String,
+    pub reputation: i32,
+    /// Players naming the same party start out grouped together.
+    #[serde(default)]
+    pub party: Option<String>,
+    #[serde(default = "SeedPlayer::default_coins")]
+    pub coins: i32,
+    /// Starting kit: quantities by catalogue item name.
+    #[serde(default)]
+    pub items: HashMap<String, i32>,
+    #[serde(default = "SeedPlayer::default_class")]
+    pub class: String,
+    #[serde(default)]
+    pub background: Option<String>,
+}
+
+/// A quest as created through the API or proposed by the narrative provider.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NewQuest {
+    pub title: String,
+    pub description: String,
+    pub objectives: Vec<NewObjective>,
+    #[serde(default)]
+    pub reward: Reward,
+}
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NewObjective {
+    pub kind: ObjectiveKind,
+    pub target_id: i32,
+    /// Fights or conversations needed; reaching a place only ever takes one.
+    #[serde(default = "NewObjective::default_count")]
+    pub count: i32,
+}
+
+impl NewObjective {
+    fn default_count() -> i32 {
+        1
+    }
+}
+
+impl WorldSeed {
+    fn default_arc() -> String {
+        arcs::DEFAULT_ARC.to_string()
+    }
+}
+
+impl SeedPlayer {
+    fn default_coins() -> i32 {
+        items::STARTING_COINS
+    }
+
+    fn default_class() -> String {
+        progression::DEFAULT_CLASS.to_string()
+    }
+}
+
+pub fn load_world_seeds(path: &str, catalogue: &Catalogue, classes: &Classes, arcs: &StoryArcs) -> Result<HashMap<String, WorldSeed>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let seeds: HashMap<String, WorldSeed> = serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+    for (name, seed) in &seeds {
+        let arc = arcs.get(&seed.arc).ok_or_else(|| format!("Seed '{}': unknown story arc '{}'", name, seed.arc))?;
+        if arc.phase(&seed.story_phase).is_none() {
+            return Err(format!("Seed '{}': arc '{}' has no phase '{}'", name, seed.arc, seed.story_phase));
+        }
+        let known = |location: &str| seed.locations.iter().any(|l| l.name == location);
+        if let Some(npc) = seed.npcs.iter().find(|n| !known(&n.location)) {
+            return Err(format!("Seed '{}': NPC {} is at unknown location '{}'", name, npc.name, npc.location));
+        }
+        for relation in &seed.faction_relations {
+            if let Some(faction) = relation.between.iter().find(|f| !seed.factions.iter().any(|x| &x.name == *f)) {
+                return Err(format!("Seed '{}': relation with unknown faction '{}'", name, faction));
+            }
+            if relation.between[0] == relation.between[1] {
+                return Err(format!("Seed '{}': faction {} cannot have a relation with itself", name, relation.between[0]));
+            }
+        }
+        for location in &seed.locations {
+            if let Some(faction) = location.influence.keys().find(|f| !seed.factions.iter().any(|x| &x.name == *f)) {
+                return Err(format!("Seed '{}': {} is held by unknown faction '{}'", name, location.name, faction));
+            }
+            if location.influence.values().any(|i| !(0..=100).contains(i)) {
+                return Err(format!("Seed '{}': influence over {} must be from 0 to 100", name, location.name));
+            }
+        }
+        for route in &seed.routes {
+            if let Some(location) = route.between.iter().find(|l| !known(l)) {
+                return Err(format!("Seed '{}': route to unknown location '{}'", name, location));
+            }
+            if route.between[0] == route.between[1] {
+                return Err(format!("Seed '{}': location {} cannot have a route to itself", name, route.between[0]));
+            }
+            if route.travel_hours <= 0 || !(0..=100).contains(&route.danger) {
+                return Err(format!("Seed '{}': route between {} and {} needs positive travel_hours and danger from 0 to 100", name, route.between[0], route.between[1]));
+            }
+        }
+        if !seed.routes.is_empty() {
+            // Everyone must be able to get everywhere, if only the long way round
+            let mut reached = vec![seed.locations[0].name.as_str()];
+            let mut i = 0;
+            while i < reached.len() {
+                let here = reached[i];
+                for route in seed.routes.iter().filter(|r| r.between.iter().any(|l| l == here)) {
+                    for location in &route.between {
+                        if !reached.contains(&location.as_str()) {
+                            reached.push(location);
+                        }
+                    }
+                }
+                i += 1;
+            }
+            if let Some(location) = seed.locations.iter().find(|l| !reached.contains(&l.name.as_str())) {
+                return Err(format!("Seed '{}': no route leads to {}", name, location.name));
+            }
+        }
+        for npc in &seed.npcs {
+            if let Some(faction) = npc.faction.as_ref().filter(|f| !seed.factions.iter().any(|x| &x.name == *f)) {
+                return Err(format!("Seed '{}': NPC {} belongs to unknown faction '{}'", name, npc.name, faction));
+            }
+            if let Some(other) = npc.relationships.iter().find(|r| r.npc == npc.name || !seed.npcs.iter().any(|n| n.name == r.npc)) {
+                return Err(format!("Seed '{}': NPC {} has a relationship with unknown NPC '{}'", name, npc.name, other.npc));
+            }
+        }
+        if seed.players.is_empty() {
+            return Err(format!("Seed '{}' has no players", name));
+        }
+        if let Some(player) = seed.players.iter().find(|p| !known(&p.location)) {
+            return Err(format!("Seed '{}': player {} starts at unknown location '{}'", name, player.name, player.location));
+        }
+        for player in &seed.players {
+            if !(0..=items::MAX_COINS).contains(&i64::from(player.coins)) {
+                return Err(format!("Seed '{}': player {} must start with 0 to {} coins", name, player.name, items::MAX_COINS));
+            }
+            if let Some(item) = player.items.keys().find(|i| catalogue.get(i).is_none()) {
+                return Err(format!("Seed '{}': player {} carries unknown item '{}'", name, player.name, item));
+            }
+            if player.items.values().any(|q| !(1..=items::MAX_STACK).contains(&i64::from(*q))) {
+                return Err(format!("Seed '{}': player {} must carry 1 to {} of each item", name, player.name, items::MAX_STACK));
+            }
+            if let Err(e) = classes.sheet(&player.class, player.background.as_deref()) {
+                return Err(format!("Seed '{}': player {}: {}", name, player.name, e));
+            }
+        }
+    }
+    Ok(seeds)
+}
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Brings the schema up to date and returns the resulting schema version.
+///
+/// Foreign keys are switched off on the migrating connection so migrations can rebuild
+/// tables (SQLite's only way to change constraints), and checked again before returning.
+pub async fn run_migrations(pool: &SqlitePool) -> Result<i64, MigrateError> {
+    let has_table = |name: &'static str| {
+        sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?").bind(name).fetch_optional(pool)
+    };
+    if has_table("world").await?.is_some() && has_table("_sqlx_migrations").await?.is_none() {
+        return Err(MigrateError::Source(
+            "world.db predates versioned migrations; back it up and remove it so the schema can be rebuilt".into(),
+        ));
+    }
+
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+    let result = MIGRATOR.run(&mut *conn).await;
+    let violations = sqlx::query("PRAGMA foreign_key_check").fetch_all(&mut *conn).await?;
+    sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await?;
+    result?;
+    if !violations.is_empty() {
+        return Err(MigrateError::Source(format!("migrations left {} foreign key violations", violations.len()).into()));
+    }
+
+    let applied: i64 = sqlx::query("SELECT COALESCE(MAX(version), 0) FROM _sqlx_migrations WHERE success = 1")
+        .fetch_one(pool).await?.get(0);
+    let latest = MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0);
+    if applied != latest {
+        return Err(MigrateError::VersionTooNew(applied, latest));
+    }
+    Ok(applied)
+}
+
+/// A fresh in-memory database at the latest schema, shared by the pool's connections. One
+/// is always kept open, since the database goes with the last of them.
+#[cfg(test)]
+pub async fn memory_pool() -> SqlitePool {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    run_migrations(&pool).await.expect("migrations");
+    pool
+}
+
+/// Starts a campaign from the seed named `seed` in `data/world_seeds.json`.
+#[cfg(test)]
+pub async fn seed_test_world(pool: &SqlitePool, seed: &str) -> World {
+    let catalogue = Catalogue::load("data/items.json").expect("item catalogue");
+    let classes = Classes::load("data/classes.json").expect("classes");
+    let arcs = StoryArcs::load("data/story_arcs.json").expect("story arcs");
+    let seeds = load_world_seeds("data/world_seeds.json", &catalogue, &classes, &arcs).expect("world seeds");
+    create_world(pool, "Test", seed, &seeds[seed], &classes).await.expect("seeded world")
+}
+
+/// Where two factions start out from how each feels about the players: alike get along,
+/// friends and enemies of the players are at war.
+pub fn default_relation(a: &str, b: &str) -> (i32, Pact) {
+    match (a, b) {
+        _ if a == b => (25, Pact::None),
+        ("Neutral", _) | (_, "Neutral") => (0, Pact::None),
+        _ => (-50, Pact::War),
+    }
+}
+
+/// Influence a faction starts with where its members are, for seed locations that do not say.
+pub const DEFAULT_INFLUENCE: i32 = 40;
+
+/// A road for seeds that do not lay out their own: half a day's travel, as dangerous as
+/// its ends are unsafe.
+pub fn default_route(a: &SeedLocation, b: &SeedLocation) -> (i32, i32) {
+    (4, (200 - a.safety - b.safety) / 2)
+}
+
+/// Creates a world and its starting content from `seed` in one transaction.
+pub async fn create_world(pool: &SqlitePool, name: &str, seed_name: &str, seed: &WorldSeed, classes: &Classes) -> Result<World, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let world = sqlx::query_as::<_, World>(
+        "INSERT INTO world (name, seed, tension, story_phase, arc) VALUES (?, ?, ?, ?, ?) RETURNING id, name, seed, tension, story_phase, arc, act"
+    )
+    .bind(name).bind(seed_name).bind(seed.tension).bind(&seed.story_phase).bind(&seed.arc)
+    .fetch_one(&mut *tx).await?;
+
+    let mut location_ids = HashMap::new();
+    for location in &seed.locations {
+        let id: i32 = sqlx::query_scalar("INSERT INTO locations (world_id, name, prosperity, safety) VALUES (?, ?, ?, ?) RETURNING id")
+            .bind(world.id).bind(&location.name).bind(location.prosperity).bind(location.safety)
+            .fetch_one(&mut *tx).await?;
+        location_ids.insert(location.name.as_str(), id);
+    }
+    for (i, a) in seed.locations.iter().enumerate() {
+        for b in &seed.locations[i + 1..] {
+            let (travel_hours, danger) = if seed.routes.is_empty() {
+                default_route(a, b)
+            } else {
+                match seed.routes.iter().find(|r| r.between.contains(&a.name) && r.between.contains(&b.name)) {
+                    Some(route) => (route.travel_hours, route.danger),
+                    None => continue,
+                }
+            };
+            set_route(&mut *tx, location_ids[a.name.as_str()], location_ids[b.name.as_str()], travel_hours, danger).await?;
+        }
+    }
+    let mut faction_ids = HashMap::new();
+    for faction in &seed.factions {
+        let id: i32 = sqlx::query_scalar("INSERT INTO factions (world_id, name, power, relation) VALUES (?, ?, ?, ?) RETURNING id")
+            .bind(world.id).bind(&faction.name).bind(faction.power).bind(&faction.relation)
+            .fetch_one(&mut *tx).await?;
+        faction_ids.insert(faction.name.as_str(), id);
+    }
+    for (i, a) in seed.factions.iter().enumerate() {
+        for b in &seed.factions[i + 1..] {
+            let seeded = seed.faction_relations.iter().find(|r| {
+                r.between.contains(&a.name) && r.between.contains(&b.name)
+            });
+            let (standing, pact) = seeded.map_or_else(|| default_relation(&a.relation, &b.relation), |r| (r.standing, r.pact));
+            set_faction_relation(&mut *tx, faction_ids[a.name.as_str()], faction_ids[b.name.as_str()], standing, pact).await?;
+        }
+    }
+    let mut npc_ids = HashMap::new();
+    for npc in &seed.npcs {
+        let id: i32 = sqlx::query_scalar(
+            "INSERT INTO npcs (world_id, name, role, status, location_id, faction_id, traits, goals) VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING id"
+        )
+        .bind(world.id).bind(&npc.name).bind(&npc.role).bind(&npc.status).bind(location_ids[npc.location.as_str()])
+        .bind(npc.faction.as_ref().map(|f| faction_ids[f.as_str()]))
+        .bind(sqlx::types::Json(&npc.traits)).bind(sqlx::types::Json(&npc.goals))
+        .fetch_one(&mut *tx).await?;
+        npc_ids.insert(npc.name.as_str(), id);
+    }
+    for location in &seed.locations {
+        let mut influence: Vec<(&str, i32)> = location.influence.iter().map(|(f, i)| (f.as_str(), *i)).collect();
+        if location.influence.is_empty() {
+            for faction in seed.npcs.iter().filter(|n| n.location == location.name).filter_map(|n| n.faction.as_deref()) {
+                if !influence.iter().any(|(f, _)| *f == faction) {
+                    influence.push((faction, DEFAULT_INFLUENCE));
+                }
+            }
+        }
+        for (faction, influence) in influence {
+            sqlx::query("INSERT INTO location_influence (location_id, faction_id, influence) VALUES (?, ?, ?)")
+                .bind(location_ids[location.name.as_str()]).bind(faction_ids[faction]).bind(influence)
+                .execute(&mut *tx).await?;
+        }
+    }
+    for npc in &seed.npcs {
+        for relationship in &npc.relationships {
+            sqlx::query("INSERT INTO npc_relationships (npc_id, other_id, kind, strength) VALUES (?, ?, ?, ?)")
+                .bind(npc_ids[npc.name.as_str()]).bind(npc_ids[relationship.npc.as_str()]).bind(&relationship.kind).bind(relationship.strength)
+                .execute(&mut *tx).await?;
+        }
+    }
+    let mut party_ids = HashMap::new();
+    for player in &seed.players {
+        let party_id = match &player.party {
+            Some(party) => match party_ids.get(party.as_str()) {
+                Some(id) => Some(*id),
+                None => {
+                    let id: i32 = sqlx::query_scalar("INSERT INTO parties (world_id, name) VALUES (?, ?) RETURNING id")
+                        .bind(world.id).bind(party)
+                        .fetch_one(&mut *tx).await?;
+                    party_ids.insert(party.as_str(), id);
+                    Some(id)
+                }
+            },
+            None => None,
+        };
+        let sheet = classes.sheet(&player.class, player.background.as_deref()).expect("seed classes are checked on load");
+        let new = NewPlayer { name: &player.name, location_id: location_ids[player.location.as_str()], reputation: player.reputation, coins: player.coins, party_id, sheet };
+        let player_id = insert_player(&mut tx, world.id, &new).await?.id;
+        for (item, quantity) in &player.items {
+            sqlx::query("INSERT INTO inventory (player_id, item, quantity) VALUES (?, ?, ?)")
+                .bind(player_id).bind(item).bind(quantity)
+                .execute(&mut *tx).await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(world)
+}
+
+pub async fn list_worlds(pool: &SqlitePool) -> Result<Vec<World>, sqlx::Error> {
+    sqlx::query_as::<_, World>("SELECT id, name, seed, tension, story_phase, arc, act FROM world ORDER BY id").fetch_all(pool).await
+}
+
+pub async fn world_exists(pool: &SqlitePool, world_id: i32) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query("SELECT 1 FROM world WHERE id = ?").bind(world_id).fetch_optional(pool).await?.is_some())
+}
+
+pub async fn get_world_state(pool: &SqlitePool, world_id: i32) -> Result<WorldState, sqlx::Error> {
+    let mut locations = sqlx::query_as::<_, Location>("SELECT id, name, prosperity, safety FROM locations WHERE world_id = ? ORDER BY id")
+        .bind(world_id).fetch_all(pool).await?;
+    let influence = sqlx::query_as::<_, (i32, i32, i32)>(
+        "SELECT i.location_id, i.faction_id, i.influence FROM location_influence i JOIN locations l ON l.id = i.location_id
+         WHERE l.world_id = ? AND i.influence > 0 ORDER BY i.influence DESC, i.faction_id"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    for (location_id, faction_id, influence) in influence {
+        if let Some(location) = locations.iter_mut().find(|l| l.id == location_id) {
+            location.influence.push(Influence { faction_id, influence });
+        }
+    }
+    for location in &mut locations {
+        location.controlled_by = territory::controller(&location.influence);
+    }
+    let factions = sqlx::query_as::<_, Faction>("SELECT id, name, power, relation FROM factions WHERE world_id = ? ORDER BY id")
+        .bind(world_id).fetch_all(pool).await?;
+    let mut npcs = sqlx::query_as::<_, Npc>(&format!("SELECT {} FROM npcs WHERE world_id = ? ORDER BY id", NPC_COLUMNS))
+        .bind(world_id).fetch_all(pool).await?;
+    let dispositions = sqlx::query_as::<_, (i32, i32, i32)>(
+        "SELECT d.npc_id, d.player_id, d.disposition FROM npc_dispositions d JOIN npcs n ON n.id = d.npc_id WHERE n.world_id = ? ORDER BY d.player_id"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    for (npc_id, player_id, disposition) in dispositions {
+        if let Some(npc) = npcs.iter_mut().find(|n| n.id == npc_id) {
+            npc.dispositions.push(Disposition { player_id, disposition });
+        }
+    }
+    let relationships = sqlx::query_as::<_, (i32, i32, String, i32)>(
+        "SELECT r.npc_id, r.other_id, r.kind, r.strength FROM npc_relationships r JOIN npcs n ON n.id = r.npc_id WHERE n.world_id = ? ORDER BY r.other_id"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    for (npc_id, other_id, kind, strength) in relationships {
+        if let Some(npc) = npcs.iter_mut().find(|n| n.id == npc_id) {
+            npc.relationships.push(Relationship { other_id, kind, strength });
+        }
+    }
+    let mut players = sqlx::query_as::<_, Player>(&format!("SELECT {} FROM players WHERE world_id = ? ORDER BY id", PLAYER_COLUMNS))
+        .bind(world_id).fetch_all(pool).await?;
+    let items = sqlx::query_as::<_, (i32, String, i32)>(
+        "SELECT i.player_id, i.item, i.quantity FROM inventory i JOIN players p ON p.id = i.player_id WHERE p.world_id = ? ORDER BY i.item"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    for (player_id, item, quantity) in items {
+        if let Some(player) = players.iter_mut().find(|p| p.id == player_id) {
+            player.inventory.push(InventoryItem { item, quantity });
+        }
+    }
+    let skills = sqlx::query_as::<_, (i32, String)>(
+        "SELECT s.player_id, s.skill FROM player_skills s JOIN players p ON p.id = s.player_id WHERE p.world_id = ? ORDER BY s.skill"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    for (player_id, skill) in skills {
+        if let Some(player) = players.iter_mut().find(|p| p.id == player_id) {
+            player.sheet.skills.push(skill);
+        }
+    }
+    let parties = sqlx::query_as::<_, Party>("SELECT id, name FROM parties WHERE world_id = ? ORDER BY id")
+        .bind(world_id).fetch_all(pool).await?;
+    let faction_relations = sqlx::query_as::<_, FactionRelation>(
+        "SELECT r.faction_id, r.other_id, r.standing, r.pact FROM faction_relations r JOIN factions f ON f.id = r.faction_id
+         WHERE f.world_id = ? ORDER BY r.faction_id, r.other_id"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    let routes = sqlx::query_as::<_, Route>(
+        "SELECT r.location_id, r.other_id, r.travel_hours, r.danger FROM routes r JOIN locations l ON l.id = r.location_id
+         WHERE l.world_id = ? ORDER BY r.location_id, r.other_id"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    let mut quests = sqlx::query_as::<_, Quest>(&format!("SELECT {} FROM quests WHERE world_id = ? ORDER BY id", QUEST_COLUMNS))
+        .bind(world_id).fetch_all(pool).await?;
+    let objectives = sqlx::query_as::<_, (i32, i32, ObjectiveKind, i32, i32, i32)>(
+        "SELECT o.quest_id, o.id, o.kind, o.target_id, o.required, o.progress FROM quest_objectives o JOIN quests q ON q.id = o.quest_id
+         WHERE q.world_id = ? ORDER BY o.id"
+    )
+    .bind(world_id).fetch_all(pool).await?;
+    for (quest_id, id, kind, target_id, required, progress) in objectives {
+        if let Some(quest) = quests.iter_mut().find(|q| q.id == quest_id) {
+            quest.objectives.push(Objective { id, kind, target_id, required, progress });
+        }
+    }
+    let world = sqlx::query_as::<_, World>("SELECT id, name, seed, tension, story_phase, arc, act FROM world WHERE id = ?")
+        .bind(world_id).fetch_one(pool).await?;
+    Ok(WorldState { locations, factions, npcs, players, parties, faction_relations, routes, quests, world })
+}
+
+const NPC_COLUMNS: &str = "id, name, role, status, location_id, faction_id, traits, goals";
+
+pub async fn get_npc(pool: &SqlitePool, world_id: i32, npc_id: i32) -> Result<Option<Npc>, sqlx::Error> {
+    let npc = sqlx::query_as::<_, Npc>(&format!("SELECT {} FROM npcs WHERE id = ? AND world_id = ?", NPC_COLUMNS))
+        .bind(npc_id).bind(world_id).fetch_optional(pool).await?;
+    let Some(mut npc) = npc else { return Ok(None) };
+    npc.dispositions = sqlx::query_as::<_, Disposition>("SELECT player_id, disposition FROM npc_dispositions WHERE npc_id = ? ORDER BY player_id")
+        .bind(npc.id).fetch_all(pool).await?;
+    npc.relationships = sqlx::query_as::<_, Relationship>("SELECT other_id, kind, strength FROM npc_relationships WHERE npc_id = ? ORDER BY other_id")
+        .bind(npc.id).fetch_all(pool).await?;
+    Ok(Some(npc))
+}
+
+pub async fn create_npc(pool: &SqlitePool, world_id: i32, fields: &NpcFields) -> Result<Npc, sqlx::Error> {
+    sqlx::query_as::<_, Npc>(&format!(
+        "INSERT INTO npcs (world_id, name, role, status, location_id, faction_id, traits, goals) VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+        NPC_COLUMNS
+    ))
+    .bind(world_id).bind(&fields.name).bind(&fields.role).bind(&fields.status).bind(fields.location_id).bind(fields.faction_id)
+    .bind(sqlx::types::Json(&fields.traits)).bind(sqlx::types::Json(&fields.goals))
+    .fetch_one(pool).await
+}
+
+/// Replaces the NPC's editable fields, keeping its dispositions and relationships.
+pub async fn update_npc(pool: &SqlitePool, world_id: i32, npc_id: i32, fields: &NpcFields) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE npcs SET name = ?, role = ?, status = ?, location_id = ?, faction_id = ?, traits = ?, goals = ? WHERE id = ? AND world_id = ?"
+    )
+    .bind(&fields.name).bind(&fields.role).bind(&fields.status).bind(fields.location_id).bind(fields.faction_id)
+    .bind(sqlx::types::Json(&fields.traits)).bind(sqlx::types::Json(&fields.goals))
+    .bind(npc_id).bind(world_id)
+    .execute(pool).await?;
+    Ok(())
+}
+
+pub async fn delete_npc(pool: &SqlitePool, world_id: i32, npc_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM npcs WHERE id = ? AND world_id = ?").bind(npc_id).bind(world_id).execute(pool).await?;
+    Ok(())
+}
+
+/// Sets how factions `a` and `b` stand with each other, in either order.
+pub async fn set_faction_relation<'e, E>(executor: E, a: i32, b: i32, standing: i32, pact: Pact) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO faction_relations (faction_id, other_id, standing, pact) VALUES (?, ?, ?, ?)
+         ON CONFLICT (faction_id, other_id) DO UPDATE SET standing = excluded.standing, pact = excluded.pact"
+    )
+    .bind(a.min(b)).bind(a.max(b)).bind(standing.clamp(-100, 100)).bind(pact)
+    .execute(executor).await?;
+    Ok(())
+}
+
+pub async fn set_influence(pool: &SqlitePool, location_id: i32, faction_id: i32, influence: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO location_influence (location_id, faction_id, influence) VALUES (?, ?, ?)
+         ON CONFLICT (location_id, faction_id) DO UPDATE SET influence = excluded.influence"
+    )
+    .bind(location_id).bind(faction_id).bind(influence)
+    .execute(pool).await?;
+    Ok(())
+}
+
+/// Shifts a faction's influence over a location by `delta`, staying within 0 to 100.
+pub async fn add_influence(pool: &SqlitePool, location_id: i32, faction_id: i32, delta: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO location_influence (location_id, faction_id, influence) VALUES (?, ?, MAX(0, MIN(100, ?)))
+         ON CONFLICT (location_id, faction_id) DO UPDATE SET influence = MAX(0, MIN(100, influence + ?))"
+    )
+    .bind(location_id).bind(faction_id).bind(delta).bind(delta)
+    .execute(pool).await?;
+    Ok(())
+}
+
+/// Lays or changes the road between locations `a` and `b`, in either order.
+pub async fn set_route<'e, E>(executor: E, a: i32, b: i32, travel_hours: i32, danger: i32) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO routes (location_id, other_id, travel_hours, danger) VALUES (?, ?, ?, ?)
+         ON CONFLICT (location_id, other_id) DO UPDATE SET travel_hours = excluded.travel_hours, danger = excluded.danger"
+    )
+    .bind(a.min(b)).bind(a.max(b)).bind(travel_hours).bind(danger)
+    .execute(executor).await?;
+    Ok(())
+}
+
+/// Returns whether there was a road to remove.
+pub async fn delete_route(pool: &SqlitePool, a: i32, b: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM routes WHERE location_id = ? AND other_id = ?")
+        .bind(a.min(b)).bind(a.max(b)).execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_disposition(pool: &SqlitePool, npc_id: i32, player_id: i32, disposition: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO npc_dispositions (npc_id, player_id, disposition) VALUES (?, ?, ?)
+         ON CONFLICT (npc_id, player_id) DO UPDATE SET disposition = excluded.disposition"
+    )
+    .bind(npc_id).bind(player_id).bind(disposition)
+    .execute(pool).await?;
+    Ok(())
+}
+
+pub async fn set_relationship(pool: &SqlitePool, npc_id: i32, other_id: i32, kind: &str, strength: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO npc_relationships (npc_id, other_id, kind, strength) VALUES (?, ?, ?, ?)
+         ON CONFLICT (npc_id, other_id) DO UPDATE SET kind = excluded.kind, strength = excluded.strength"
+    )
+    .bind(npc_id).bind(other_id).bind(kind).bind(strength)
+    .execute(pool).await?;
+    Ok(())
+}
+
+/// Returns whether there was a relationship to remove.
+pub async fn delete_relationship(pool: &SqlitePool, npc_id: i32, other_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM npc_relationships WHERE npc_id = ? AND other_id = ?")
+        .bind(npc_id).bind(other_id).execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_player(pool: &SqlitePool, world_id: i32, player_id: i32) -> Result<Option<Player>, sqlx::Error> {
+    let player = sqlx::query_as::<_, Player>(&format!("SELECT {} FROM players WHERE id = ? AND world_id = ?", PLAYER_COLUMNS))
+        .bind(player_id).bind(world_id).fetch_optional(pool).await?;
+    let Some(mut player) = player else { return Ok(None) };
+    player.inventory = sqlx::query_as::<_, InventoryItem>("SELECT item, quantity FROM inventory WHERE player_id = ? ORDER BY item")
+        .bind(player.id).fetch_all(pool).await?;
+    player.sheet.skills = sqlx::query_scalar("SELECT skill FROM player_skills WHERE player_id = ? ORDER BY skill")
+        .bind(player.id).fetch_all(pool).await?;
+    Ok(Some(player))
+}
+
+const PLAYER_COLUMNS: &str = "id, name, location_id, reputation, coins, party_id, class, background, might, agility, wits, presence, hp, max_hp, xp, level";
+
+/// A player character about to be created.
+pub struct NewPlayer<'a> {
+    pub name: &'a str,
+    pub location_id: i32,
+    pub reputation: i32,
+    pub coins: i32,
+    pub party_id: Option<i32>,
+    pub sheet: Sheet,
+}
+
+async fn insert_player(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, world_id: i32, new: &NewPlayer<'_>) -> Result<Player, sqlx::Error> {
+    let sheet = &new.sheet;
+    let mut player = sqlx::query_as::<_, Player>(&format!(
+        "INSERT INTO players (world_id, name, location_id, reputation, coins, party_id, class, background, might, agility, wits, presence, hp, max_hp, xp, level)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING {}", PLAYER_COLUMNS
+    ))
+    .bind(world_id).bind(new.name).bind(new.location_id).bind(new.reputation).bind(new.coins).bind(new.party_id)
+    .bind(&sheet.class).bind(&sheet.background).bind(sheet.might).bind(sheet.agility).bind(sheet.wits).bind(sheet.presence)
+    .bind(sheet.hp).bind(sheet.max_hp).bind(sheet.xp).bind(sheet.level)
+    .fetch_one(&mut **tx).await?;
+    for skill in &sheet.skills {
+        sqlx::query("INSERT INTO player_skills (player_id, skill) VALUES (?, ?)").bind(player.id).bind(skill).execute(&mut **tx).await?;
+    }
+    player.sheet.skills = sheet.skills.clone();
+    Ok(player)
+}
+
+pub async fn create_player(pool: &SqlitePool, world_id: i32, new: &NewPlayer<'_>) -> Result<Player, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let player = insert_player(&mut tx, world_id, new).await?;
+    tx.commit().await?;
+    Ok(player)
+}
+
+pub async fn create_party(pool: &SqlitePool, world_id: i32, name: &str, member_ids: &[i32]) -> Result<Party, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let party = sqlx::query_as::<_, Party>("INSERT INTO parties (world_id, name) VALUES (?, ?) RETURNING id, name")
+        .bind(world_id).bind(name).fetch_one(&mut *tx).await?;
+    for member_id in member_ids {
+        sqlx::query("UPDATE players SET party_id = ? WHERE id = ? AND world_id = ?")
+            .bind(party.id).bind(member_id).bind(world_id).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(party)
+}
+
+/// Moves a player into `party_id`, or out of any party when it is `None`.
+pub async fn set_party(pool: &SqlitePool, world_id: i32, player_id: i32, party_id: Option<i32>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE players SET party_id = ? WHERE id = ? AND world_id = ?")
+        .bind(party_id).bind(player_id).bind(world_id).execute(pool).await?;
+    Ok(())
+}
+
+/// Adds `delta` of `item` to a player's inventory (removing when negative) and returns
+/// the new quantity, or `None` if they do not hold that many.
+pub async fn adjust_inventory(pool: &SqlitePool, player_id: i32, item: &str, delta: i32) -> Result<Option<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let held: i32 = sqlx::query_scalar("SELECT quantity FROM inventory WHERE player_id = ? AND item = ?")
+        .bind(player_id).bind(item).fetch_optional(&mut *tx).await?.unwrap_or(0);
+    let quantity = held + delta;
+    if quantity < 0 {
+        return Ok(None);
+    }
+    if quantity == 0 {
+        sqlx::query("DELETE FROM inventory WHERE player_id = ? AND item = ?").bind(player_id).bind(item).execute(&mut *tx).await?;
+    } else {
+        sqlx::query("INSERT INTO inventory (player_id, item, quantity) VALUES (?, ?, ?) ON CONFLICT (player_id, item) DO UPDATE SET quantity = excluded.quantity")
+            .bind(player_id).bind(item).bind(quantity).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(Some(quantity))
+}
+
+const QUEST_COLUMNS: &str = "id, title, description, status, player_id, party_id, reward_reputation, reward_coins, reward_item, created_at";
+
+/// Stores `quest` for one player character or a party, returning its id.
+pub async fn create_quest(pool: &SqlitePool, world_id: i32, player_id: Option<i32>, party_id: Option<i32>, quest: &NewQuest) -> Result<i32, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let id: i32 = sqlx::query_scalar(
+        "INSERT INTO quests (world_id, title, description, player_id, party_id, reward_reputation, reward_coins, reward_item)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING id"
+    )
+    .bind(world_id).bind(&quest.title).bind(&quest.description).bind(player_id).bind(party_id)
+    .bind(quest.reward.reputation).bind(quest.reward.coins).bind(&quest.reward.item)
+    .fetch_one(&mut *tx).await?;
+    for objective in &quest.objectives {
+        sqlx::query("INSERT INTO quest_objectives (quest_id, kind, target_id, required) VALUES (?, ?, ?, ?)")
+            .bind(id).bind(objective.kind).bind(objective.target_id).bind(objective.count)
+            .execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Moves an active quest to `status`; `false` when it was no longer active.
+pub async fn finish_quest(pool: &SqlitePool, quest_id: i32, status: QuestStatus) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE quests SET status = ? WHERE id = ? AND status = 'active'")
+        .bind(status).bind(quest_id).execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Adds `delta` to an objective's progress, never past what it requires.
+pub async fn advance_objective(pool: &SqlitePool, objective_id: i32, delta: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE quest_objectives SET progress = MAX(0, MIN(required, progress + ?)) WHERE id = ?")
+        .bind(delta).bind(objective_id).execute(pool).await?;
+    Ok(())
+}
+
+const ENCOUNTER_COLUMNS: &str = "id, location_id, faction_id, npc_id, status, round, created_at";
+const COMBATANT_COLUMNS: &str = "id, name, side, player_id, npc_id, initiative, hp, max_hp, attack, defense, damage_die, damage_bonus, defending, fled";
+
+async fn fill_encounter(pool: &SqlitePool, mut encounter: Encounter) -> Result<Encounter, sqlx::Error> {
+    encounter.combatants = sqlx::query_as::<_, Combatant>(&format!(
+        "SELECT {} FROM combatants WHERE encounter_id = ? ORDER BY initiative DESC, id", COMBATANT_COLUMNS
+    ))
+    .bind(encounter.id).fetch_all(pool).await?;
+    encounter.rounds = sqlx::query_as::<_, Round>("SELECT round, log, narration FROM encounter_rounds WHERE encounter_id = ? ORDER BY round")
+        .bind(encounter.id).fetch_all(pool).await?;
+    Ok(encounter)
+}
+
+pub async fn get_encounter(pool: &SqlitePool, world_id: i32, encounter_id: i32) -> Result<Option<Encounter>, sqlx::Error> {
+    let encounter = sqlx::query_as::<_, Encounter>(&format!("SELECT {} FROM encounters WHERE id = ? AND world_id = ?", ENCOUNTER_COLUMNS))
+        .bind(encounter_id).bind(world_id).fetch_optional(pool).await?;
+    match encounter {
+        Some(encounter) => Ok(Some(fill_encounter(pool, encounter).await?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn list_encounters(pool: &SqlitePool, world_id: i32, status: Option<EncounterStatus>) -> Result<Vec<Encounter>, sqlx::Error> {
+    let encounters = sqlx::query_as::<_, Encounter>(&format!(
+        "SELECT {} FROM encounters WHERE world_id = ? AND (? IS NULL OR status = ?) ORDER BY id", ENCOUNTER_COLUMNS
+    ))
+    .bind(world_id).bind(status).bind(status).fetch_all(pool).await?;
+    let mut filled = Vec::new();
+    for encounter in encounters {
+        filled.push(fill_encounter(pool, encounter).await?);
+    }
+    Ok(filled)
+}
+
+/// The active encounter `player_id` is fighting in, if any.
+pub async fn active_encounter_of<'e, E>(executor: E, player_id: i32) -> Result<Option<i32>, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    sqlx::query_scalar(
+        "SELECT e.id FROM encounters e JOIN combatants c ON c.encounter_id = e.id WHERE c.player_id = ? AND e.status = 'active'"
+    )
+    .bind(player_id).fetch_optional(executor).await
+}
+
+/// A player character mustered for a fight while still in another.
+pub struct AlreadyFighting { pub player_id: i32, pub encounter_id: i32 }
+
+/// Starts a fight where `location_id` is between `combatants` (whose ids are ignored),
+/// returning its id, or who is already fighting elsewhere, starting nothing.
+pub async fn create_encounter(
+    pool: &SqlitePool, world_id: i32, location_id: i32, faction_id: Option<i32>, npc_id: Option<i32>, combatants: &[Combatant],
+) -> Result<Result<i32, AlreadyFighting>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    // Writing first takes the database's write lock, so no other fight can be started between
+    // the checks below and the commit
+    let id: i32 = sqlx::query_scalar("INSERT INTO encounters (world_id, location_id, faction_id, npc_id) VALUES (?, ?, ?, ?) RETURNING id")
+        .bind(world_id).bind(location_id).bind(faction_id).bind(npc_id)
+        .fetch_one(&mut *tx).await?;
+    for c in combatants {
+        if let Some(player_id) = c.player_id {
+            if let Some(encounter_id) = active_encounter_of(&mut *tx, player_id).await? {
+                return Ok(Err(AlreadyFighting { player_id, encounter_id }));
+            }
+        }
+        sqlx::query(
+            "INSERT INTO combatants (encounter_id, name, side, player_id, npc_id, initiative, hp, max_hp, attack, defense, damage_die, damage_bonus)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(id).bind(&c.name).bind(c.side).bind(c.player_id).bind(c.npc_id).bind(c.initiative).bind(c.hp).bind(c.max_hp)
+        .bind(c.attack).bind(c.defense).bind(c.damage_die).bind(c.damage_bonus)
+        .execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(Ok(id))
+}
+
+/// Stores a fought round: where everyone stands after it, what happened and how the
+/// encounter now stands. Player characters' hit points follow their combatants'. Returns
+/// `false`, storing nothing, when another round was fought in the meantime.
+pub async fn record_round(pool: &SqlitePool, encounter: &Encounter, round: &Round) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("UPDATE encounters SET round = ?, status = ? WHERE id = ? AND round = ? AND status = 'active'")
+        .bind(round.round).bind(encounter.status).bind(encounter.id).bind(round.round - 1).execute(&mut *tx).await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    for c in &encounter.combatants {
+        let before: i32 = sqlx::query_scalar("SELECT hp FROM combatants WHERE id = ?").bind(c.id).fetch_one(&mut *tx).await?;
+        sqlx::query("UPDATE combatants SET hp = ?, defending = ?, fled = ? WHERE id = ?")
+            .bind(c.hp).bind(c.defending).bind(c.fled).bind(c.id).execute(&mut *tx).await?;
+        if let Some(player_id) = c.player_id {
+            // Only what this round did to them, so healing or wounds from elsewhere since still count
+            sqlx::query("UPDATE players SET hp = MAX(0, MIN(max_hp, hp + ?)) WHERE id = ?").bind(c.hp - before).bind(player_id).execute(&mut *tx).await?;
+        }
+    }
+    sqlx::query("INSERT INTO encounter_rounds (encounter_id, round, log, narration) VALUES (?, ?, ?, ?)")
+        .bind(encounter.id).bind(round.round).bind(sqlx::types::Json(&round.log)).bind(&round.narration)
+        .execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Swaps `quantity` of `item` (negative to give some up) for `coins` (negative to pay) in
+/// one go. Returns `false`, changing nothing, when the player cannot cover their side.
+pub async fn trade(pool: &SqlitePool, player_id: i32, item: &str, quantity: i32, coins: i32) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let (purse, held): (i32, i32) = sqlx::query_as(
+        "SELECT coins, COALESCE((SELECT quantity FROM inventory WHERE player_id = p.id AND item = ?), 0) FROM players p WHERE p.id = ?"
+    )
+    .bind(item).bind(player_id).fetch_one(&mut *tx).await?;
+    let (purse, held) = (purse + coins, held + quantity);
+    if purse < 0 || held < 0 || i64::from(purse) > items::MAX_COINS || i64::from(held) > items::MAX_STACK {
+        return Ok(false);
+    }
+    sqlx::query("UPDATE players SET coins = ? WHERE id = ?").bind(purse).bind(player_id).execute(&mut *tx).await?;
+    if held == 0 {
+        sqlx::query("DELETE FROM inventory WHERE player_id = ? AND item = ?").bind(player_id).bind(item).execute(&mut *tx).await?;
+    } else {
+        sqlx::query("INSERT INTO inventory (player_id, item, quantity) VALUES (?, ?, ?) ON CONFLICT (player_id, item) DO UPDATE SET quantity = excluded.quantity")
+            .bind(player_id).bind(item).bind(held).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn log_event(pool: &SqlitePool, world_id: i32, description: &str, caused_by: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO event_log (world_id, timestamp, description, caused_by) VALUES (?, datetime('now'), ?, ?)")
+        .bind(world_id).bind(description).bind(caused_by).execute(pool).await?;
+    Ok(())
+}
+/// The latest `limit` things that happened in a world, newest first: narrated story events
+/// and the game master's log alike.
+pub async fn recent_events(pool: &SqlitePool, world_id: i32, limit: usize) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT description FROM (
+             SELECT description, CAST(created_at AS TEXT) AS at, 1 AS source, id FROM events WHERE world_id = ?
+             UNION ALL
+             SELECT description, timestamp AS at, 0 AS source, id FROM event_log WHERE world_id = ?
+         ) ORDER BY at DESC, source DESC, id DESC LIMIT ?"
+    )
+    .bind(world_id).bind(world_id).bind(limit as i64)
+    .fetch_all(pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat::{self, Foe};
+
+    #[tokio::test]
+    async fn keeps_player_characters_to_one_fight_at_a_time() {
+        let pool = memory_pool().await;
+        let world = seed_test_world(&pool, "default").await;
+        let state = get_world_state(&pool, world.id).await.unwrap();
+        let (player, foes) = (&state.players[0], state.factions[1].id);
+        let items = Catalogue::load("data/items.json").unwrap();
+        let (location_id, fighters) = combat::muster(&state, &player.name, &[player], Foe::Faction(foes), &items, &mut rand::thread_rng()).unwrap();
+
+        let Ok(first) = create_encounter(&pool, world.id, location_id, Some(foes), None, &fighters).await.unwrap() else {
+            panic!("{} was not fighting yet", player.name);
+        };
+        match create_encounter(&pool, world.id, location_id, Some(foes), None, &fighters).await.unwrap() {
+            Err(fighting) => assert_eq!((fighting.player_id, fighting.encounter_id), (player.id, first)),
+            Ok(second) => panic!("{} was put into encounter {} while fighting in {}", player.name, second, first),
+        }
+        assert_eq!(list_encounters(&pool, world.id, None).await.unwrap().len(), 1);
+
+        sqlx::query("UPDATE encounters SET status = ? WHERE id = ?").bind(EncounterStatus::Fled).bind(first).execute(&pool).await.unwrap();
+        assert!(create_encounter(&pool, world.id, location_id, Some(foes), None, &fighters).await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,114 @@
+//! Conversations between player characters and NPCs. The narrative provider speaks for the
+//! NPC, and every exchange is kept as that NPC's memory for the next conversation.
+
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use crate::db::{Npc, Player, WorldState};
+
+pub const MAX_MESSAGE_CHARS: usize = 500;
+/// Earlier exchanges quoted back to the NPC, newest last.
+const REMEMBERED_EXCHANGES: i64 = 6;
+
+#[derive(Serialize, FromRow)]
+pub struct NpcMemory {
+    pub id: i32,
+    pub npc_id: i32,
+    pub player_id: i32,
+    pub player_name: String,
+    pub message: String,
+    pub reply: String,
+    pub created_at: String,
+}
+
+const MEMORY_COLUMNS: &str = "m.id, m.npc_id, m.player_id, p.name AS player_name, m.message, m.reply, m.created_at";
+
+/// Puts a disposition from -100 to 100 into words.
+pub fn disposition_label(disposition: i32) -> &'static str {
+    match disposition {
+        40.. => "warm",
+        10..=39 => "friendly",
+        -9..=9 => "neutral",
+        -39..=-10 => "wary",
+        _ => "hostile",
+    }
+}
+
+/// The NPC's conversations with anyone, newest first.
+pub async fn memories(pool: &SqlitePool, npc_id: i32, limit: i64) -> Result<Vec<NpcMemory>, sqlx::Error> {
+    sqlx::query_as::<_, NpcMemory>(&format!(
+        "SELECT {} FROM npc_memories m JOIN players p ON p.id = m.player_id WHERE m.npc_id = ? ORDER BY m.id DESC LIMIT ?",
+        MEMORY_COLUMNS
+    ))
+    .bind(npc_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn remember(pool: &SqlitePool, npc_id: i32, player: &Player, message: &str, reply: &str) -> Result<NpcMemory, sqlx::Error> {
+    // Built from RETURNING rather than read back, which another pooled connection may not see yet
+    let (id, created_at): (i32, String) = sqlx::query_as(
+        "INSERT INTO npc_memories (npc_id, player_id, message, reply) VALUES (?, ?, ?, ?) RETURNING id, created_at"
+    )
+    .bind(npc_id)
+    .bind(player.id)
+    .bind(message)
+    .bind(reply)
+    .fetch_one(pool)
+    .await?;
+    Ok(NpcMemory {
+        id,
+        npc_id,
+        player_id: player.id,
+        player_name: player.name.clone(),
+        message: message.to_string(),
+        reply: reply.to_string(),
+        created_at,
+    })
+}
+
+/// The prompt that has the provider answer `message` as `npc`, after the world `context`.
+pub async fn prompt(
+    pool: &SqlitePool,
+    state: &WorldState,
+    context: &str,
+    npc: &Npc,
+    player: &Player,
+    message: &str,
+) -> Result<String, sqlx::Error> {
+    let location = state.locations.iter().find(|l| l.id == npc.location_id).map_or("an unknown place", |l| l.name.as_str());
+    let mut prompt = format!("{}\n\nYou are {}, {} at {} ({})", context, npc.name, npc.role, location, npc.status);
+    if let Some(faction) = npc.faction_id.and_then(|id| state.factions.iter().find(|f| f.id == id)) {
+        prompt.push_str(&format!(", a member of the {}", faction.name));
+    }
+    prompt.push('.');
+    if !npc.traits.is_empty() {
+        prompt.push_str(&format!(" Your traits: {}.", npc.traits.join(", ")));
+    }
+    if !npc.goals.is_empty() {
+        prompt.push_str(&format!(" Your goals: {}.", npc.goals.join("; ")));
+    }
+    for relationship in &npc.relationships {
+        if let Some(other) = state.npcs.iter().find(|n| n.id == relationship.other_id) {
+            prompt.push_str(&format!(" {} is your {} ({}).", other.name, relationship.kind, disposition_label(relationship.strength)));
+        }
+    }
+    let disposition = npc.disposition_toward(player);
+    prompt.push_str(&format!(" You regard {} (reputation {}) as {}.", player.name, player.reputation, disposition_label(disposition)));
+
+    let mut earlier = memories(pool, npc.id, REMEMBERED_EXCHANGES).await?;
+    earlier.reverse();
+    if !earlier.is_empty() {
+        prompt.push_str("\nYou remember these earlier conversations, oldest first:");
+        for memory in &earlier {
+            prompt.push_str(&format!("\n- {} said \"{}\" and you answered \"{}\"", memory.player_name, memory.message, memory.reply));
+        }
+    }
+
+    prompt.push_str(&format!(
+        "\n{} says to you: \"{}\"\nAnswer in character as {}, in one to three sentences of speech only. \
+         Only mention what {} would plausibly know.",
+        player.name, message, npc.name, npc.name
+    ));
+    Ok(prompt)
+}
@@ -0,0 +1,172 @@
+//! Skill checks: a d20 plus modifiers against a difficulty, with a natural 1 or 20 always
+//! going badly or well. Every roll keeps its breakdown so outcomes can be explained.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Easiest and hardest a check can be.
+pub const MIN_DIFFICULTY: i32 = 5;
+pub const MAX_DIFFICULTY: i32 = 25;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    CriticalFailure,
+    Failure,
+    Success,
+    CriticalSuccess,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::CriticalFailure => "critical failure",
+            Outcome::Failure => "failure",
+            Outcome::Success => "success",
+            Outcome::CriticalSuccess => "critical success",
+        }
+    }
+
+    pub fn succeeded(self) -> bool {
+        matches!(self, Outcome::Success | Outcome::CriticalSuccess)
+    }
+
+    /// `base` as it plays out: doubled on a critical success, nothing on a failure and
+    /// half of it the wrong way on a critical failure.
+    pub fn scale(self, base: i32) -> i32 {
+        match self {
+            Outcome::CriticalFailure => -base / 2,
+            Outcome::Failure => 0,
+            Outcome::Success => base,
+            Outcome::CriticalSuccess => base * 2,
+        }
+    }
+}
+
+/// Something adding to or taking from a roll, e.g. `+2` for carrying a weapon.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Modifier {
+    pub source: String,
+    pub value: i32,
+}
+
+impl Modifier {
+    pub fn new(source: &str, value: i32) -> Modifier {
+        Modifier { source: source.to_string(), value }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Roll {
+    /// The natural d20.
+    pub die: i32,
+    pub modifiers: Vec<Modifier>,
+    pub total: i32,
+    pub difficulty: i32,
+    pub outcome: Outcome,
+}
+
+/// Rolls a d20 plus `modifiers` against `difficulty` (kept within
+/// [`MIN_DIFFICULTY`]..=[`MAX_DIFFICULTY`]). Modifiers worth nothing are left out.
+pub fn check(difficulty: i32, modifiers: Vec<Modifier>, rng: &mut impl Rng) -> Roll {
+    let modifiers: Vec<Modifier> = modifiers.into_iter().filter(|m| m.value != 0).collect();
+    let difficulty = difficulty.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY);
+    let die = rng.gen_range(1..=20);
+    let total = die + modifiers.iter().map(|m| m.value).sum::<i32>();
+    let outcome = match die {
+        1 => Outcome::CriticalFailure,
+        20 => Outcome::CriticalSuccess,
+        _ if total >= difficulty => Outcome::Success,
+        _ => Outcome::Failure,
+    };
+    Roll { die, modifiers, total, difficulty, outcome }
+}
+
+/// E.g. "d20 14 +2 weapon -1 reputation = 15 vs 12: success".
+impl fmt::Display for Roll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "d20 {}", self.die)?;
+        for modifier in &self.modifiers {
+            write!(f, " {:+} {}", modifier.value, modifier.source)?;
+        }
+        write!(f, " = {} vs {}: {}", self.total, self.difficulty, self.outcome.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// The same check rolled with a few hundred seeds, enough to see every face of the die.
+    fn rolls(difficulty: i32, modifiers: &[Modifier]) -> Vec<Roll> {
+        (0..400).map(|seed| check(difficulty, modifiers.to_vec(), &mut StdRng::seed_from_u64(seed))).collect()
+    }
+
+    #[test]
+    fn totals_the_die_and_modifiers_against_the_difficulty() {
+        for roll in rolls(12, &[Modifier::new("weapon", 2), Modifier::new("reputation", -1)]) {
+            assert!((1..=20).contains(&roll.die));
+            assert_eq!(roll.total, roll.die + 1);
+            if (2..=19).contains(&roll.die) {
+                assert_eq!(roll.outcome.succeeded(), roll.total >= 12, "{}", roll);
+            }
+        }
+        let same = |seed| check(12, vec![Modifier::new("weapon", 2)], &mut StdRng::seed_from_u64(seed)).die;
+        assert_eq!(same(7), same(7));
+    }
+
+    #[test]
+    fn natural_ones_and_twenties_override_the_total() {
+        let dice: Vec<i32> = rolls(10, &[]).iter().map(|r| r.die).collect();
+        assert!(dice.contains(&1) && dice.contains(&20));
+        for roll in rolls(MAX_DIFFICULTY, &[Modifier::new("blessing", 100)]).into_iter().filter(|r| r.die == 1) {
+            assert_eq!(roll.outcome, Outcome::CriticalFailure, "{}", roll);
+        }
+        for roll in rolls(MIN_DIFFICULTY, &[Modifier::new("curse", -100)]).into_iter().filter(|r| r.die == 20) {
+            assert_eq!(roll.outcome, Outcome::CriticalSuccess, "{}", roll);
+        }
+        assert!(rolls(MAX_DIFFICULTY, &[Modifier::new("blessing", 100)]).iter().all(|r| r.die == 1 || r.outcome.succeeded()));
+    }
+
+    #[test]
+    fn keeps_the_difficulty_within_bounds() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(check(-3, Vec::new(), &mut rng).difficulty, MIN_DIFFICULTY);
+        assert_eq!(check(40, Vec::new(), &mut rng).difficulty, MAX_DIFFICULTY);
+        assert_eq!(check(14, Vec::new(), &mut rng).difficulty, 14);
+    }
+
+    #[test]
+    fn leaves_out_modifiers_worth_nothing() {
+        let roll = check(10, vec![Modifier::new("numbers", 0), Modifier::new("weapon", 2), Modifier::new("armor", 0)], &mut StdRng::seed_from_u64(3));
+        let sources: Vec<&str> = roll.modifiers.iter().map(|m| m.source.as_str()).collect();
+        assert_eq!(sources, ["weapon"]);
+        assert_eq!(roll.total, roll.die + 2);
+    }
+
+    #[test]
+    fn scales_by_outcome() {
+        assert_eq!(Outcome::CriticalSuccess.scale(10), 20);
+        assert_eq!(Outcome::Success.scale(10), 10);
+        assert_eq!(Outcome::Failure.scale(10), 0);
+        assert_eq!(Outcome::CriticalFailure.scale(10), -5);
+        assert_eq!(Outcome::CriticalFailure.scale(3), -1);
+    }
+
+    #[test]
+    fn explains_the_roll() {
+        let roll = Roll {
+            die: 14,
+            modifiers: vec![Modifier::new("weapon", 2), Modifier::new("reputation", -1)],
+            total: 15,
+            difficulty: 12,
+            outcome: Outcome::Success,
+        };
+        assert_eq!(roll.to_string(), "d20 14 +2 weapon -1 reputation = 15 vs 12: success");
+        let plain = Roll { die: 1, modifiers: Vec::new(), total: 1, difficulty: 5, outcome: Outcome::CriticalFailure };
+        assert_eq!(plain.to_string(), "d20 1 = 1 vs 5: critical failure");
+    }
+}
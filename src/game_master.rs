@@ -1,123 +1,991 @@
-use serde::{Serialize, Deserialize};
-use sqlx::{SqlitePool, Row};
-use rand::Rng;
-use std::fs;
-use crate::db::log_event;
-use crate::db::get_world_state;
-
-#[derive(Serialize, Deserialize)]
-pub struct EventResponse { pub events: Vec<String>, pub narrative: String }
-
-#[derive(Serialize, Deserialize)]
-struct EventTemplate { phase: String, description: String, effect: Option<Effect> }
-#[derive(Serialize, Deserialize)]
-struct Effect { query: String, params: Vec<String> }
-
-pub struct GameMaster {
-    event_templates: Vec<EventTemplate>,
-    story_cycles: serde_json::Value,
-    tension_thresholds: Vec<(String, i32)>,
-}
-
-impl GameMaster {
-    pub fn new() -> Self {
-        // Read and parse events.json
-        let event_content = match fs::read_to_string("data/events.json") {
-            Ok(content) => content,
-            Err(e) => panic!("Failed to read data/events.json: {:?}", e),
-        };
-        let event_templates: Vec<EventTemplate> = match serde_json::from_str(&event_content) {
-            Ok(templates) => templates,
-            Err(e) => panic!("Failed to parse data/events.json: {:?}", e),
-        };
-
-        // Read and parse story_cycles.json
-        let story_content = match fs::read_to_string("data/story_cycles.json") {
-            Ok(content) => content,
-            Err(e) => panic!("Failed to read data/story_cycles.json: {:?}", e),
-        };
-        let story_cycles: serde_json::Value = match serde_json::from_str(&story_content) {
-            Ok(cycles) => cycles,
-            Err(e) => panic!("Failed to parse data/story_cycles.json: {:?}", e),
-        };
-
-        GameMaster {
-            event_templates,
-            story_cycles,
-            tension_thresholds: vec![("Build-Up".to_string(), 40), ("Conflict".to_string(), 70), ("Climax".to_string(), 100)],
-        }
-    }
-
-    pub async fn update_world(&self, pool: &SqlitePool, state_change: serde_json::Value) -> Result<EventResponse, sqlx::Error> {
-        let action = state_change["action"].as_str().unwrap_or("");
-        let target = state_change["target"].as_i64().unwrap_or(0) as i32;
-        let value = state_change["value"].as_i64().unwrap_or(1) as i32;
-        let caused_by = state_change["caused_by"].as_str().unwrap_or("Player");
-
-        match action {
-            "move" => {
-                sqlx::query("UPDATE player SET location_id = ? WHERE id = 1").bind(target).execute(pool).await?;
-                log_event(pool, &format!("Knight moved to location {}", target), caused_by).await?;
-            }
-            "help" => {
-                sqlx::query("UPDATE locations SET prosperity = prosperity + ?, safety = safety + ? WHERE id = ?")
-                    .bind(10 * value).bind(10 * value).bind(target).execute(pool).await?;
-                sqlx::query("UPDATE player SET reputation = reputation + ? WHERE id = 1").bind(5 * value).execute(pool).await?;
-                log_event(pool, &format!("Knight helped location {}, increasing prosperity and safety", target), caused_by).await?;
-            }
-            "fight" => {
-                sqlx::query("UPDATE factions SET power = power - ? WHERE id = ?")
-                    .bind(10 * value).bind(target).execute(pool).await?;
-                sqlx::query("UPDATE player SET reputation = reputation + ? WHERE id = 1").bind(3 * value).execute(pool).await?;
-                log_event(pool, &format!("Knight fought faction {}, reducing their power", target), caused_by).await?;
-            }
-            _ => {}
-        }
-
-        let mut tension = sqlx::query("SELECT tension FROM world WHERE id = 1").fetch_one(pool).await?.get::<i32, _>(0);
-        tension = (tension + rand::thread_rng().gen_range(5..15)).min(100);
-        let current_phase = sqlx::query("SELECT story_phase FROM world WHERE id = 1").fetch_one(pool).await?.get::<String, _>(0);
-        let new_phase = self.get_next_phase(tension, &current_phase);
-        sqlx::query("UPDATE world SET tension = ?, story_phase = ? WHERE id = 1").bind(tension).bind(&new_phase).execute(pool).await?;
-
-        Ok(self.generate_events(pool).await?)
-    }
-
-    fn get_next_phase(&self, tension: i32, current_phase: &str) -> String {
-        for (phase, threshold) in &self.tension_thresholds {
-            if tension < *threshold && phase != current_phase {
-                return phase.clone();
-            }
-        }
-        if tension >= 100 { "Climax".to_string() } else { current_phase.to_string() }
-    }
-
-    pub async fn generate_events(&self, pool: &SqlitePool) -> Result<EventResponse, sqlx::Error> {
-        let state = get_world_state(pool).await?;
-        let _tension = state.world.tension;
-        let phase = state.world.story_phase.clone();
-        let player_location = state.player.location_id;
-
-        let possible_events: Vec<_> = self.event_templates.iter().filter(|e| e.phase == phase).collect();
-        if possible_events.is_empty() {
-            return Ok(EventResponse { events: vec![], narrative: "The kingdom is quiet for now...".to_string() });
-        }
-
-        let event = possible_events[rand::thread_rng().gen_range(0..possible_events.len())];
-        let location_name = state.locations.iter().find(|l| l.id == player_location).unwrap().name.clone();
-        let faction_name = state.factions[rand::thread_rng().gen_range(0..possible_events.len())].name.clone();
-        let event_desc = event.description.replace("{location}", &location_name).replace("{faction}", &faction_name);
-        log_event(pool, &event_desc, "System").await?;
-
-        if let Some(effect) = &event.effect {
-            let mut query = effect.query.clone();
-            for param in &effect.params {
-                query = query.replace(param, &state.locations.iter().find(|l| l.id == player_location).unwrap().name);
-            }
-            sqlx::query(&query).execute(pool).await?;
-        }
-
-        let narrative = self.story_cycles.get(&phase).unwrap_or(&serde_json::Value::String("The story unfolds...".to_string())).as_str().unwrap().to_string();
-        Ok(EventResponse { events: vec![event_desc], narrative })
-    }
-}
\ No newline at end of file
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+use crate::arcs::StoryArcs;
+use crate::choices::ChoiceDraft;
+use crate::db::{self, add_influence, get_world_state, log_event, set_faction_relation, FactionRelation, Npc, Player, WorldState};
+use crate::dice::{self, Modifier, Outcome, Roll};
+use crate::items::{Catalogue, MAX_COINS, MAX_STACK};
+use crate::models::{ItemKind, Pact, Risk};
+use crate::progression;
+use crate::quests::{self, Deed};
+use crate::state_changes::{Entity, StateChange, StateChangeError, StateChanges};
+use crate::territory;
+use crate::travel;
+
+#[derive(Serialize, Deserialize)]
+pub struct EventResponse {
+    pub events: Vec<String>,
+    pub narrative: String,
+    /// The skill check that decided the action, if it took one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roll: Option<Roll>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventTemplate {
+    phase: String,
+    description: String,
+    /// Which faction fills `{faction}` and is hit by `faction`/`event` effects.
+    #[serde(default)]
+    faction: Option<Scope>,
+    /// Requires two factions standing this way with each other, filling `{faction}` and
+    /// `{other_faction}`; the event only happens when such a pair exists.
+    #[serde(default)]
+    relation: Option<RelationCondition>,
+    /// Which NPC fills `{npc}` and is hit by `npc`/`event` effects; the event only happens
+    /// when someone fits.
+    #[serde(default)]
+    npc: Option<Participant>,
+    #[serde(default)]
+    effects: Vec<Effect>,
+}
+
+/// e.g. `{"pact": ["war"]}` or `{"min_standing": -40, "max_standing": 40}`.
+#[derive(Serialize, Deserialize)]
+struct RelationCondition {
+    /// Any of these pacts; empty allows all.
+    #[serde(default)]
+    pact: Vec<Pact>,
+    #[serde(default)]
+    min_standing: Option<i32>,
+    #[serde(default)]
+    max_standing: Option<i32>,
+}
+
+impl RelationCondition {
+    fn matches(&self, relation: &FactionRelation) -> bool {
+        (self.pact.is_empty() || self.pact.contains(&relation.pact))
+            && self.min_standing.is_none_or(|min| relation.standing >= min)
+            && self.max_standing.is_none_or(|max| relation.standing <= max)
+    }
+}
+
+/// A stock branch choice, offered when the narrative provider cannot come up with usable ones.
+#[derive(Serialize, Deserialize)]
+struct ChoiceTemplate {
+    description: String,
+    #[serde(default)]
+    faction: Option<Scope>,
+    #[serde(default)]
+    npc: Option<Participant>,
+    risk: Risk,
+    #[serde(default)]
+    effects: Vec<Effect>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum EffectTarget { World, Player, Location, Faction, Npc, Influence }
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Scope {
+    /// The acting player or party, where they stand, or an NPC standing there.
+    Current,
+    /// The faction or NPC named in the event description.
+    Event,
+    /// The second faction of an event conditioned on a relation.
+    Other,
+    /// The faction holding the location where the actor stands.
+    Controller,
+    Random,
+    RandomHostile,
+    RandomFriendly,
+}
+
+/// The part an NPC plays in an event. Dead NPCs never take part, and those where the
+/// actor stands are preferred.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Participant {
+    /// Anyone where the actor stands.
+    Local,
+    /// A member of a faction matching the template's `faction` scope, who then decides
+    /// which faction the event is about.
+    Member,
+    /// Whoever is best disposed toward the actor, if anyone is.
+    Ally,
+    /// Whoever is worst disposed toward the actor, if anyone is.
+    Rival,
+}
+
+/// A declarative template consequence, e.g.
+/// `{"target": "location", "scope": "current", "field": "safety", "delta": -20}`.
+/// Influence effects have no field: the scope picks the faction whose hold on the actor's
+/// location changes, e.g. `{"target": "influence", "scope": "event", "delta": 10}`.
+#[derive(Serialize, Deserialize)]
+struct Effect {
+    target: EffectTarget,
+    #[serde(default)]
+    scope: Option<Scope>,
+    #[serde(default)]
+    field: String,
+    #[serde(default)]
+    delta: Option<i64>,
+    #[serde(default)]
+    set: Option<serde_json::Value>,
+}
+
+impl Effect {
+    /// Turns the effect into a concrete state change against `entity`.
+    fn to_change(&self, entity: Entity) -> Result<StateChange, StateChangeError> {
+        let path = match entity {
+            Entity::World => format!("world.{}", self.field),
+            Entity::Player(id) => format!("players.{}.{}", id, self.field),
+            Entity::Location(id) => format!("locations.{}.{}", id, self.field),
+            Entity::Faction(id) => format!("factions.{}.{}", id, self.field),
+            Entity::Npc(id) => format!("npcs.{}.{}", id, self.field),
+            Entity::Influence(location, faction) => format!("locations.{}.influence.{}", location, faction),
+            Entity::Item(player, item) => format!("players.{}.items.{}", player, item),
+        };
+        let op = match (self.delta, &self.set) {
+            (Some(delta), None) => serde_json::json!([{ "path": path, "add": delta }]),
+            (None, Some(value)) => serde_json::json!([{ "path": path, "set": value }]),
+            _ => return Err(StateChangeError::Invalid(format!("effect on {} needs exactly one of delta or set", path))),
+        };
+        StateChanges::parse(&op).map(|mut changes| changes.0.remove(0))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let scope_ok = match self.target {
+            EffectTarget::World => self.scope.is_none(),
+            EffectTarget::Player => matches!(self.scope, None | Some(Scope::Current | Scope::Random)),
+            EffectTarget::Location => matches!(self.scope, Some(Scope::Current | Scope::Random)),
+            EffectTarget::Faction | EffectTarget::Influence => matches!(
+                self.scope,
+                Some(Scope::Event | Scope::Other | Scope::Controller | Scope::Random | Scope::RandomHostile | Scope::RandomFriendly)
+            ),
+            EffectTarget::Npc => matches!(self.scope, Some(Scope::Current | Scope::Event | Scope::Random)),
+        };
+        if !scope_ok {
+            return Err(format!("{:?} effects cannot use scope {:?}", self.target, self.scope));
+        }
+        if self.target == EffectTarget::Influence && !self.field.is_empty() {
+            return Err("influence effects take no field".to_string());
+        }
+        let example = match self.target {
+            EffectTarget::World => Entity::World,
+            EffectTarget::Player => Entity::Player(0),
+            EffectTarget::Location => Entity::Location(0),
+            EffectTarget::Faction => Entity::Faction(0),
+            EffectTarget::Npc => Entity::Npc(0),
+            EffectTarget::Influence => Entity::Influence(0, 0),
+        };
+        self.to_change(example).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    /// Picks the entities the effect lands on, none when the world has no candidate.
+    /// Player and current-location effects hit every member of the acting party.
+    /// Influence effects land on the faction's hold over each place the actors stand.
+    fn resolve(&self, state: &WorldState, staging: &Staging, cast: &[&Player]) -> Vec<Entity> {
+        let mut rng = rand::thread_rng();
+        let here = whereabouts(cast);
+        let faction = || -> Option<i32> {
+            match self.scope {
+                Some(Scope::Event) => staging.faction,
+                Some(Scope::Other) => staging.other_faction,
+                scope => pick_faction(state, scope, cast),
+            }
+        };
+        let ids = |ids: Vec<i32>, entity: fn(i64) -> Entity| ids.into_iter().map(|id| entity(id.into())).collect();
+        match (self.target, self.scope) {
+            (EffectTarget::World, _) => vec![Entity::World],
+            (EffectTarget::Player, Some(Scope::Random)) => ids(state.players.choose(&mut rng).map(|p| p.id).into_iter().collect(), Entity::Player),
+            (EffectTarget::Player, _) => ids(cast.iter().map(|p| p.id).collect(), Entity::Player),
+            (EffectTarget::Location, Some(Scope::Current)) => ids(here, Entity::Location),
+            (EffectTarget::Location, _) => ids(state.locations.choose(&mut rng).map(|l| l.id).into_iter().collect(), Entity::Location),
+            (EffectTarget::Faction, _) => ids(faction().into_iter().collect(), Entity::Faction),
+            (EffectTarget::Influence, _) => match faction() {
+                Some(faction) => here.into_iter().map(|location| Entity::Influence(location.into(), faction.into())).collect(),
+                None => Vec::new(),
+            },
+            (EffectTarget::Npc, Some(Scope::Event)) => ids(staging.npc.map(|n| n.id).into_iter().collect(), Entity::Npc),
+            (EffectTarget::Npc, Some(Scope::Current)) => ids(
+                state.npcs.iter()
+                    .filter(|n| here.contains(&n.location_id))
+                    .collect::<Vec<_>>()
+                    .choose(&mut rng)
+                    .map(|n| n.id)
+                    .into_iter()
+                    .collect(),
+                Entity::Npc,
+            ),
+            (EffectTarget::Npc, _) => ids(state.npcs.choose(&mut rng).map(|n| n.id).into_iter().collect(), Entity::Npc),
+        }
+    }
+}
+
+/// The distinct locations the actors stand in.
+fn whereabouts(cast: &[&Player]) -> Vec<i32> {
+    let mut here: Vec<i32> = cast.iter().map(|p| p.location_id).collect();
+    here.sort_unstable();
+    here.dedup();
+    here
+}
+
+/// The faction relation a scope asks for, if any.
+fn scope_relation(scope: Option<Scope>) -> Option<&'static str> {
+    match scope {
+        Some(Scope::RandomHostile) => Some("Hostile"),
+        Some(Scope::RandomFriendly) => Some("Friendly"),
+        _ => None,
+    }
+}
+
+fn faction_matches(state: &WorldState, faction_id: i32, scope: Option<Scope>, cast: &[&Player]) -> bool {
+    if scope == Some(Scope::Controller) {
+        let here = whereabouts(cast);
+        return state.locations.iter().any(|l| here.contains(&l.id) && l.controlled_by == Some(faction_id));
+    }
+    let relation = scope_relation(scope);
+    state.factions.iter().any(|f| f.id == faction_id && relation.is_none_or(|r| f.relation == r))
+}
+
+fn pick_faction(state: &WorldState, scope: Option<Scope>, cast: &[&Player]) -> Option<i32> {
+    state.factions.iter()
+        .filter(|f| faction_matches(state, f.id, scope, cast))
+        .collect::<Vec<_>>()
+        .choose(&mut rand::thread_rng())
+        .map(|f| f.id)
+}
+
+/// `member_of` says which factions a `Member` may belong to.
+fn pick_participant<'a>(state: &'a WorldState, role: Participant, member_of: impl Fn(i32) -> bool, cast: &[&Player]) -> Option<&'a Npc> {
+    let mood = |npc: &Npc| cast.iter().map(|p| npc.disposition_toward(p)).sum::<i32>() / cast.len().max(1) as i32;
+    let mut candidates: Vec<&Npc> = state.npcs.iter()
+        .filter(|n| n.is_alive())
+        .filter(|n| match role {
+            Participant::Local => cast.iter().any(|p| p.location_id == n.location_id),
+            Participant::Member => n.faction_id.is_some_and(&member_of),
+            Participant::Ally => mood(n) > 0,
+            Participant::Rival => mood(n) < 0,
+        })
+        .collect();
+    if candidates.iter().any(|n| cast.iter().any(|p| p.location_id == n.location_id)) {
+        candidates.retain(|n| cast.iter().any(|p| p.location_id == n.location_id));
+    }
+    match role {
+        Participant::Ally => candidates.into_iter().max_by_key(|n| mood(n)),
+        Participant::Rival => candidates.into_iter().min_by_key(|n| mood(n)),
+        Participant::Local | Participant::Member => candidates.choose(&mut rand::thread_rng()).copied(),
+    }
+}
+
+/// The factions and NPC a template is about.
+struct Staging<'a> {
+    faction: Option<i32>,
+    other_faction: Option<i32>,
+    npc: Option<&'a Npc>,
+}
+
+/// Casts a template, or `None` when it needs an NPC or a pair of factions that this world
+/// does not have. A member brings their own faction.
+fn stage<'a>(
+    state: &'a WorldState,
+    faction: Option<Scope>,
+    relation: Option<&RelationCondition>,
+    npc: Option<Participant>,
+    cast: &[&Player],
+) -> Option<Staging<'a>> {
+    let pairs: Option<Vec<(i32, i32)>> = relation.map(|condition| {
+        state.faction_relations.iter()
+            .filter(|r| condition.matches(r))
+            .flat_map(|r| [(r.faction_id, r.other_id), (r.other_id, r.faction_id)])
+            .filter(|(a, _)| faction_matches(state, *a, faction, cast))
+            .collect()
+    });
+    let member_of = |id: i32| match &pairs {
+        Some(pairs) => pairs.iter().any(|(a, _)| *a == id),
+        None => faction_matches(state, id, faction, cast),
+    };
+    let npc = match npc {
+        Some(role) => Some((role, pick_participant(state, role, member_of, cast)?)),
+        None => None,
+    };
+    let brought = npc.filter(|(role, _)| *role == Participant::Member).and_then(|(_, npc)| npc.faction_id);
+    let npc = npc.map(|(_, npc)| npc);
+    match pairs {
+        Some(pairs) => {
+            let pairs: Vec<_> = pairs.into_iter().filter(|(a, _)| brought.is_none_or(|id| id == *a)).collect();
+            let (a, b) = *pairs.choose(&mut rand::thread_rng())?;
+            Some(Staging { faction: Some(a), other_faction: Some(b), npc })
+        }
+        None => {
+            let picked = brought.or_else(|| pick_faction(state, faction, cast));
+            // An event about whoever holds the ground needs someone holding it
+            if faction == Some(Scope::Controller) && picked.is_none() {
+                return None;
+            }
+            Some(Staging { faction: picked, other_faction: None, npc })
+        }
+    }
+}
+
+/// Who an action or generated event is about: one character or a whole party.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Actor {
+    Player(i32),
+    Party(i32),
+}
+
+impl Actor {
+    /// The actor's display name and the characters it stands for.
+    pub fn cast<'a>(&self, state: &'a WorldState) -> Result<(String, Vec<&'a Player>), GameError> {
+        match *self {
+            Actor::Player(id) => state.players.iter()
+                .find(|p| p.id == id)
+                .map(|p| (p.name.clone(), vec![p]))
+                .ok_or_else(|| GameError::Invalid(format!("No player with id {} in world {}", id, state.world.id))),
+            Actor::Party(id) => {
+                let party = state.parties.iter()
+                    .find(|p| p.id == id)
+                    .ok_or_else(|| GameError::Invalid(format!("No party with id {} in world {}", id, state.world.id)))?;
+                let members: Vec<_> = state.players.iter().filter(|p| p.party_id == Some(id)).collect();
+                if members.is_empty() {
+                    return Err(GameError::Invalid(format!("Party {} has no members", party.name)));
+                }
+                Ok((party.name.clone(), members))
+            }
+        }
+    }
+}
+
+fn location_name(state: &WorldState, id: i32) -> &str {
+    state.locations.iter().find(|l| l.id == id).map_or("an unknown place", |l| l.name.as_str())
+}
+
+fn faction_name(state: &WorldState, id: i32) -> &str {
+    state.factions.iter().find(|f| f.id == id).map_or("an unknown faction", |f| f.name.as_str())
+}
+
+fn pact_phrase(pact: Pact) -> &'static str {
+    match pact {
+        Pact::None => "a ceasefire",
+        Pact::Treaty => "a treaty",
+        Pact::Alliance => "an alliance",
+        Pact::War => "war",
+    }
+}
+
+/// `description` with the roll that decided it, if any, e.g. "... [d20 14 +2 weapon = 16 vs 12: success]".
+fn with_roll(description: String, roll: Option<&Roll>) -> String {
+    match roll {
+        Some(roll) => format!("{} [{}]", description, roll),
+        None => description,
+    }
+}
+
+/// Each of `members` loses `dice`d6 hit points, down to none.
+async fn wound(pool: &SqlitePool, members: &[&Player], dice: i32) -> Result<(), sqlx::Error> {
+    for member in members {
+        let damage: i32 = {
+            let mut rng = rand::thread_rng();
+            (0..dice).map(|_| rng.gen_range(1..=6)).sum()
+        };
+        sqlx::query("UPDATE players SET hp = MAX(0, hp - ?) WHERE id = ?").bind(damage).bind(member.id).execute(pool).await?;
+    }
+    Ok(())
+}
+
+pub async fn add_reputation(pool: &SqlitePool, members: &[&Player], delta: i32) -> Result<(), sqlx::Error> {
+    for member in members {
+        sqlx::query("UPDATE players SET reputation = MAX(-100, MIN(100, reputation + ?)) WHERE id = ?")
+            .bind(delta).bind(member.id).execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// Gives `player` one more `item`, up to as many as anyone can carry.
+async fn add_item(pool: &SqlitePool, player: &Player, item: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO inventory (player_id, item, quantity) VALUES (?, ?, 1)
+         ON CONFLICT (player_id, item) DO UPDATE SET quantity = MIN(?, quantity + 1)"
+    )
+    .bind(player.id).bind(item).bind(MAX_STACK).execute(pool).await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum GameError {
+    Invalid(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::Invalid(e) => write!(f, "{}", e),
+            GameError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<sqlx::Error> for GameError {
+    fn from(e: sqlx::Error) -> Self {
+        GameError::Database(e)
+    }
+}
+
+impl From<StateChangeError> for GameError {
+    fn from(e: StateChangeError) -> Self {
+        match e {
+            StateChangeError::Database(e) => GameError::Database(e),
+            other => GameError::Invalid(other.to_string()),
+        }
+    }
+}
+
+/// How far an action can be pushed: its `value` runs from 1 for a plain attempt to this for
+/// an all-out one, scaling both what it achieves and what it risks.
+pub const MAX_ACTION_VALUE: i32 = 3;
+
+pub struct GameMaster {
+    items: Arc<Catalogue>,
+    event_templates: Vec<EventTemplate>,
+    choice_templates: Vec<ChoiceTemplate>,
+    arcs: Arc<StoryArcs>,
+}
+
+impl GameMaster {
+    pub fn new(items: Arc<Catalogue>, arcs: Arc<StoryArcs>) -> Self {
+        // Read and parse events.json
+        let event_content = match fs::read_to_string("data/events.json") {
+            Ok(content) => content,
+            Err(e) => panic!("Failed to read data/events.json: {:?}", e),
+        };
+        let event_templates: Vec<EventTemplate> = match serde_json::from_str(&event_content) {
+            Ok(templates) => templates,
+            Err(e) => panic!("Failed to parse data/events.json: {:?}", e),
+        };
+        for template in &event_templates {
+            for effect in &template.effects {
+                if let Err(e) = effect.validate() {
+                    panic!("Invalid effect in data/events.json for '{}': {}", template.description, e);
+                }
+                if effect.scope == Some(Scope::Other) && template.relation.is_none() {
+                    panic!("Invalid effect in data/events.json for '{}': scope other needs a relation", template.description);
+                }
+            }
+        }
+
+        // Read and parse choice_templates.json
+        let choice_content = match fs::read_to_string("data/choice_templates.json") {
+            Ok(content) => content,
+            Err(e) => panic!("Failed to read data/choice_templates.json: {:?}", e),
+        };
+        let choice_templates: Vec<ChoiceTemplate> = match serde_json::from_str(&choice_content) {
+            Ok(templates) => templates,
+            Err(e) => panic!("Failed to parse data/choice_templates.json: {:?}", e),
+        };
+        for template in &choice_templates {
+            for effect in &template.effects {
+                if let Err(e) = effect.validate() {
+                    panic!("Invalid effect in data/choice_templates.json for '{}': {}", template.description, e);
+                }
+                if effect.scope == Some(Scope::Other) {
+                    panic!("Invalid effect in data/choice_templates.json for '{}': choices have no other faction", template.description);
+                }
+            }
+        }
+        if !choice_templates.iter().any(|t| t.faction.is_none() && t.npc.is_none()) {
+            panic!("data/choice_templates.json needs at least one choice that works without a faction or NPC");
+        }
+
+        GameMaster {
+            items,
+            event_templates,
+            choice_templates,
+            arcs,
+        }
+    }
+
+    /// What the actors bring to a check for `action`: standing for help and talks, numbers
+    /// for help and fights, and the gear they carry for fights and getting out of trouble.
+    fn modifiers(&self, action: &str, members: &[&Player]) -> Vec<Modifier> {
+        let carries = |kind: ItemKind| members.iter().flat_map(|p| &p.inventory)
+            .any(|i| self.items.get(&i.item).is_some_and(|item| item.kind == kind));
+        let reputation = members.iter().map(|p| p.reputation).sum::<i32>() / members.len() as i32;
+        let numbers = (members.len() as i32 - 1).min(3);
+        let mut modifiers = Vec::new();
+        if matches!(action, "help" | "negotiate" | "betray") {
+            modifiers.push(Modifier::new("reputation", reputation / 20));
+        }
+        if matches!(action, "help" | "fight") {
+            modifiers.push(Modifier::new("numbers", numbers));
+        }
+        if matches!(action, "fight" | "move") && carries(ItemKind::Weapon) {
+            modifiers.push(Modifier::new("weapon", 2));
+        }
+        if action == "fight" && carries(ItemKind::Armor) {
+            modifiers.push(Modifier::new("armor", 1));
+        }
+        modifiers.extend(progression::modifiers(action, members));
+        modifiers
+    }
+
+    pub async fn update_world(&self, pool: &SqlitePool, world_id: i32, actor: Actor, state_change: serde_json::Value) -> Result<EventResponse, GameError> {
+        let action = state_change["action"].as_str().unwrap_or("");
+        let target = state_change["target"].as_i64().unwrap_or(0) as i32;
+        let value = state_change["value"].as_i64().unwrap_or(1);
+        if !(1..=i64::from(MAX_ACTION_VALUE)).contains(&value) {
+            return Err(GameError::Invalid(format!("value must be between 1 and {}", MAX_ACTION_VALUE)));
+        }
+        let value = value as i32;
+        let caused_by = state_change["caused_by"].as_str().unwrap_or("Player");
+        // Diplomacy is between the target faction and this one
+        let other = state_change["other"].as_i64().map(|id| id as i32);
+
+        let state = get_world_state(pool, world_id).await?;
+        let (name, members) = actor.cast(&state)?;
+        // A fight is played out round by round through its encounter, not around it
+        for member in &members {
+            if let Some(encounter_id) = db::active_encounter_of(pool, member.id).await? {
+                return Err(GameError::Invalid(format!("{} is in the middle of a fight (encounter {}) and must see it through first", member.name, encounter_id)));
+            }
+        }
+        // Targets are plain ids, so make sure they belong to this campaign before touching anything
+        let target_known = match action {
+            "move" | "help" | "rally" => state.locations.iter().any(|l| l.id == target),
+            "fight" | "negotiate" | "betray" | "sabotage" => state.factions.iter().any(|f| f.id == target),
+            _ => true,
+        };
+        if !target_known {
+            let kind = if matches!(action, "move" | "help" | "rally") { "location" } else { "faction" };
+            return Err(GameError::Invalid(format!("No {} with id {} in world {}", kind, target, world_id)));
+        }
+        if let Some(level) = progression::unlocked_at(action).filter(|level| !members.iter().any(|m| m.sheet.level >= *level)) {
+            return Err(GameError::Invalid(format!("{} must reach level {} to {}", name, level, action)));
+        }
+        if let Some(hurt) = members.iter().find(|m| m.sheet.hp == 0).filter(|_| matches!(action, "fight" | "sabotage")) {
+            return Err(GameError::Invalid(format!("{} is too badly hurt to {}; rest first", hurt.name, action)));
+        }
+        let diplomacy = match (action, other) {
+            ("negotiate" | "betray", None) => return Err(GameError::Invalid(format!("{} needs the other faction's id", action))),
+            ("negotiate" | "betray", Some(other)) => Some((other, state.relation_between(target, other).ok_or_else(|| {
+                GameError::Invalid(format!("No faction with id {} other than {} in world {}", other, target, world_id))
+            })?)),
+            _ => None,
+        };
+
+        let modifiers = self.modifiers(action, &members);
+        let mut roll = None;
+        match (action, diplomacy) {
+            ("move", _) => {
+                let from = members[0].location_id;
+                if members.iter().any(|m| m.location_id != from) {
+                    return Err(GameError::Invalid(format!("{} must be in one place to travel together", name)));
+                }
+                if from == target {
+                    return Err(GameError::Invalid(format!("{} is already at {}", name, location_name(&state, target))));
+                }
+                let journey = travel::find_path(&state, from, target).ok_or_else(|| {
+                    GameError::Invalid(format!("No road leads from {} to {}", location_name(&state, from), location_name(&state, target)))
+                })?;
+                // Trouble on the road can be slipped past; otherwise it sends the travellers back to where that leg began
+                let (encounter, evasion) = {
+                    let mut rng = rand::thread_rng();
+                    let encounter = travel::roll_encounter(&state, &journey, &mut rng);
+                    let evasion = encounter.map(|(leg, ambusher)| {
+                        let difficulty = 8 + leg.danger / 8 + ambusher.map_or(0, |f| f.power / 10);
+                        dice::check(difficulty, modifiers, &mut rng)
+                    });
+                    (encounter, evasion)
+                };
+                let outcome = evasion.as_ref().map(|r| r.outcome);
+                let stopped = encounter.filter(|_| !outcome.is_some_and(Outcome::succeeded));
+                let reached = stopped.map_or(target, |(leg, _)| leg.from_id);
+                for member in &members {
+                    sqlx::query("UPDATE players SET location_id = ? WHERE id = ?").bind(reached).bind(member.id).execute(pool).await?;
+                }
+                let via: Vec<&str> = journey.legs.iter().skip(1).map(|leg| location_name(&state, leg.from_id)).collect();
+                let via = if via.is_empty() { String::new() } else { format!(" by way of {}", via.join(", ")) };
+                let travelled = format!("travelled from {} to {}{} ({} hours)", location_name(&state, from), location_name(&state, target), via, journey.hours);
+                let description = match encounter {
+                    Some((leg, ambusher)) => {
+                        let road = format!("the road from {} to {}", location_name(&state, leg.from_id), location_name(&state, leg.to_id));
+                        let fell_back = location_name(&state, leg.from_id);
+                        let trouble = ambusher.map_or("trouble".to_string(), |f| format!("an ambush by {}", f.name));
+                        match (ambusher, outcome) {
+                            (_, Some(Outcome::Success | Outcome::CriticalSuccess)) => format!("{} slipped past {} on {} and {}", name, trouble, road, travelled),
+                            (Some(faction), outcome) => {
+                                // A rout emboldens the ambushers all the more
+                                let gain = if outcome == Some(Outcome::CriticalFailure) { 4 } else { 2 };
+                                sqlx::query("UPDATE factions SET power = MIN(100, power + ?) WHERE id = ?").bind(gain).bind(faction.id).execute(pool).await?;
+                                wound(pool, &members, 1).await?;
+                                format!("{} was ambushed by {} on {} and fell back to {}", name, faction.name, road, fell_back)
+                            }
+                            (None, _) => format!("{} ran into trouble on {} and fell back to {}", name, road, fell_back),
+                        }
+                    }
+                    None => format!("{} {}", name, travelled),
+                };
+                log_event(pool, world_id, &with_roll(description, evasion.as_ref()), caused_by).await?;
+                roll = evasion;
+            }
+            ("help", _) => {
+                let location = state.locations.iter().find(|l| l.id == target);
+                // Places in a bad way are harder to set right
+                let safety = location.map_or(50, |l| l.safety);
+                let check = dice::check(10 + (100 - safety) / 10, modifiers, &mut rand::thread_rng());
+                let boost = check.outcome.scale(10 * value);
+                sqlx::query("UPDATE locations SET prosperity = MAX(0, MIN(100, prosperity + ?)), safety = MAX(0, MIN(100, safety + ?)) WHERE id = ? AND world_id = ?")
+                    .bind(boost).bind(boost).bind(target).bind(world_id).execute(pool).await?;
+                // Order returns with the friendliest hold on the place, or the strongest friend there is
+                let friendly = |id: i32| state.factions.iter().any(|f| f.id == id && f.relation == "Friendly");
+                let backer = location.and_then(|l| l.influence.iter().find(|i| friendly(i.faction_id)).map(|i| i.faction_id))
+                    .or_else(|| state.factions.iter().filter(|f| f.relation == "Friendly").max_by_key(|f| f.power).map(|f| f.id));
+                if let Some(backer) = backer {
+                    add_influence(pool, target, backer, boost).await?;
+                }
+                for hold in location.map_or(&[][..], |l| &l.influence) {
+                    if state.factions.iter().any(|f| f.id == hold.faction_id && f.relation == "Hostile") {
+                        add_influence(pool, target, hold.faction_id, -boost / 2).await?;
+                    }
+                }
+                add_reputation(pool, &members, check.outcome.scale(5 * value)).await?;
+                let place = location_name(&state, target);
+                let description = match check.outcome {
+                    Outcome::CriticalSuccess => format!("{} worked wonders in {}, greatly increasing prosperity and safety", name, place),
+                    Outcome::Success => format!("{} helped {}, increasing prosperity and safety", name, place),
+                    Outcome::Failure => format!("{} tried to help {}, but nothing came of it", name, place),
+                    Outcome::CriticalFailure => format!("{} bungled an attempt to help {}, leaving it worse off", name, place),
+                };
+                log_event(pool, world_id, &with_roll(description, Some(&check)), caused_by).await?;
+                roll = Some(check);
+            }
+            ("fight", _) => {
+                // Stronger factions are harder to beat
+                let power = state.factions.iter().find(|f| f.id == target).map_or(0, |f| f.power);
+                let check = dice::check(8 + power / 8, modifiers, &mut rand::thread_rng());
+                let damage = check.outcome.scale(10 * value);
+                sqlx::query("UPDATE factions SET power = MAX(0, MIN(100, power - ?)) WHERE id = ? AND world_id = ?")
+                    .bind(damage).bind(target).bind(world_id).execute(pool).await?;
+                // Nobody comes out of a lost fight unhurt
+                match check.outcome {
+                    Outcome::Failure => wound(pool, &members, 1).await?,
+                    Outcome::CriticalFailure => wound(pool, &members, 2).await?,
+                    _ => {}
+                }
+                for location in whereabouts(&members) {
+                    add_influence(pool, location, target, -damage).await?;
+                }
+                add_reputation(pool, &members, check.outcome.scale(3 * value)).await?;
+                let foe = faction_name(&state, target);
+                let description = match check.outcome {
+                    Outcome::CriticalSuccess => format!("{} routed {}, badly reducing their power", name, foe),
+                    Outcome::Success => format!("{} fought {}, reducing their power", name, foe),
+                    Outcome::Failure => format!("{} fought {} to a standstill", name, foe),
+                    Outcome::CriticalFailure => format!("{} was beaten back by {}, emboldening them", name, foe),
+                };
+                log_event(pool, world_id, &with_roll(description, Some(&check)), caused_by).await?;
+                if check.outcome.succeeded() {
+                    quests::record(pool, &state, &members, Deed::Fought(target)).await?;
+                    // Spoils: a few coins each, and now and then something worth keeping for one of them
+                    let (coins, loot, finder) = {
+                        let mut rng = rand::thread_rng();
+                        (rng.gen_range(5..=15) * value, self.items.roll_loot(&mut rng), members.choose(&mut rng).copied())
+                    };
+                    for member in &members {
+                        sqlx::query("UPDATE players SET coins = MAX(0, MIN(?, coins + ?)) WHERE id = ?")
+                            .bind(MAX_COINS).bind(coins).bind(member.id).execute(pool).await?;
+                    }
+                    if let (Some(item), Some(finder)) = (loot, finder) {
+                        add_item(pool, finder, &item.name).await?;
+                        log_event(pool, world_id, &format!("{} took a {} from the {}", finder.name, item.name, foe), caused_by).await?;
+                    }
+                }
+                roll = Some(check);
+            }
+            ("negotiate", Some((other, relation))) => {
+                // Respected go-betweens are listened to more often; the stronger side is harder to bring round
+                let power = state.factions.iter().filter(|f| f.id == target || f.id == other).map(|f| f.power).max().unwrap_or(0);
+                let check = dice::check(10 + power / 10, modifiers, &mut rand::thread_rng());
+                let between = format!("{} and {}", faction_name(&state, target), faction_name(&state, other));
+                let description = match check.outcome {
+                    Outcome::Success | Outcome::CriticalSuccess => {
+                        let standing = (relation.standing + check.outcome.scale(15 * value)).clamp(-100, 100);
+                        let pact = relation.pact.improve(standing);
+                        set_faction_relation(pool, target, other, standing, pact).await?;
+                        add_reputation(pool, &members, check.outcome.scale(3 * value)).await?;
+                        let outcome = if pact == relation.pact { "relations warmed".to_string() } else { format!("they agreed to {}", pact_phrase(pact)) };
+                        format!("{} brokered talks between {}; {}", name, between, outcome)
+                    }
+                    Outcome::Failure => {
+                        set_faction_relation(pool, target, other, relation.standing - 5, relation.pact).await?;
+                        format!("Talks {} brokered between {} broke down", name, between)
+                    }
+                    Outcome::CriticalFailure => {
+                        let standing = (relation.standing - 15).clamp(-100, 100);
+                        set_faction_relation(pool, target, other, standing, relation.pact.sour(standing)).await?;
+                        add_reputation(pool, &members, -2).await?;
+                        format!("Talks {} brokered between {} ended in insults", name, between)
+                    }
+                };
+                log_event(pool, world_id, &with_roll(description, Some(&check)), caused_by).await?;
+                roll = Some(check);
+            }
+            ("betray", Some((other, relation))) => {
+                // The betrayal happens either way; how much it costs the betrayed is down to the roll
+                let power = state.factions.iter().find(|f| f.id == target).map_or(0, |f| f.power);
+                let check = dice::check(10 + power / 10, modifiers, &mut rand::thread_rng());
+                let standing = (relation.standing - 30 * value).clamp(-100, 100);
+                set_faction_relation(pool, target, other, standing, relation.pact.sour(standing)).await?;
+                sqlx::query("UPDATE factions SET relation = 'Hostile', power = MAX(0, MIN(100, power - ?)) WHERE id = ? AND world_id = ?")
+                    .bind(check.outcome.scale(10 * value)).bind(target).bind(world_id).execute(pool).await?;
+                sqlx::query("UPDATE factions SET power = MAX(0, MIN(100, power + ?)) WHERE id = ? AND world_id = ?")
+                    .bind(check.outcome.scale(5 * value)).bind(other).bind(world_id).execute(pool).await?;
+                add_reputation(pool, &members, -5 * value).await?;
+                let (betrayed, gainer) = (faction_name(&state, target), faction_name(&state, other));
+                let description = match check.outcome {
+                    Outcome::Success | Outcome::CriticalSuccess => format!("{} betrayed {} to {}", name, betrayed, gainer),
+                    Outcome::Failure => format!("{} betrayed {} to {}, though little came of it", name, betrayed, gainer),
+                    Outcome::CriticalFailure => format!("{} betrayed {} to {}, but the plot backfired on {}", name, betrayed, gainer, gainer),
+                };
+                log_event(pool, world_id, &with_roll(description, Some(&check)), caused_by).await?;
+                roll = Some(check);
+            }
+            ("rally", _) => {
+                // The harder hostile factions hold the place, the harder it is to turn people against them
+                let location = state.locations.iter().find(|l| l.id == target);
+                let hostile = |id: i32| state.factions.iter().any(|f| f.id == id && f.relation == "Hostile");
+                let holds: Vec<i32> = location.map_or(&[][..], |l| &l.influence).iter().filter(|i| hostile(i.faction_id)).map(|i| i.faction_id).collect();
+                let grip = location.map_or(0, |l| l.influence.iter().filter(|i| hostile(i.faction_id)).map(|i| i.influence).max().unwrap_or(0));
+                let check = dice::check(8 + grip / 8, modifiers, &mut rand::thread_rng());
+                let push = check.outcome.scale(10 * value);
+                for faction in &holds {
+                    add_influence(pool, target, *faction, -push).await?;
+                }
+                sqlx::query("UPDATE locations SET safety = MAX(0, MIN(100, safety + ?)) WHERE id = ? AND world_id = ?")
+                    .bind(push / 2).bind(target).bind(world_id).execute(pool).await?;
+                add_reputation(pool, &members, check.outcome.scale(2 * value)).await?;
+                let place = location_name(&state, target);
+                let description = match check.outcome {
+                    Outcome::CriticalSuccess => format!("{} roused all of {} against its oppressors", name, place),
+                    Outcome::Success => format!("{} rallied the people of {}, loosening the hostile grip on it", name, place),
+                    Outcome::Failure => format!("{} tried to rally the people of {}, but few listened", name, place),
+                    Outcome::CriticalFailure => format!("{}'s rally in {} was broken up, leaving the people cowed", name, place),
+                };
+                log_event(pool, world_id, &with_roll(description, Some(&check)), caused_by).await?;
+                roll = Some(check);
+            }
+            ("sabotage", _) => {
+                // Quieter than a fight: less harm done, but nothing to answer for unless caught
+                let faction = state.factions.iter().find(|f| f.id == target);
+                let check = dice::check(10 + faction.map_or(0, |f| f.power) / 10, modifiers, &mut rand::thread_rng());
+                let damage = check.outcome.scale(6 * value);
+                sqlx::query("UPDATE factions SET power = MAX(0, MIN(100, power - ?)) WHERE id = ? AND world_id = ?")
+                    .bind(damage).bind(target).bind(world_id).execute(pool).await?;
+                for location in state.locations.iter().filter(|l| l.influence.iter().any(|i| i.faction_id == target)) {
+                    add_influence(pool, location.id, target, -damage / 2).await?;
+                }
+                if check.outcome == Outcome::CriticalFailure {
+                    wound(pool, &members, 1).await?;
+                    add_reputation(pool, &members, -5).await?;
+                }
+                let foe = faction_name(&state, target);
+                let description = match check.outcome {
+                    Outcome::CriticalSuccess => format!("{} crippled {} from within", name, foe),
+                    Outcome::Success => format!("{} sabotaged {}, weakening them", name, foe),
+                    Outcome::Failure => format!("{} tried to sabotage {}, but could find no way in", name, foe),
+                    Outcome::CriticalFailure => format!("{} was caught sabotaging {} and barely got away", name, foe),
+                };
+                log_event(pool, world_id, &with_roll(description, Some(&check)), caused_by).await?;
+                roll = Some(check);
+            }
+            ("rest", _) => {
+                if members.iter().all(|m| m.sheet.hp == m.sheet.max_hp) {
+                    return Err(GameError::Invalid(format!("{} is already at full strength", name)));
+                }
+                for member in &members {
+                    sqlx::query("UPDATE players SET hp = MIN(max_hp, hp + MAX(1, max_hp / 2)) WHERE id = ?").bind(member.id).execute(pool).await?;
+                }
+                log_event(pool, world_id, &format!("{} rested and recovered their strength", name), caused_by).await?;
+            }
+            _ => return Err(GameError::Invalid(format!("Unknown action '{}'", action))),
+        }
+        if let Some(roll) = &roll {
+            for advance in progression::award(pool, world_id, &members, progression::xp_for(roll.outcome)).await? {
+                log_event(pool, world_id, &advance, caused_by).await?;
+            }
+        }
+        territory::log_shifts(pool, world_id, &state, caused_by).await?;
+        quests::settle(pool, world_id, caused_by).await?;
+
+        if let Some(turn) = self.arcs.advance(pool, world_id).await? {
+            log_event(pool, world_id, &turn, "System").await?;
+        }
+
+        let mut response = self.generate_events(pool, world_id, actor).await?;
+        response.roll = roll;
+        Ok(response)
+    }
+
+    /// Rolls an event for the current phase around `actor`, applying the template's effects.
+    pub async fn generate_events(&self, pool: &SqlitePool, world_id: i32, actor: Actor) -> Result<EventResponse, GameError> {
+        let state = get_world_state(pool, world_id).await?;
+        let phase = state.world.story_phase.clone();
+        let (actor_name, cast) = actor.cast(&state)?;
+        let player_location = cast[0].location_id;
+
+        let mut possible_events: Vec<_> = self.event_templates.iter().filter(|e| e.phase == phase).collect();
+        possible_events.shuffle(&mut rand::thread_rng());
+        // Take the first template whose participants can be found in this world
+        let Some((event, staging)) = possible_events.into_iter()
+            .find_map(|e| stage(&state, e.faction, e.relation.as_ref(), e.npc, &cast).map(|staged| (e, staged)))
+        else {
+            return Ok(EventResponse { events: vec![], narrative: "The kingdom is quiet for now...".to_string(), roll: None });
+        };
+
+        let location_name = match state.locations.iter().find(|l| l.id == player_location) {
+            Some(location) => location.name.clone(),
+            None => return Ok(EventResponse { events: vec![], narrative: format!("{} wanders somewhere unknown...", actor_name), roll: None }),
+        };
+        let faction_name = match staging.faction.and_then(|id| state.factions.iter().find(|f| f.id == id)) {
+            Some(faction) => faction.name.clone(),
+            None => "A nameless band".to_string(),
+        };
+        let mut event_desc = event.description.replace("{location}", &location_name).replace("{faction}", &faction_name).replace("{player}", &actor_name);
+        if let Some(other) = staging.other_faction.and_then(|id| state.factions.iter().find(|f| f.id == id)) {
+            event_desc = event_desc.replace("{other_faction}", &other.name);
+        }
+        if let Some(npc) = staging.npc {
+            event_desc = event_desc.replace("{npc}", &npc.name);
+        }
+        log_event(pool, world_id, &event_desc, "System").await?;
+
+        let mut changes = Vec::new();
+        for effect in &event.effects {
+            let targets = effect.resolve(&state, &staging, &cast);
+            if targets.is_empty() {
+                log::warn!("No {:?} matches scope {:?} for '{}', skipping effect", effect.target, effect.scope, event_desc);
+            }
+            for id in targets {
+                changes.push(effect.to_change(id)?);
+            }
+        }
+        StateChanges(changes).apply(pool, world_id).await?;
+        territory::log_shifts(pool, world_id, &state, "System").await?;
+        quests::settle(pool, world_id, "System").await?;
+
+        let narrative = self.arcs.narrative(&state.world.arc, &phase).to_string();
+        Ok(EventResponse { events: vec![event_desc], narrative, roll: None })
+    }
+
+    /// Stock choices for the world's first player (and their party), used when the
+    /// narrative provider's choices cannot be parsed.
+    pub fn fallback_choices(&self, state: &WorldState) -> Result<Vec<ChoiceDraft>, GameError> {
+        let focus = state.players.first().ok_or_else(|| GameError::Invalid(format!("World {} has no players", state.world.id)))?;
+        let actor = match focus.party_id {
+            Some(party_id) => Actor::Party(party_id),
+            None => Actor::Player(focus.id),
+        };
+        let (actor_name, cast) = actor.cast(state)?;
+        let location = state.locations.iter().find(|l| l.id == focus.location_id);
+
+        let mut templates: Vec<&ChoiceTemplate> = self.choice_templates.iter().collect();
+        templates.shuffle(&mut rand::thread_rng());
+        let mut drafts = Vec::new();
+        for template in templates {
+            let Some(mut staging) = stage(state, template.faction, None, template.npc, &cast) else {
+                continue;
+            };
+            // Only templates that name a faction (or bring one with their NPC) are about one
+            if template.faction.is_none() && template.npc != Some(Participant::Member) {
+                staging.faction = None;
+            }
+            let faction = staging.faction.and_then(|id| state.factions.iter().find(|f| f.id == id));
+            if template.faction.is_some() && faction.is_none() {
+                continue;
+            }
+            let mut description = template.description.replace("{player}", &actor_name);
+            let mut affected = Vec::new();
+            if let Some(location) = location.filter(|_| description.contains("{location}")) {
+                description = description.replace("{location}", &location.name);
+                affected.push(location.name.clone());
+            }
+            if let Some(faction) = faction {
+                description = description.replace("{faction}", &faction.name);
+                affected.push(faction.name.clone());
+            }
+            if let Some(npc) = staging.npc {
+                description = description.replace("{npc}", &npc.name);
+                affected.push(npc.name.clone());
+            }
+            let mut changes = Vec::new();
+            for effect in &template.effects {
+                for id in effect.resolve(state, &staging, &cast) {
+                    changes.push(effect.to_change(id)?);
+                }
+            }
+            drafts.push(ChoiceDraft { description, state_changes: StateChanges(changes), risk: template.risk, affected });
+            if drafts.len() == 3 {
+                break;
+            }
+        }
+        Ok(drafts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat;
+    use crate::models::EncounterStatus;
+    use serde_json::json;
+
+    fn game_master() -> GameMaster {
+        let items = Arc::new(Catalogue::load("data/items.json").unwrap());
+        let arcs = Arc::new(StoryArcs::load("data/story_arcs.json").unwrap());
+        GameMaster::new(items, arcs)
+    }
+
+    #[tokio::test]
+    async fn refuses_every_action_while_fighting() {
+        let pool = db::memory_pool().await;
+        let world = db::seed_test_world(&pool, "default").await;
+        let state = get_world_state(&pool, world.id).await.unwrap();
+        let player = &state.players[0];
+        let (friends, foes) = (state.factions[0].id, state.factions[1].id);
+        let items = Catalogue::load("data/items.json").unwrap();
+        let (location_id, fighters) = combat::muster(&state, &player.name, &[player], combat::Foe::Faction(foes), &items, &mut rand::thread_rng()).unwrap();
+        let Ok(encounter_id) = db::create_encounter(&pool, world.id, location_id, Some(foes), None, &fighters).await.unwrap() else {
+            panic!("{} was not fighting yet", player.name);
+        };
+        let game_master = game_master();
+
+        for action in ["move", "help", "fight", "negotiate", "betray", "rest", "rally", "sabotage"] {
+            let request = json!({"action": action, "target": if action == "move" { 2 } else { foes }, "other": friends});
+            match game_master.update_world(&pool, world.id, Actor::Player(player.id), request).await {
+                Err(GameError::Invalid(e)) => assert!(e.contains("middle of a fight"), "{} was refused for another reason: {}", action, e),
+                Err(e) => panic!("{} failed: {}", action, e),
+                Ok(_) => panic!("{} was allowed mid-fight", action),
+            }
+        }
+
+        sqlx::query("UPDATE encounters SET status = ? WHERE id = ?").bind(EncounterStatus::Won).bind(encounter_id).execute(&pool).await.unwrap();
+        let request = json!({"action": "help", "target": player.location_id});
+        assert!(game_master.update_world(&pool, world.id, Actor::Player(player.id), request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn refuses_unknown_actions_without_touching_the_world() {
+        let pool = db::memory_pool().await;
+        let world = db::seed_test_world(&pool, "default").await;
+        let player = get_world_state(&pool, world.id).await.unwrap().players[0].id;
+        let events = db::recent_events(&pool, world.id, 100).await.unwrap().len();
+        match game_master().update_world(&pool, world.id, Actor::Player(player), json!({"action": "dance", "target": 1})).await {
+            Err(GameError::Invalid(e)) => assert_eq!(e, "Unknown action 'dance'"),
+            Err(e) => panic!("dancing failed: {}", e),
+            Ok(_) => panic!("dancing was allowed"),
+        }
+        assert_eq!(db::recent_events(&pool, world.id, 100).await.unwrap().len(), events);
+    }
+}
@@ -0,0 +1,308 @@
+use async_trait::async_trait;
+use base64::Engine;
+use log::{info, warn};
+use reqwest::Client;
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// What to draw. The location stats tint the placeholder so a thriving capital and a
+/// burning village do not look alike.
+pub struct ImageRequest<'a> {
+    pub prompt: &'a str,
+    pub prosperity: i32,
+    pub safety: i32,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Request(String),
+    Status(u16, String),
+    Malformed(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Request(e) => write!(f, "request failed: {}", e),
+            ImageError::Status(status, body) => write!(f, "backend returned status {}: {}", status, body),
+            ImageError::Malformed(e) => write!(f, "malformed image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+#[async_trait]
+pub trait ImageBackend: Send + Sync {
+    fn name(&self) -> &str;
+    /// Returns PNG bytes.
+    async fn render(&self, request: &ImageRequest<'_>) -> Result<Vec<u8>, ImageError>;
+}
+
+pub fn is_png(data: &[u8]) -> bool {
+    data.len() > PNG_SIGNATURE.len() && data.starts_with(&PNG_SIGNATURE)
+}
+
+fn pixel_art_prompt(prompt: &str) -> String {
+    format!("16-bit pixel art of {}, retro style, vibrant colors, sharp edges", prompt)
+}
+
+pub struct StabilityBackend {
+    client: Client,
+    api_key: String,
+}
+
+impl StabilityBackend {
+    pub fn new(client: Client, api_key: String) -> Self {
+        StabilityBackend { client, api_key }
+    }
+}
+
+#[async_trait]
+impl ImageBackend for StabilityBackend {
+    fn name(&self) -> &str {
+        "stability"
+    }
+
+    async fn render(&self, request: &ImageRequest<'_>) -> Result<Vec<u8>, ImageError> {
+        let resp = self.client
+            .post("https://api.stability.ai/v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "image/png")
+            .json(&serde_json::json!({
+                "text_prompts": [{
+                    "text": pixel_art_prompt(request.prompt),
+                    "weight": 1
+                }],
+                "cfg_scale": 7,
+                "height": 1024,
+                "width": 1024,
+                "samples": 1,
+                "steps": 30
+            }))
+            .send()
+            .await
+            .map_err(|e| ImageError::Request(e.to_string()))?;
+
+        let status = resp.status();
+        info!("Stability AI response status: {}", status);
+        if !status.is_success() {
+            let error_text = resp.text().await.unwrap_or("Unknown error".to_string());
+            return Err(ImageError::Status(status.as_u16(), error_text));
+        }
+        let bytes = resp.bytes().await.map_err(|e| ImageError::Request(e.to_string()))?;
+        if !is_png(&bytes) {
+            return Err(ImageError::Malformed(format!("{} bytes, not a PNG", bytes.len())));
+        }
+        Ok(bytes.to_vec())
+    }
+}
+
+/// A self-hosted Stable Diffusion server exposing the Automatic1111 `txt2img` API
+/// (also served by ComfyUI and Forge compatibility layers).
+pub struct LocalDiffusionBackend {
+    client: Client,
+    base_url: String,
+}
+
+impl LocalDiffusionBackend {
+    pub fn new(client: Client, base_url: &str) -> Self {
+        LocalDiffusionBackend { client, base_url: base_url.trim_end_matches('/').to_string() }
+    }
+}
+
+#[async_trait]
+impl ImageBackend for LocalDiffusionBackend {
+    fn name(&self) -> &str {
+        "local-diffusion"
+    }
+
+    async fn render(&self, request: &ImageRequest<'_>) -> Result<Vec<u8>, ImageError> {
+        let resp = self.client
+            .post(format!("{}/sdapi/v1/txt2img", self.base_url))
+            .json(&serde_json::json!({
+                "prompt": pixel_art_prompt(request.prompt),
+                "width": 512,
+                "height": 512,
+                "steps": 20,
+                "cfg_scale": 7
+            }))
+            .send()
+            .await
+            .map_err(|e| ImageError::Request(e.to_string()))?;
+
+        let status = resp.status();
+        info!("Local diffusion response status: {}", status);
+        if !status.is_success() {
+            let error_text = resp.text().await.unwrap_or("Unknown error".to_string());
+            return Err(ImageError::Status(status.as_u16(), error_text));
+        }
+        let json: serde_json::Value = resp.json().await.map_err(|e| ImageError::Malformed(e.to_string()))?;
+        let encoded = json["images"][0]
+            .as_str()
+            .ok_or_else(|| ImageError::Malformed("missing images[0]".to_string()))?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| ImageError::Malformed(e.to_string()))?;
+        if !is_png(&bytes) {
+            return Err(ImageError::Malformed(format!("{} bytes, not a PNG", bytes.len())));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Procedural pixel-art card, deterministic in the prompt and location stats.
+pub struct PlaceholderBackend;
+
+type Rgb = [u8; 3];
+
+impl PlaceholderBackend {
+    const GRID: usize = 32;
+    const SCALE: usize = 8;
+
+    fn mix(a: Rgb, b: Rgb, t: f32) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+        [0, 1, 2].map(|i| (a[i] as f32 + (b[i] as f32 - a[i] as f32) * t) as u8)
+    }
+
+    fn draw(request: &ImageRequest<'_>) -> Vec<Rgb> {
+        let mut seed = request.prompt.bytes()
+            .fold(0xcbf29ce484222325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+            | 1;
+        let mut next = move |bound: usize| {
+            // xorshift64
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound as u64) as usize
+        };
+
+        let prosperity = request.prosperity.clamp(0, 100) as f32 / 100.0;
+        let danger = 1.0 - request.safety.clamp(0, 100) as f32 / 100.0;
+        // Prosperous places get a bright dusk, dangerous ones a red, smoky sky.
+        let sky_top = Self::mix([16, 18, 48], [40, 60, 140], prosperity);
+        let sky_bottom = Self::mix(Self::mix([90, 70, 160], [250, 170, 90], prosperity), [200, 40, 30], danger);
+        let ground = Self::mix([20, 20, 28], [40, 44, 60], prosperity);
+        let lights = Self::mix([255, 220, 120], [255, 80, 40], danger);
+        let frame = Self::mix([120, 120, 140], [220, 180, 80], prosperity);
+
+        let n = Self::GRID;
+        let mut grid = vec![[0u8; 3]; n * n];
+        for y in 0..n {
+            let t = y as f32 / (n - 1) as f32;
+            for x in 0..n {
+                // Ordered dither between gradient bands keeps the retro look.
+                let dither = if (x + y) % 2 == 0 { 0.03 } else { -0.03 };
+                grid[y * n + x] = Self::mix(sky_top, sky_bottom, t + dither);
+            }
+        }
+
+        for _ in 0..(6 + ((1.0 - danger) * 10.0) as usize) {
+            let (x, y) = (next(n), next(n / 2));
+            grid[y * n + x] = [230, 230, 255];
+        }
+
+        let (moon_x, moon_y, radius) = (4 + next(n - 8), 3 + next(6), 2 + next(2));
+        for y in 0..n {
+            for x in 0..n {
+                let (dx, dy) = (x as i32 - moon_x as i32, y as i32 - moon_y as i32);
+                if dx * dx + dy * dy <= (radius * radius) as i32 {
+                    grid[y * n + x] = Self::mix([240, 236, 210], sky_bottom, danger * 0.6);
+                }
+            }
+        }
+
+        let mut x = 0;
+        while x < n {
+            let width = 2 + next(4);
+            let height = 4 + next(8 + (prosperity * 8.0) as usize);
+            for bx in x..(x + width).min(n) {
+                for by in (n - height)..n {
+                    let lit = bx > x && by % 2 == 0 && next(3) == 0;
+                    grid[by * n + bx] = if lit { lights } else { ground };
+                }
+            }
+            x += width + next(2);
+        }
+
+        for i in 0..n {
+            for (x, y) in [(i, 0), (i, n - 1), (0, i), (n - 1, i)] {
+                grid[y * n + x] = frame;
+            }
+        }
+        grid
+    }
+
+    fn encode(grid: &[Rgb]) -> Result<Vec<u8>, ImageError> {
+        let size = Self::GRID * Self::SCALE;
+        let mut pixels = Vec::with_capacity(size * size * 3);
+        for y in 0..size {
+            for x in 0..size {
+                pixels.extend_from_slice(&grid[(y / Self::SCALE) * Self::GRID + x / Self::SCALE]);
+            }
+        }
+
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, size as u32, size as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| ImageError::Malformed(e.to_string()))?;
+        writer.write_image_data(&pixels).map_err(|e| ImageError::Malformed(e.to_string()))?;
+        writer.finish().map_err(|e| ImageError::Malformed(e.to_string()))?;
+        Ok(out)
+    }
+}
+
+#[async_trait]
+impl ImageBackend for PlaceholderBackend {
+    fn name(&self) -> &str {
+        "placeholder"
+    }
+
+    async fn render(&self, request: &ImageRequest<'_>) -> Result<Vec<u8>, ImageError> {
+        Self::encode(&Self::draw(request))
+    }
+}
+
+/// Renders with the configured backend, falling back to the placeholder so an event
+/// is never lost because image generation failed.
+pub async fn render_or_placeholder(backend: &dyn ImageBackend, request: &ImageRequest<'_>) -> Result<Vec<u8>, ImageError> {
+    match backend.render(request).await {
+        Ok(bytes) => Ok(bytes),
+        Err(e) => {
+            warn!("Image backend {} failed, using placeholder: {}", backend.name(), e);
+            PlaceholderBackend.render(request).await
+        }
+    }
+}
+
+/// Builds the backend selected by `IMAGE_BACKEND` (`stability`, `local` or `placeholder`).
+///
+/// Without an explicit choice, Stability is used when `STABILITY_API_KEY` is set and the
+/// placeholder renderer otherwise.
+pub fn backend_from_env(client: &Client) -> Result<Arc<dyn ImageBackend>, String> {
+    let backend: Arc<dyn ImageBackend> = match env::var("IMAGE_BACKEND").ok().as_deref() {
+        Some("stability") => {
+            let key = env::var("STABILITY_API_KEY").map_err(|_| "IMAGE_BACKEND=stability requires STABILITY_API_KEY".to_string())?;
+            Arc::new(StabilityBackend::new(client.clone(), key))
+        }
+        Some("local") => {
+            let base_url = env::var("IMAGE_BASE_URL").unwrap_or("http://127.0.0.1:7860".to_string());
+            Arc::new(LocalDiffusionBackend::new(client.clone(), &base_url))
+        }
+        Some("placeholder") => Arc::new(PlaceholderBackend),
+        Some(other) => return Err(format!("Unknown IMAGE_BACKEND '{}'", other)),
+        None => match env::var("STABILITY_API_KEY") {
+            Ok(key) => Arc::new(StabilityBackend::new(client.clone(), key)),
+            Err(_) => {
+                warn!("STABILITY_API_KEY not set and no IMAGE_BACKEND configured, using placeholder images");
+                Arc::new(PlaceholderBackend)
+            }
+        },
+    };
+    info!("Using image backend: {}", backend.name());
+    Ok(backend)
+}
@@ -0,0 +1,103 @@
+//! What there is to carry: the item catalogue in `data/items.json`, what shops charge for
+//! it, and what turns up after a fight.
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use crate::db::Location;
+use crate::models::ItemKind;
+
+/// Coins a new player character starts with.
+pub const STARTING_COINS: i32 = 50;
+/// Most coins a character can hold.
+pub const MAX_COINS: i64 = 1_000_000;
+/// Most of one item a character can carry.
+pub const MAX_STACK: i64 = 999;
+/// Percent chance that a won fight turns up an item as well as coins.
+const LOOT_CHANCE: i32 = 50;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Item {
+    pub name: String,
+    pub description: String,
+    pub kind: ItemKind,
+    /// List price in coins; see [`buy_price`] for what shops actually ask.
+    pub price: i32,
+    /// Shops carry the item where prosperity is at least this. Left out, no shop sells it.
+    #[serde(default)]
+    pub sold_from: Option<i32>,
+    /// How often the item turns up as loot, relative to the others. 0 never does.
+    #[serde(default)]
+    pub loot_weight: u32,
+}
+
+/// An item on a shop's shelf, priced for where the shop is.
+#[derive(Serialize)]
+pub struct Offer<'a> {
+    #[serde(flatten)]
+    pub item: &'a Item,
+    pub buy_price: i32,
+    pub sell_price: i32,
+}
+
+pub struct Catalogue {
+    items: Vec<Item>,
+}
+
+impl Catalogue {
+    pub fn load(path: &str) -> Result<Catalogue, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let items: Vec<Item> = serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        for (i, item) in items.iter().enumerate() {
+            // Names end up in state change paths, which are split on dots
+            if item.name.trim().is_empty() || item.name.contains('.') {
+                return Err(format!("{}: item names must not be empty or contain '.', got '{}'", path, item.name));
+            }
+            if items[..i].iter().any(|other| other.name == item.name) {
+                return Err(format!("{}: item {} is listed twice", path, item.name));
+            }
+            if item.price <= 0 || item.sold_from.is_some_and(|p| !(0..=100).contains(&p)) {
+                return Err(format!("{}: item {} needs a positive price and sold_from from 0 to 100", path, item.name));
+            }
+        }
+        Ok(Catalogue { items })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Item> {
+        self.items.iter().find(|i| i.name == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(|i| i.name.as_str())
+    }
+
+    /// What the shop at `location` sells, cheapest first.
+    pub fn stock<'a>(&'a self, location: &Location) -> Vec<Offer<'a>> {
+        let mut offers: Vec<Offer> = self.items.iter()
+            .filter(|i| i.sold_from.is_some_and(|from| location.prosperity >= from))
+            .map(|item| Offer { item, buy_price: buy_price(item, location), sell_price: sell_price(item, location) })
+            .collect();
+        offers.sort_by_key(|o| o.buy_price);
+        offers
+    }
+
+    /// Rolls for an item among the spoils of a fight.
+    pub fn roll_loot(&self, rng: &mut impl Rng) -> Option<&Item> {
+        if rng.gen_range(0..100) >= LOOT_CHANCE {
+            return None;
+        }
+        self.items.choose_weighted(rng, |i| i.loot_weight).ok()
+    }
+}
+
+/// What a shop at `location` asks for `item`: from half the list price where trade has
+/// dried up to half again as much where it is booming.
+pub fn buy_price(item: &Item, location: &Location) -> i32 {
+    (item.price * (50 + location.prosperity) / 100).max(1)
+}
+
+/// What a shop at `location` pays for `item`: half what it asks.
+pub fn sell_price(item: &Item, location: &Location) -> i32 {
+    buy_price(item, location) / 2
+}
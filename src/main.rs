@@ -1,94 +1,223 @@
 use actix_web::{web, App, HttpResponse, HttpServer, Responder, Error};
 use actix_cors::Cors;
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Sqlite, FromRow, Row};
+use sqlx::{Pool, Sqlite, SqlitePool, FromRow, Row};
+use sqlx::sqlite::SqliteConnectOptions;
 use log::info;
 use reqwest::Client;
-use std::env;
-use actix_web::body::to_bytes;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+mod arcs;
+mod choices;
+mod combat;
+mod db;
+mod dice;
+mod dialogue;
+mod game_master;
+mod imagery;
+mod items;
+mod memory;
+mod narrative;
+mod models;
+mod progression;
+mod prompt_context;
+mod quests;
+mod search;
+mod simulation;
+mod state_changes;
+mod story;
+mod territory;
+mod travel;
+
+use arcs::StoryArcs;
+use db::WorldSeed;
+use game_master::{Actor, GameError, GameMaster};
+use models::{EncounterStatus, Pact, QuestStatus};
+use imagery::ImageBackend;
+use items::Catalogue;
+use narrative::{narrate_or, NarrativeProvider, PromptKind};
+use progression::Classes;
+use prompt_context::{PromptContext, Recall};
+use state_changes::{Entity, StateChangeError, StateChanges};
+use story::StoryError;
 
 #[derive(Clone)]
 struct AppState {
     pool: Pool<Sqlite>,
-    client: Client,
+    narrative: Arc<dyn NarrativeProvider>,
+    images: Arc<dyn ImageBackend>,
+    game_master: Arc<GameMaster>,
+    choice_timeout_secs: i64,
+    choice_retries: usize,
+    prompt_context: PromptContext,
+    seeds: Arc<HashMap<String, WorldSeed>>,
+    items: Arc<Catalogue>,
+    classes: Arc<Classes>,
 }
 
 #[derive(Serialize, Deserialize, FromRow)]
-struct World {
+struct Event {
     id: i32,
-    tension: i32,
-    story_phase: String,
+    world_id: i32,
+    description: String,
+    created_at: String,
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
-struct Player {
-    id: i32,
-    location_id: i32,
-    reputation: i32,
+#[derive(Serialize, Deserialize)]
+struct CreateWorldRequest {
+    name: String,
+    seed: Option<String>, // key in data/world_seeds.json, defaults to "default"
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
-struct Location {
-    id: i32,
-    name: String,
-    prosperity: i32,
-    safety: i32,
+#[derive(Serialize, Deserialize)]
+struct UpdateStateRequest {
+    player_reputation: Option<Vec<(i32, i32)>>, // (player_id, reputation)
+    faction_power: Option<Vec<(i32, i32)>>, // (faction_id, power)
+    changes: Option<StateChanges>, // e.g., {"world.tension": 10, "locations.1.safety": -20}
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
-struct Faction {
-    id: i32,
-    name: String,
-    power: i32,
-    relation: String,
+#[derive(Serialize, Deserialize)]
+struct GenerateEventRequest {
+    context: String, // e.g., "battle between factions"
+    player_id: Option<i32>, // whose surroundings to narrate, defaults to the first player
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
-struct Npc {
-    id: i32,
+#[derive(Serialize, Deserialize)]
+struct PlayerActionRequest {
+    action: String, // "move", "help", "fight", "negotiate", "betray" or "rest", or once unlocked "rally" or "sabotage"
+    #[serde(default)]
+    target: i32,    // location id for move (by road), help and rally, faction id otherwise; rest needs none
+    other: Option<i32>, // negotiate between target and this faction, or betray target to it
+    value: Option<i32>, // how hard to push, from 1 (the default) to game_master::MAX_ACTION_VALUE
+    player_id: Option<i32>, // who acts: one player character...
+    party_id: Option<i32>,  // ...or every member of a party
+}
+
+#[derive(Serialize, Deserialize)]
+struct CreatePlayerRequest {
     name: String,
-    role: String,
-    status: String,
     location_id: i32,
+    reputation: Option<i32>,
+    coins: Option<i32>, // defaults to items::STARTING_COINS
+    party_id: Option<i32>,
+    class: Option<String>, // defaults to progression::DEFAULT_CLASS
+    background: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
-struct WorldState {
-    world: World,
-    player: Player,
-    locations: Vec<Location>,
-    factions: Vec<Faction>,
-    npcs: Vec<Npc>,
+struct CreatePartyRequest {
+    name: String,
+    member_ids: Option<Vec<i32>>,
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
-struct Event {
-    id: i32,
-    world_id: i32,
-    description: String,
-    created_at: String,
+#[derive(Serialize, Deserialize)]
+struct SetPartyRequest {
+    party_id: Option<i32>, // null leaves the current party
 }
 
 #[derive(Serialize, Deserialize)]
-struct UpdateStateRequest {
-    player_reputation: Option<i32>,
-    faction_power: Option<Vec<(i32, i32)>>, // (faction_id, power)
+struct InventoryRequest {
+    item: String,
+    quantity: i32, // negative to remove
 }
 
 #[derive(Serialize, Deserialize)]
-struct GenerateEventRequest {
-    context: String, // e.g., "battle between factions"
+struct ShopRequest {
+    player_id: i32, // who trades; they must be at the shop's location
+    item: String,
+    quantity: Option<i32>, // defaults to 1
 }
 
 #[derive(Serialize, Deserialize)]
-struct BranchChoice {
-    id: i32,
-    description: String,
+struct CreateQuestRequest {
+    player_id: Option<i32>, // who takes the quest on: one player character...
+    party_id: Option<i32>,  // ...or every member of a party
+    #[serde(flatten)]
+    quest: db::NewQuest,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GenerateQuestRequest {
+    player_id: Option<i32>,
+    party_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QuestQuery {
+    status: Option<QuestStatus>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CreateEncounterRequest {
+    player_id: Option<i32>, // who fights: one player character...
+    party_id: Option<i32>,  // ...or every member of a party
+    faction_id: Option<i32>, // against a faction's fighters...
+    npc_id: Option<i32>,     // ...or one NPC where they are
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncounterQuery {
+    status: Option<EncounterStatus>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ActRequest {
+    #[serde(default)]
+    orders: Vec<combat::Order>, // player characters without one attack
+}
+
+#[derive(Serialize, Deserialize)]
+struct FactionRelationRequest {
+    standing: i32, // -100 to 100
+    pact: Pact,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RouteRequest {
+    travel_hours: i32, // at least 1
+    danger: i32, // 0 to 100
+}
+
+#[derive(Serialize, Deserialize)]
+struct InfluenceRequest {
+    influence: i32, // 0 to 100
 }
 
-#[actix_web::options("/world/state")]
+#[derive(Serialize, Deserialize)]
+struct TravelQuery {
+    from: i32, // location ids
+    to: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DispositionRequest {
+    disposition: i32, // -100 (hostile) to 100 (devoted)
+}
+
+#[derive(Serialize, Deserialize)]
+struct RelationshipRequest {
+    kind: String, // e.g. "rival", "mentor", "sibling"
+    strength: i32, // -100 to 100
+}
+
+#[derive(Serialize, Deserialize)]
+struct TalkRequest {
+    player_id: i32,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchQuery {
+    q: String,
+    kind: Option<String>, // one of search::KINDS
+    limit: Option<i64>,
+}
+
+#[actix_web::options("/worlds/{world_id}/state")]
 async fn world_state_options() -> impl Responder {
-    info!("Handling /world/state, method: OPTIONS");
+    info!("Handling /worlds/{{world_id}}/state, method: OPTIONS");
     HttpResponse::Ok()
         .insert_header((actix_web::http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "http://127.0.0.1:8081"))
         .insert_header((actix_web::http::header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD, OPTIONS"))
@@ -101,95 +230,322 @@ async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("OK")
 }
 
-async fn get_world_state(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
-    let pool = &data.pool;
+/// Fails with 404 unless `world_id` names an existing campaign.
+async fn require_world(pool: &SqlitePool, world_id: i32) -> Result<(), Error> {
+    let exists = db::world_exists(pool, world_id).await.map_err(|e| {
+        log::error!("Failed to look up world {}: {}", world_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to look up world")
+    })?;
+    if !exists {
+        return Err(actix_web::error::ErrorNotFound(format!("World {} not found", world_id)));
+    }
+    Ok(())
+}
 
-    let world = sqlx::query_as::<_, World>("SELECT * FROM world WHERE id = 1")
-        .fetch_one(pool)
-        .await
-        .map_err(|e| {
-            log::error!("Failed to fetch world: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to fetch world state")
-        })?;
+/// Maps a failed insert or update to 409 when it broke a uniqueness rule (e.g. a
+/// duplicate name within the world) and to 500 otherwise.
+fn write_error(e: sqlx::Error, what: &str) -> Error {
+    if e.as_database_error().is_some_and(|d| d.is_unique_violation()) {
+        return actix_web::error::ErrorConflict(format!("{} already exists", what));
+    }
+    log::error!("Failed to write {}: {}", what, e);
+    actix_web::error::ErrorInternalServerError(format!("Failed to write {}", what))
+}
 
-    let player = sqlx::query_as::<_, Player>("SELECT * FROM player WHERE id = 1")
-        .fetch_one(pool)
-        .await
-        .map_err(|e| {
-            log::error!("Failed to fetch player: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to fetch player state")
-        })?;
+async fn load_state(pool: &SqlitePool, world_id: i32) -> Result<db::WorldState, Error> {
+    db::get_world_state(pool, world_id).await.map_err(|e| {
+        log::error!("Failed to fetch state of world {}: {}", world_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch world state")
+    })
+}
 
-    let locations = sqlx::query_as::<_, Location>("SELECT * FROM locations")
-        .fetch_all(pool)
-        .await
-        .map_err(|e| {
-            log::error!("Failed to fetch locations: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to fetch locations")
-        })?;
+/// Logs the locations that changed hands since `before`.
+async fn log_territory(pool: &SqlitePool, before: &db::WorldState, caused_by: &str) -> Result<(), Error> {
+    territory::log_shifts(pool, before.world.id, before, caused_by).await.map_err(|e| {
+        log::error!("Failed to log territory changes in world {}: {}", before.world.id, e);
+        actix_web::error::ErrorInternalServerError("Failed to log territory changes")
+    })?;
+    Ok(())
+}
 
-    let factions = sqlx::query_as::<_, Faction>("SELECT * FROM factions")
-        .fetch_all(pool)
-        .await
-        .map_err(|e| {
-            log::error!("Failed to fetch factions: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to fetch factions")
-        })?;
+/// Brings quests up to date after a change made outside the game master.
+async fn settle_quests(pool: &SqlitePool, world_id: i32, caused_by: &str) -> Result<(), Error> {
+    quests::settle(pool, world_id, caused_by).await.map_err(|e| {
+        log::error!("Failed to settle quests in world {}: {}", world_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to settle quests")
+    })?;
+    Ok(())
+}
 
-    let npcs = sqlx::query_as::<_, Npc>("SELECT * FROM npcs")
-        .fetch_all(pool)
+/// The one player character or party a request names.
+fn requested_actor(player_id: Option<i32>, party_id: Option<i32>) -> Result<Actor, Error> {
+    match (player_id, party_id) {
+        (Some(player_id), None) => Ok(Actor::Player(player_id)),
+        (None, Some(party_id)) => Ok(Actor::Party(party_id)),
+        _ => Err(actix_web::error::ErrorBadRequest("Exactly one of player_id or party_id is required")),
+    }
+}
+
+async fn load_player(pool: &SqlitePool, world_id: i32, player_id: i32) -> Result<db::Player, Error> {
+    db::get_player(pool, world_id, player_id)
         .await
         .map_err(|e| {
-            log::error!("Failed to fetch npcs: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to fetch npcs")
-        })?;
+            log::error!("Failed to fetch player {}: {}", player_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch player")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("Player {} not found", player_id)))
+}
 
-    let world_state = WorldState {
-        world,
-        player,
-        locations,
-        factions,
-        npcs,
-    };
+async fn list_worlds(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let worlds = db::list_worlds(&data.pool).await.map_err(|e| {
+        log::error!("Failed to list worlds: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to list worlds")
+    })?;
+    Ok(HttpResponse::Ok().json(worlds))
+}
+
+async fn create_world(data: web::Data<AppState>, req: web::Json<CreateWorldRequest>) -> Result<HttpResponse, Error> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("World name must not be empty"));
+    }
+    let seed_name = req.seed.as_deref().unwrap_or("default");
+    let seed = data.seeds.get(seed_name)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("Unknown world seed '{}'", seed_name)))?;
+
+    let world = db::create_world(&data.pool, name, seed_name, seed, &data.classes).await.map_err(|e| {
+        log::error!("Failed to create world {} from seed {}: {}", name, seed_name, e);
+        actix_web::error::ErrorInternalServerError("Failed to create world")
+    })?;
+    info!("Created world {} ('{}') from seed {}", world.id, world.name, seed_name);
+    Ok(HttpResponse::Created().json(world))
+}
+
+async fn get_world_state(data: web::Data<AppState>, path: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let world_state = load_state(&data.pool, world_id).await?;
 
     Ok(HttpResponse::Ok().json(world_state))
 }
 
-async fn update_state(data: web::Data<AppState>, req: web::Json<UpdateStateRequest>) -> Result<HttpResponse, Error> {
+async fn update_state(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<UpdateStateRequest>) -> Result<HttpResponse, Error> {
     let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
 
-    if let Some(reputation) = req.player_reputation {
-        sqlx::query("UPDATE player SET reputation = ? WHERE id = 1")
+    if req.player_reputation.iter().flatten().any(|(_, reputation)| !(-100..=100).contains(reputation)) {
+        return Err(actix_web::error::ErrorBadRequest("player_reputation must be between -100 and 100"));
+    }
+    if req.faction_power.iter().flatten().any(|(_, power)| !(0..=100).contains(power)) {
+        return Err(actix_web::error::ErrorBadRequest("faction power must be between 0 and 100"));
+    }
+
+    let unknown_item = req.changes.iter().flat_map(|c| c.paths()).find_map(|p| match &p.entity {
+        Entity::Item(_, item) if data.items.get(item).is_none() => Some(item),
+        _ => None,
+    });
+    if let Some(item) = unknown_item {
+        return Err(actix_web::error::ErrorBadRequest(format!("Unknown item '{}'", item)));
+    }
+
+    // Everything below lands together or not at all, so a rejected change leaves no half-applied update
+    let before = load_state(pool, world_id).await?;
+    let mut tx = pool.begin().await.map_err(|e| {
+        log::error!("Failed to start state update: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update state")
+    })?;
+    for (player_id, reputation) in req.player_reputation.iter().flatten() {
+        sqlx::query("UPDATE players SET reputation = ? WHERE id = ? AND world_id = ?")
             .bind(reputation)
-            .execute(pool)
+            .bind(player_id)
+            .bind(world_id)
+            .execute(&mut *tx)
             .await
             .map_err(|e| {
-                log::error!("Failed to update player reputation: {}", e);
+                log::error!("Failed to update player {} reputation: {}", player_id, e);
                 actix_web::error::ErrorInternalServerError("Failed to update player")
             })?;
     }
+    for (faction_id, power) in req.faction_power.iter().flatten() {
+        sqlx::query("UPDATE factions SET power = ? WHERE id = ? AND world_id = ?")
+            .bind(power)
+            .bind(faction_id)
+            .bind(world_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to update faction {} power: {}", faction_id, e);
+                actix_web::error::ErrorInternalServerError("Failed to update faction")
+            })?;
+    }
+    let applied = match &req.changes {
+        Some(changes) => changes.apply_in(&mut tx, world_id).await.map_err(|e| match e {
+            StateChangeError::Database(e) => {
+                log::error!("Failed to apply state changes: {}", e);
+                actix_web::error::ErrorInternalServerError("Failed to apply state changes")
+            }
+            e => actix_web::error::ErrorBadRequest(e.to_string()),
+        })?,
+        None => Vec::new(),
+    };
+    tx.commit().await.map_err(|e| {
+        log::error!("Failed to commit state update: {}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update state")
+    })?;
+    for change in applied {
+        info!("Applied {}: {} -> {}", change.path, change.before, change.after);
+    }
+    if req.changes.is_some() {
+        log_territory(pool, &before, "System").await?;
+    }
+    settle_quests(pool, world_id, "System").await?;
+
+    Ok(HttpResponse::Ok().body("State updated"))
+}
+
+async fn player_action(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<PlayerActionRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let actor = requested_actor(req.player_id, req.party_id)?;
 
-    if let Some(faction_powers) = &req.faction_power {
-        for (faction_id, power) in faction_powers {
-            sqlx::query("UPDATE factions SET power = ? WHERE id = ?")
-                .bind(power)
-                .bind(faction_id)
-                .execute(pool)
-                .await
-                .map_err(|e| {
-                    log::error!("Failed to update faction {} power: {}", faction_id, e);
-                    actix_web::error::ErrorInternalServerError("Failed to update faction")
-                })?;
+    let state_change = serde_json::json!({
+        "action": req.action,
+        "target": req.target,
+        "other": req.other,
+        "value": req.value.unwrap_or(1),
+        "caused_by": "Player",
+    });
+    let response = data.game_master.update_world(pool, world_id, actor, state_change).await.map_err(|e| match e {
+        GameError::Invalid(e) => actix_web::error::ErrorBadRequest(e),
+        GameError::Database(e) => {
+            log::error!("Failed to apply player action {}: {}", req.action, e);
+            actix_web::error::ErrorInternalServerError("Failed to apply player action")
         }
+    })?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn create_player(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<CreatePlayerRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let name = req.name.trim();
+    let reputation = req.reputation.unwrap_or(0);
+    let coins = req.coins.unwrap_or(items::STARTING_COINS);
+    if name.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Player name must not be empty"));
+    }
+    if !(-100..=100).contains(&reputation) {
+        return Err(actix_web::error::ErrorBadRequest("reputation must be between -100 and 100"));
+    }
+    if !(0..=items::MAX_COINS).contains(&i64::from(coins)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("coins must be between 0 and {}", items::MAX_COINS)));
     }
+    let state = load_state(pool, world_id).await?;
+    if !state.locations.iter().any(|l| l.id == req.location_id) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No location with id {} in world {}", req.location_id, world_id)));
+    }
+    if let Some(party_id) = req.party_id.filter(|id| !state.parties.iter().any(|p| p.id == *id)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No party with id {} in world {}", party_id, world_id)));
+    }
+    let class = req.class.as_deref().unwrap_or(progression::DEFAULT_CLASS);
+    let sheet = data.classes.sheet(class, req.background.as_deref()).map_err(actix_web::error::ErrorBadRequest)?;
 
-    Ok(HttpResponse::Ok().body("State updated"))
+    let new = db::NewPlayer { name, location_id: req.location_id, reputation, coins, party_id: req.party_id, sheet };
+    let player = db::create_player(pool, world_id, &new)
+        .await
+        .map_err(|e| write_error(e, &format!("Player '{}'", name)))?;
+    info!("Created player {} ('{}') in world {}", player.id, player.name, world_id);
+    Ok(HttpResponse::Created().json(player))
+}
+
+async fn get_player(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let (world_id, player_id) = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let player = load_player(&data.pool, world_id, player_id).await?;
+    Ok(HttpResponse::Ok().json(player))
+}
+
+async fn set_player_party(data: web::Data<AppState>, path: web::Path<(i32, i32)>, req: web::Json<SetPartyRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, player_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let state = load_state(pool, world_id).await?;
+    if !state.players.iter().any(|p| p.id == player_id) {
+        return Err(actix_web::error::ErrorNotFound(format!("Player {} not found", player_id)));
+    }
+    if let Some(party_id) = req.party_id.filter(|id| !state.parties.iter().any(|p| p.id == *id)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No party with id {} in world {}", party_id, world_id)));
+    }
+
+    db::set_party(pool, world_id, player_id, req.party_id).await.map_err(|e| write_error(e, "Party membership"))?;
+    Ok(HttpResponse::Ok().body("Party updated"))
 }
 
-async fn get_events(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+async fn adjust_inventory(data: web::Data<AppState>, path: web::Path<(i32, i32)>, req: web::Json<InventoryRequest>) -> Result<HttpResponse, Error> {
     let pool = &data.pool;
+    let (world_id, player_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let item = req.item.trim();
+    if item.is_empty() || req.quantity == 0 {
+        return Err(actix_web::error::ErrorBadRequest("item must be named and quantity must not be zero"));
+    }
+    // Items from before the catalogue can still be taken away, but nothing unknown is handed out
+    if req.quantity > 0 && data.items.get(item).is_none() {
+        return Err(actix_web::error::ErrorBadRequest(format!("Unknown item '{}'", item)));
+    }
+    let player = load_player(pool, world_id, player_id).await?;
+    if player.inventory.iter().find(|i| i.item == item).map_or(0, |i| i.quantity) as i64 + req.quantity as i64 > items::MAX_STACK {
+        return Err(actix_web::error::ErrorBadRequest(format!("Nobody can carry more than {} x {}", items::MAX_STACK, item)));
+    }
+
+    db::adjust_inventory(pool, player.id, item, req.quantity)
+        .await
+        .map_err(|e| write_error(e, "Inventory"))?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("{} does not carry {} x {}", player.name, -req.quantity, item)))?;
+
+    let player = load_player(pool, world_id, player_id).await?;
+    Ok(HttpResponse::Ok().json(player))
+}
+
+async fn create_party(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<CreatePartyRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Party name must not be empty"));
+    }
+    let member_ids = req.member_ids.clone().unwrap_or_default();
+    let state = load_state(pool, world_id).await?;
+    if let Some(missing) = member_ids.iter().find(|id| !state.players.iter().any(|p| p.id == **id)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No player with id {} in world {}", missing, world_id)));
+    }
+
+    let party = db::create_party(pool, world_id, name, &member_ids)
+        .await
+        .map_err(|e| write_error(e, &format!("Party '{}'", name)))?;
+    info!("Created party {} ('{}') in world {} with {} members", party.id, party.name, world_id, member_ids.len());
+    Ok(HttpResponse::Created().json(party))
+}
 
-    let events = sqlx::query_as::<_, Event>("SELECT * FROM events ORDER BY created_at DESC")
+async fn get_events(data: web::Data<AppState>, path: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let events = sqlx::query_as::<_, Event>("SELECT * FROM events WHERE world_id = ? ORDER BY created_at DESC")
+        .bind(world_id)
         .fetch_all(pool)
         .await
         .map_err(|e| {
@@ -200,111 +556,46 @@ async fn get_events(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
     Ok(HttpResponse::Ok().json(events))
 }
 
-async fn generate_story_event(data: web::Data<AppState>, req: web::Json<GenerateEventRequest>) -> Result<HttpResponse, Error> {
+async fn generate_story_event(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<GenerateEventRequest>) -> Result<HttpResponse, Error> {
     let pool = &data.pool;
-    let client = &data.client;
-
-    // Call Grok API for narrative
-    let grok_api_key = env::var("GROK_API_KEY").map_err(|_| {
-        log::error!("GROK_API_KEY not set");
-        actix_web::error::ErrorInternalServerError("Missing GROK_API_KEY")
-    })?;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
 
-    let mut description = "A mysterious event occurred.".to_string();
-    
-    let grok_response = client
-        .post("https://api.x.ai/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", grok_api_key))
-        .json(&serde_json::json!({
-            "model": "grok-3",
-            "messages": [{
-                "role": "user",
-                "content": format!("Generate a sci-fi story event based on: {}. Keep it concise, under 100 words.", req.context)
-            }]
-        }))
-        .send()
-        .await;
-
-    match grok_response {
-        Ok(resp) => {
-            let grok_status = resp.status();
-            info!("Grok API response status: {}", grok_status);
-            if grok_status.is_success() {
-                let grok_json: serde_json::Value = resp
-                    .json()
-                    .await
-                    .map_err(|e| {
-                        log::error!("Failed to parse Grok response: {}", e);
-                        actix_web::error::ErrorInternalServerError("Failed to parse Grok response")
-                    })?;
-                description = grok_json["choices"][0]["message"]["content"]
-                    .as_str()
-                    .unwrap_or("A mysterious event occurred.")
-                    .to_string();
-            } else {
-                let error_text = resp.text().await.unwrap_or("Unknown error".to_string());
-                log::warn!("Grok API failed (status {}), using fallback description: {}", grok_status, error_text);
-            }
-        }
-        Err(e) => {
-            log::warn!("Grok API request failed, using fallback description: {}", e);
-        }
+    let world_state = load_state(pool, world_id).await?;
+    if let Some(player_id) = req.player_id.filter(|id| !world_state.players.iter().any(|p| p.id == *id)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No player with id {} in world {}", player_id, world_id)));
     }
+    let context = world_context(&data, &world_state, req.player_id, &req.context).await?;
 
-    // Call Stability AI API for pixel art
-    let stability_api_key = env::var("STABILITY_API_KEY").map_err(|_| {
-        log::error!("STABILITY_API_KEY not set");
-        actix_web::error::ErrorInternalServerError("Missing STABILITY_API_KEY")
-    })?;
+    // Ask the configured narrative provider, keeping the event even if it fails
+    let prompt = format!(
+        "{}\n\nGenerate a sci-fi story event based on: {}. Keep it consistent with the world above, concise, under 100 words.",
+        context, req.context
+    );
+    let description = narrate_or(data.narrative.as_ref(), PromptKind::StoryEvent, &prompt, || "A mysterious event occurred.".to_string()).await;
 
-    let image_response = client
-        .post("https://api.stability.ai/v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image")
-        .header("Authorization", format!("Bearer {}", stability_api_key))
-        .header("Accept", "image/png")
-        .json(&serde_json::json!({
-            "text_prompts": [{
-                "text": format!("16-bit pixel art of {}, retro style, vibrant colors, sharp edges", req.context),
-                "weight": 1
-            }],
-            "cfg_scale": 7,
-            "height": 1024,
-            "width": 1024,
-            "samples": 1,
-            "steps": 30
-        }))
-        .send()
-        .await;
-
-    let image_data = match image_response {
-        Ok(resp) => {
-            let status = resp.status();
-            info!("Stability AI response status: {}", status);
-            if !status.is_success() {
-                let error_text = resp.text().await.unwrap_or("Unknown error".to_string());
-                log::error!("Stability AI failed: {}", error_text);
-                return Err(actix_web::error::ErrorInternalServerError(format!("Stability AI failed: {}", error_text)));
-            }
-            let bytes = resp.bytes().await.map_err(|e| {
-                log::error!("Failed to read image data: {}", e);
-                actix_web::error::ErrorInternalServerError("Failed to read image data")
-            })?;
-            info!("Received image data: {} bytes", bytes.len());
-            if bytes.is_empty() || bytes.len() < 1000 {
-                log::error!("Invalid image data: {} bytes", bytes.len());
-                return Err(actix_web::error::ErrorInternalServerError("Invalid image data"));
-            }
-            bytes.to_vec()
-        }
-        Err(e) => {
-            log::error!("Stability AI request failed: {}", e);
-            return Err(actix_web::error::ErrorInternalServerError(format!("Stability AI request failed: {}", e)));
-        }
-    };
+    // Render the event image; backends fall back to a placeholder rather than losing the event
+    let (prosperity, safety) = req.player_id
+        .and_then(|id| world_state.players.iter().find(|p| p.id == id))
+        .or(world_state.players.first())
+        .and_then(|player| world_state.locations.iter().find(|l| l.id == player.location_id))
+        .map(|l| (l.prosperity, l.safety))
+        .unwrap_or((50, 50));
+
+    let image_request = imagery::ImageRequest { prompt: &req.context, prosperity, safety };
+    let image_data = imagery::render_or_placeholder(data.images.as_ref(), &image_request)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to render event image: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to render event image")
+        })?;
+    info!("Rendered event image: {} bytes", image_data.len());
 
     // Store event and image in database
     let event = sqlx::query_as::<_, Event>(
-        "INSERT INTO events (world_id, description) VALUES (1, ?) RETURNING *"
+        "INSERT INTO events (world_id, description) VALUES (?, ?) RETURNING *"
     )
+    .bind(world_id)
     .bind(&description)
     .fetch_one(pool)
     .await
@@ -325,101 +616,775 @@ async fn generate_story_event(data: web::Data<AppState>, req: web::Json<Generate
 
     info!("Stored event {} with image of {} bytes", event.id, image_data.len());
 
+    // Fold the event into the story so far; the event stands even if this fails
+    if let Err(e) = memory::record(pool, data.narrative.as_ref(), world_id, event.id, &description).await {
+        log::error!("Failed to update story summary for world {}: {}", world_id, e);
+    }
+
+    // Settle any choices left hanging, then add the event to the story graph
+    story::resolve_expired(pool, world_id).await.map_err(story_error)?;
+    story::record_event(pool, world_id, Some(event.id), &description).await.map_err(story_error)?;
+
     Ok(HttpResponse::Ok().json(event))
 }
 
-async fn get_branch_choices(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
-    let client = &data.client;
+/// The world summary that opens every narrative prompt, see [`PromptContext`].
+async fn world_context(data: &AppState, state: &db::WorldState, focus: Option<i32>, topic: &str) -> Result<String, Error> {
+    let recent = db::recent_events(&data.pool, state.world.id, data.prompt_context.recent_events)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch recent events for world {}: {}", state.world.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch recent events")
+        })?;
+    let story_so_far = memory::summary(&data.pool, state.world.id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch story summary for world {}: {}", state.world.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch story summary")
+        })?
+        .map(|s| s.summary);
 
-    // Fetch and deserialize world state
-    let world_state_response = get_world_state(data.clone()).await?;
-    let world_state: WorldState = serde_json::from_slice(
-        &to_bytes(world_state_response.into_body())
-            .await
-            .map_err(|e| {
-                log::error!("Failed to read response body: {}", e);
-                actix_web::error::ErrorInternalServerError("Failed to read response body")
+    // Look up older events about the topic and about where the focus player is and who is there
+    let mut about = vec![topic.to_string()];
+    let here = focus
+        .and_then(|id| state.players.iter().find(|p| p.id == id))
+        .or(state.players.first())
+        .map(|p| p.location_id);
+    if let Some(here) = here {
+        about.extend(state.locations.iter().filter(|l| l.id == here).map(|l| l.name.clone()));
+        about.extend(state.npcs.iter().filter(|n| n.location_id == here).map(|n| n.name.clone()));
+    }
+    let related = search::related_events(&data.pool, state.world.id, &about.join(" "), &recent, data.prompt_context.related_events)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to search past events for world {}: {}", state.world.id, e);
+            actix_web::error::ErrorInternalServerError("Failed to search past events")
+        })?;
+
+    let context = data.prompt_context.build(state, focus, &Recall { story_so_far, recent, related });
+    info!("Prompt context for world {}: ~{} tokens", state.world.id, prompt_context::estimate_tokens(&context));
+    log::debug!("Prompt context for world {}:\n{}", state.world.id, context);
+    Ok(context)
+}
+
+fn story_error(e: StoryError) -> Error {
+    match e {
+        StoryError::NotFound(e) => actix_web::error::ErrorNotFound(e),
+        StoryError::Conflict(e) => actix_web::error::ErrorConflict(e),
+        StoryError::Invalid(e) => actix_web::error::ErrorBadRequest(e),
+        StoryError::Database(e) => {
+            log::error!("Story graph query failed: {}", e);
+            actix_web::error::ErrorInternalServerError("Failed to access story graph")
+        }
+    }
+}
+
+async fn get_branch_choices(data: web::Data<AppState>, path: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    story::resolve_expired(pool, world_id).await.map_err(story_error)?;
+    let parent = story::latest_event(pool, world_id)
+        .await
+        .map_err(story_error)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("No story event to branch from yet"))?;
+
+    // Choices are generated once per event and then kept, so the graph stays stable
+    let existing = story::choices_for(pool, parent.id).await.map_err(story_error)?;
+    if !existing.is_empty() {
+        return Ok(HttpResponse::Ok().json(existing));
+    }
+
+    let world_state = load_state(pool, world_id).await?;
+    let context = world_context(&data, &world_state, None, &parent.narrative).await?;
+
+    // Ask the configured narrative provider for choices, telling it what was wrong with
+    // any reply that does not validate, and fall back to stock choices after that
+    let mut prompt = format!(
+        "{}\n\nThe latest story event: {}. Propose 3 player decisions for what happens next.\n{}",
+        context, parent.narrative, choices::reply_format(&world_state, &data.items)
+    );
+    let mut choices = None;
+    for attempt in 0..=data.choice_retries {
+        let problem = match data.narrative.complete(PromptKind::BranchChoices, &prompt).await {
+            Ok(reply) => match choices::parse_reply(&reply, &world_state, &data.items) {
+                Ok(parsed) => {
+                    choices = Some(parsed);
+                    break;
+                }
+                Err(e) => {
+                    prompt = format!("{}\n\nYour previous reply was rejected: {}. Reply with the JSON array only.", prompt, e);
+                    e
+                }
+            },
+            Err(e) => e.to_string(),
+        };
+        log::warn!("Narrative provider {} gave no usable choices (attempt {}): {}", data.narrative.name(), attempt + 1, problem);
+    }
+    let choices = match choices {
+        Some(choices) => choices,
+        None => {
+            log::warn!("Using template choices for story event {}", parent.id);
+            data.game_master.fallback_choices(&world_state).map_err(|e| match e {
+                GameError::Invalid(e) => actix_web::error::ErrorConflict(e),
+                GameError::Database(e) => {
+                    log::error!("Failed to build fallback choices: {}", e);
+                    actix_web::error::ErrorInternalServerError("Failed to build branch choices")
+                }
             })?
-    ).map_err(|e| {
-        log::error!("Failed to deserialize world state: {}", e);
-        actix_web::error::ErrorInternalServerError("Failed to deserialize world state")
-    })?;
+        }
+    };
+
+    let stored = story::store_choices(pool, parent.id, &choices, data.choice_timeout_secs)
+        .await
+        .map_err(story_error)?;
+
+    Ok(HttpResponse::Ok().json(stored))
+}
+
+async fn resolve_branch_choice(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let (world_id, choice_id) = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+    let before = load_state(&data.pool, world_id).await?;
+    let resolution = story::resolve_choice(&data.pool, world_id, choice_id).await.map_err(story_error)?;
+    log_territory(&data.pool, &before, "Player").await?;
+    settle_quests(&data.pool, world_id, "Player").await?;
+    info!("Resolved story event {} with choice {} ({:?})", resolution.choice.event_id, resolution.choice.id, resolution.choice.resolved_by);
+    Ok(HttpResponse::Ok().json(resolution))
+}
+
+async fn get_story_summary(data: web::Data<AppState>, path: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+    let summary = memory::summary(&data.pool, world_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch story summary for world {}: {}", world_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch story summary")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Nothing has happened in this world yet"))?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+async fn set_faction_relation(data: web::Data<AppState>, path: web::Path<(i32, i32, i32)>, req: web::Json<FactionRelationRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, faction_id, other_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    if !(-100..=100).contains(&req.standing) {
+        return Err(actix_web::error::ErrorBadRequest("standing must be between -100 and 100"));
+    }
+    let state = load_state(pool, world_id).await?;
+    if state.relation_between(faction_id, other_id).is_none() {
+        return Err(actix_web::error::ErrorNotFound(format!("No factions {} and {} in world {}", faction_id, other_id, world_id)));
+    }
+    db::set_faction_relation(pool, faction_id, other_id, req.standing, req.pact).await.map_err(|e| write_error(e, "Faction relation"))?;
+    Ok(HttpResponse::Ok().body("Faction relation updated"))
+}
+
+async fn set_route(data: web::Data<AppState>, path: web::Path<(i32, i32, i32)>, req: web::Json<RouteRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, location_id, other_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    if req.travel_hours < 1 || !(0..=100).contains(&req.danger) {
+        return Err(actix_web::error::ErrorBadRequest("travel_hours must be at least 1 and danger between 0 and 100"));
+    }
+    if location_id == other_id {
+        return Err(actix_web::error::ErrorBadRequest("A route needs two different locations"));
+    }
+    let state = load_state(pool, world_id).await?;
+    if let Some(id) = [location_id, other_id].into_iter().find(|id| !state.locations.iter().any(|l| l.id == *id)) {
+        return Err(actix_web::error::ErrorNotFound(format!("No location with id {} in world {}", id, world_id)));
+    }
+    db::set_route(pool, location_id, other_id, req.travel_hours, req.danger).await.map_err(|e| write_error(e, "Route"))?;
+    Ok(HttpResponse::Ok().body("Route updated"))
+}
+
+async fn delete_route(data: web::Data<AppState>, path: web::Path<(i32, i32, i32)>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, location_id, other_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let state = load_state(pool, world_id).await?;
+    if state.route_between(location_id, other_id).is_none() {
+        return Err(actix_web::error::ErrorNotFound(format!("No route between locations {} and {} in world {}", location_id, other_id, world_id)));
+    }
+    db::delete_route(pool, location_id, other_id).await.map_err(|e| write_error(e, "Route"))?;
+    Ok(HttpResponse::Ok().body("Route removed"))
+}
+
+async fn set_influence(data: web::Data<AppState>, path: web::Path<(i32, i32, i32)>, req: web::Json<InfluenceRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, location_id, faction_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    if !(0..=100).contains(&req.influence) {
+        return Err(actix_web::error::ErrorBadRequest("influence must be between 0 and 100"));
+    }
+    let state = load_state(pool, world_id).await?;
+    if !state.locations.iter().any(|l| l.id == location_id) {
+        return Err(actix_web::error::ErrorNotFound(format!("No location with id {} in world {}", location_id, world_id)));
+    }
+    if !state.factions.iter().any(|f| f.id == faction_id) {
+        return Err(actix_web::error::ErrorNotFound(format!("No faction with id {} in world {}", faction_id, world_id)));
+    }
+    db::set_influence(pool, location_id, faction_id, req.influence).await.map_err(|e| write_error(e, "Influence"))?;
+    log_territory(pool, &state, "System").await?;
+    Ok(HttpResponse::Ok().body("Influence updated"))
+}
+
+async fn list_quests(data: web::Data<AppState>, path: web::Path<i32>, query: web::Query<QuestQuery>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let state = load_state(&data.pool, world_id).await?;
+    let quests: Vec<&db::Quest> = state.quests.iter().filter(|q| query.status.is_none_or(|s| q.status == s)).collect();
+    Ok(HttpResponse::Ok().json(quests))
+}
+
+async fn get_quest(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let (world_id, quest_id) = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let state = load_state(&data.pool, world_id).await?;
+    let quest = state.quests.iter().find(|q| q.id == quest_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("Quest {} not found", quest_id)))?;
+    Ok(HttpResponse::Ok().json(quest))
+}
 
-    let world_state_json = serde_json::to_string(&world_state).map_err(|e| {
-        log::error!("Failed to serialize world state: {}", e);
-        actix_web::error::ErrorInternalServerError("Failed to serialize world state")
+/// The name and characters of `actor`, who must exist in `state`.
+fn cast_actor(state: &db::WorldState, actor: Actor) -> Result<(String, Vec<&db::Player>), Error> {
+    actor.cast(state).map_err(|e| match e {
+        GameError::Invalid(e) => actix_web::error::ErrorBadRequest(e),
+        GameError::Database(e) => {
+            log::error!("Failed to look up {:?}: {}", actor, e);
+            actix_web::error::ErrorInternalServerError("Failed to look up player characters")
+        }
+    })
+}
+
+async fn create_quest(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<CreateQuestRequest>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let actor = requested_actor(req.player_id, req.party_id)?;
+    let state = load_state(&data.pool, world_id).await?;
+    let (name, cast) = cast_actor(&state, actor)?;
+    let quest = quests::check(&req.quest, &state, &cast, &data.items).map_err(actix_web::error::ErrorBadRequest)?;
+    store_quest(&data, world_id, actor, &name, &quest).await
+}
+
+async fn generate_quest(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<GenerateQuestRequest>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let actor = requested_actor(req.player_id, req.party_id)?;
+    let state = load_state(&data.pool, world_id).await?;
+    let (name, cast) = cast_actor(&state, actor)?;
+    let context = world_context(&data, &state, Some(cast[0].id), "quest").await?;
+
+    // Same deal as branch choices: explain rejected replies, then fall back to a stock quest
+    let mut prompt = format!(
+        "{}\n\nPropose one quest for {} that grows out of the situation above.\n{}",
+        context, name, quests::reply_format(&state, &data.items)
+    );
+    let mut quest = None;
+    for attempt in 0..=data.choice_retries {
+        let problem = match data.narrative.complete(PromptKind::Quest, &prompt).await {
+            Ok(reply) => match quests::parse_reply(&reply, &state, &cast, &data.items) {
+                Ok(parsed) => {
+                    quest = Some(parsed);
+                    break;
+                }
+                Err(e) => {
+                    prompt = format!("{}\n\nYour previous reply was rejected: {}. Reply with the JSON object only.", prompt, e);
+                    e
+                }
+            },
+            Err(e) => e.to_string(),
+        };
+        log::warn!("Narrative provider {} gave no usable quest (attempt {}): {}", data.narrative.name(), attempt + 1, problem);
+    }
+    let quest = match quest {
+        Some(quest) => quest,
+        None => {
+            log::warn!("Using a stock quest for {} in world {}", name, world_id);
+            quests::fallback(&state, &cast).ok_or_else(|| actix_web::error::ErrorConflict("There is no quest to be had here right now"))?
+        }
+    };
+    store_quest(&data, world_id, actor, &name, &quest).await
+}
+
+/// Stores a checked quest for `actor` (called `name`) and returns it as created.
+async fn store_quest(data: &AppState, world_id: i32, actor: Actor, name: &str, quest: &db::NewQuest) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (player_id, party_id) = match actor {
+        Actor::Player(id) => (Some(id), None),
+        Actor::Party(id) => (None, Some(id)),
+    };
+    let quest_id = db::create_quest(pool, world_id, player_id, party_id, quest).await.map_err(|e| write_error(e, "Quest"))?;
+    db::log_event(pool, world_id, &format!("{} took up the quest '{}'", name, quest.title), "Player").await.map_err(|e| {
+        log::error!("Failed to log quest {}: {}", quest_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to log quest")
     })?;
+    info!("Created quest {} ('{}') in world {}", quest_id, quest.title, world_id);
+
+    let state = load_state(pool, world_id).await?;
+    let quest = state.quests.iter().find(|q| q.id == quest_id)
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Quest vanished after creation"))?;
+    Ok(HttpResponse::Created().json(quest))
+}
+
+async fn abandon_quest(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, quest_id) = path.into_inner();
+    require_world(pool, world_id).await?;
 
-    // Call Grok API for choices
-    let grok_api_key = env::var("GROK_API_KEY").map_err(|_| {
-        log::error!("GROK_API_KEY not set");
-        actix_web::error::ErrorInternalServerError("Missing GROK_API_KEY")
+    let state = load_state(pool, world_id).await?;
+    let quest = state.quests.iter().find(|q| q.id == quest_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("Quest {} not found", quest_id)))?;
+    let abandoned = db::finish_quest(pool, quest_id, QuestStatus::Failed).await.map_err(|e| write_error(e, "Quest"))?;
+    if !abandoned {
+        return Err(actix_web::error::ErrorConflict(format!("Quest '{}' is already {}", quest.title, quest.status.as_str())));
+    }
+    let (name, _) = quests::holders(&state, quest);
+    db::log_event(pool, world_id, &format!("{} abandoned the quest '{}'", name, quest.title), "Player").await.map_err(|e| {
+        log::error!("Failed to log quest {}: {}", quest_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to log quest")
     })?;
+    Ok(HttpResponse::Ok().body("Quest abandoned"))
+}
 
-    let grok_response = client
-        .post("https://api.x.ai/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", grok_api_key))
-        .json(&serde_json::json!({
-            "model": "grok-3",
-            "messages": [{
-                "role": "user",
-                "content": format!("Based on this world state: {}. Generate 3 concise player decision options (each under 20 words) for the next story event.", world_state_json)
-            }]
-        }))
-        .send()
+async fn load_encounter(pool: &SqlitePool, world_id: i32, encounter_id: i32) -> Result<db::Encounter, Error> {
+    db::get_encounter(pool, world_id, encounter_id)
         .await
         .map_err(|e| {
-            log::error!("Grok API request failed: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to call Grok API")
+            log::error!("Failed to fetch encounter {}: {}", encounter_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch encounter")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("Encounter {} not found", encounter_id)))
+}
+
+async fn list_encounters(data: web::Data<AppState>, path: web::Path<i32>, query: web::Query<EncounterQuery>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let encounters = db::list_encounters(&data.pool, world_id, query.status).await.map_err(|e| {
+        log::error!("Failed to fetch encounters of world {}: {}", world_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch encounters")
+    })?;
+    Ok(HttpResponse::Ok().json(encounters))
+}
+
+async fn get_encounter(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let (world_id, encounter_id) = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let encounter = load_encounter(&data.pool, world_id, encounter_id).await?;
+    Ok(HttpResponse::Ok().json(encounter))
+}
+
+async fn create_encounter(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<CreateEncounterRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let actor = requested_actor(req.player_id, req.party_id)?;
+    let foe = match (req.faction_id, req.npc_id) {
+        (Some(faction_id), None) => combat::Foe::Faction(faction_id),
+        (None, Some(npc_id)) => combat::Foe::Npc(npc_id),
+        _ => return Err(actix_web::error::ErrorBadRequest("Exactly one of faction_id or npc_id is required")),
+    };
+    let state = load_state(pool, world_id).await?;
+    let (name, cast) = cast_actor(&state, actor)?;
+    let (location_id, combatants) = combat::muster(&state, &name, &cast, foe, &data.items, &mut rand::thread_rng())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let encounter_id = db::create_encounter(pool, world_id, location_id, req.faction_id, req.npc_id, &combatants)
+        .await
+        .map_err(|e| write_error(e, "Encounter"))?
+        .map_err(|fighting| {
+            let who = cast.iter().find(|p| p.id == fighting.player_id).map_or("A player character", |p| p.name.as_str());
+            actix_web::error::ErrorConflict(format!("{} is already fighting in encounter {}", who, fighting.encounter_id))
         })?;
+    let encounter = load_encounter(pool, world_id, encounter_id).await?;
+    let place = state.locations.iter().find(|l| l.id == location_id).map_or("somewhere unknown", |l| l.name.as_str());
+    db::log_event(pool, world_id, &format!("{} started a fight with {} at {}", name, combat::foe_name(&state, &encounter), place), "Player").await.map_err(|e| {
+        log::error!("Failed to log encounter {}: {}", encounter_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to log encounter")
+    })?;
+    info!("Started encounter {} in world {}", encounter_id, world_id);
+    Ok(HttpResponse::Created().json(encounter))
+}
 
-    let grok_status = grok_response.status();
-    info!("Grok API response status: {}", grok_status);
-    if !grok_status.is_success() {
-        let error_text = grok_response.text().await.unwrap_or("Unknown error".to_string());
-        log::error!("Grok API failed: {}", error_text);
-        return Err(actix_web::error::ErrorInternalServerError(format!("Grok API failed: {}", error_text)));
+async fn act_in_encounter(data: web::Data<AppState>, path: web::Path<(i32, i32)>, req: web::Json<ActRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, encounter_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let mut encounter = load_encounter(pool, world_id, encounter_id).await?;
+    if encounter.status != EncounterStatus::Active {
+        return Err(actix_web::error::ErrorConflict(format!("Encounter {} is already over", encounter_id)));
+    }
+    let state = load_state(pool, world_id).await?;
+    let fought = combat::fight_round(&mut encounter, &req.orders, &mut rand::thread_rng()).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let prompt = combat::narration_prompt(&state, &encounter, &fought.log);
+    let narration = narrate_or(data.narrative.as_ref(), PromptKind::Combat, &prompt, || combat::plain_account(&fought.log)).await;
+    let round = db::Round { round: encounter.round, log: fought.log, narration };
+    let recorded = db::record_round(pool, &encounter, &round).await.map_err(|e| write_error(e, "Encounter round"))?;
+    if !recorded {
+        return Err(actix_web::error::ErrorConflict(format!("Encounter {} moved on in the meantime; fetch it and try again", encounter_id)));
     }
 
-    let grok_json: serde_json::Value = grok_response
-        .json()
+    let aftermath = combat::aftermath(pool, &state, &encounter, &fought.fallen).await.map_err(|e| {
+        log::error!("Failed to apply the aftermath of encounter {}: {}", encounter_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to apply the fight's aftermath")
+    })?;
+    log_territory(pool, &state, "Player").await?;
+    settle_quests(pool, world_id, "Player").await?;
+    Ok(HttpResponse::Ok().json(combat::RoundReport {
+        encounter_id,
+        status: encounter.status,
+        round,
+        combatants: encounter.combatants,
+        aftermath,
+    }))
+}
+
+async fn get_shop(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let (world_id, location_id) = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let state = load_state(&data.pool, world_id).await?;
+    let location = state.locations.iter().find(|l| l.id == location_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("No location with id {} in world {}", location_id, world_id)))?;
+    Ok(HttpResponse::Ok().json(data.items.stock(location)))
+}
+
+async fn buy_item(data: web::Data<AppState>, path: web::Path<(i32, i32)>, req: web::Json<ShopRequest>) -> Result<HttpResponse, Error> {
+    let (world_id, location_id) = path.into_inner();
+    shop_trade(&data, world_id, location_id, &req, true).await
+}
+
+async fn sell_item(data: web::Data<AppState>, path: web::Path<(i32, i32)>, req: web::Json<ShopRequest>) -> Result<HttpResponse, Error> {
+    let (world_id, location_id) = path.into_inner();
+    shop_trade(&data, world_id, location_id, &req, false).await
+}
+
+/// Buys from or sells to the shop at `location_id`, returning the player afterwards.
+/// Shops only sell what they stock but buy anything in the catalogue, at half their price.
+async fn shop_trade(data: &AppState, world_id: i32, location_id: i32, req: &ShopRequest, buying: bool) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    require_world(pool, world_id).await?;
+
+    let quantity = req.quantity.unwrap_or(1);
+    if !(1..=items::MAX_STACK).contains(&i64::from(quantity)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("quantity must be between 1 and {}", items::MAX_STACK)));
+    }
+    let state = load_state(pool, world_id).await?;
+    let location = state.locations.iter().find(|l| l.id == location_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("No location with id {} in world {}", location_id, world_id)))?;
+    let player = state.players.iter().find(|p| p.id == req.player_id)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("No player with id {} in world {}", req.player_id, world_id)))?;
+    if player.location_id != location_id {
+        return Err(actix_web::error::ErrorBadRequest(format!("{} is not at {}", player.name, location.name)));
+    }
+    let item = data.items.get(req.item.trim())
+        .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("Unknown item '{}'", req.item.trim())))?;
+
+    let traded = if buying {
+        if !data.items.stock(location).iter().any(|o| o.item.name == item.name) {
+            return Err(actix_web::error::ErrorBadRequest(format!("Nobody in {} sells {}", location.name, item.name)));
+        }
+        let cost = items::buy_price(item, location) * quantity;
+        db::trade(pool, player.id, &item.name, quantity, -cost).await.map_err(|e| write_error(e, "Purchase"))?
+            .then_some(())
+            .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("{} cannot afford {} x {} for {} coins", player.name, quantity, item.name, cost)))
+    } else {
+        let earned = items::sell_price(item, location) * quantity;
+        db::trade(pool, player.id, &item.name, -quantity, earned).await.map_err(|e| write_error(e, "Sale"))?
+            .then_some(())
+            .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("{} does not carry {} x {}", player.name, quantity, item.name)))
+    };
+    traded?;
+    info!("Player {} {} {} x {} in {}", player.id, if buying { "bought" } else { "sold" }, quantity, item.name, location.name);
+
+    let player = load_player(pool, world_id, player.id).await?;
+    Ok(HttpResponse::Ok().json(player))
+}
+
+async fn plan_travel(data: web::Data<AppState>, path: web::Path<i32>, query: web::Query<TravelQuery>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let state = load_state(pool, world_id).await?;
+    if let Some(id) = [query.from, query.to].into_iter().find(|id| !state.locations.iter().any(|l| l.id == *id)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No location with id {} in world {}", id, world_id)));
+    }
+    let journey = travel::find_path(&state, query.from, query.to)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("No road leads from location {} to {}", query.from, query.to)))?;
+    Ok(HttpResponse::Ok().json(journey))
+}
+
+/// Trims `fields` and checks they only refer to this world.
+fn check_npc_fields(fields: &db::NpcFields, state: &db::WorldState) -> Result<db::NpcFields, Error> {
+    let trimmed = |items: &[String]| items.iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    let fields = db::NpcFields {
+        name: fields.name.trim().to_string(),
+        role: fields.role.trim().to_string(),
+        status: fields.status.trim().to_string(),
+        location_id: fields.location_id,
+        faction_id: fields.faction_id,
+        traits: trimmed(&fields.traits),
+        goals: trimmed(&fields.goals),
+    };
+    if fields.name.is_empty() || fields.role.is_empty() || fields.status.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("NPC name, role and status must not be empty"));
+    }
+    if !state.locations.iter().any(|l| l.id == fields.location_id) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No location with id {} in world {}", fields.location_id, state.world.id)));
+    }
+    if let Some(faction_id) = fields.faction_id.filter(|id| !state.factions.iter().any(|f| f.id == *id)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("No faction with id {} in world {}", faction_id, state.world.id)));
+    }
+    Ok(fields)
+}
+
+fn find_npc(state: &db::WorldState, npc_id: i32) -> Result<&db::Npc, Error> {
+    state.npcs.iter()
+        .find(|n| n.id == npc_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("NPC {} not found", npc_id)))
+}
+
+async fn create_npc(data: web::Data<AppState>, path: web::Path<i32>, req: web::Json<db::NpcFields>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let world_id = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let state = load_state(pool, world_id).await?;
+    let fields = check_npc_fields(&req, &state)?;
+    let npc = db::create_npc(pool, world_id, &fields).await.map_err(|e| write_error(e, &format!("NPC '{}'", fields.name)))?;
+    info!("Created NPC {} ('{}') in world {}", npc.id, npc.name, world_id);
+    Ok(HttpResponse::Created().json(npc))
+}
+
+async fn get_npc(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let (world_id, npc_id) = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+
+    let npc = db::get_npc(&data.pool, world_id, npc_id)
         .await
         .map_err(|e| {
-            log::error!("Failed to parse Grok response: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to parse Grok response")
+            log::error!("Failed to fetch NPC {}: {}", npc_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch NPC")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("NPC {} not found", npc_id)))?;
+    Ok(HttpResponse::Ok().json(npc))
+}
+
+async fn update_npc(data: web::Data<AppState>, path: web::Path<(i32, i32)>, req: web::Json<db::NpcFields>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, npc_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let state = load_state(pool, world_id).await?;
+    find_npc(&state, npc_id)?;
+    let fields = check_npc_fields(&req, &state)?;
+    db::update_npc(pool, world_id, npc_id, &fields).await.map_err(|e| write_error(e, &format!("NPC '{}'", fields.name)))?;
+    settle_quests(pool, world_id, "System").await?;
+    Ok(HttpResponse::Ok().body("NPC updated"))
+}
+
+async fn delete_npc(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, npc_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let state = load_state(pool, world_id).await?;
+    let npc = find_npc(&state, npc_id)?;
+    db::delete_npc(pool, world_id, npc_id).await.map_err(|e| write_error(e, &format!("NPC '{}'", npc.name)))?;
+    info!("Deleted NPC {} ('{}') from world {}", npc.id, npc.name, world_id);
+    settle_quests(pool, world_id, "System").await?;
+    Ok(HttpResponse::Ok().body("NPC deleted"))
+}
+
+async fn set_npc_disposition(data: web::Data<AppState>, path: web::Path<(i32, i32, i32)>, req: web::Json<DispositionRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, npc_id, player_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    if !(-100..=100).contains(&req.disposition) {
+        return Err(actix_web::error::ErrorBadRequest("disposition must be between -100 and 100"));
+    }
+    let state = load_state(pool, world_id).await?;
+    find_npc(&state, npc_id)?;
+    if !state.players.iter().any(|p| p.id == player_id) {
+        return Err(actix_web::error::ErrorNotFound(format!("Player {} not found", player_id)));
+    }
+    db::set_disposition(pool, npc_id, player_id, req.disposition).await.map_err(|e| write_error(e, "Disposition"))?;
+    Ok(HttpResponse::Ok().body("Disposition updated"))
+}
+
+async fn set_npc_relationship(data: web::Data<AppState>, path: web::Path<(i32, i32, i32)>, req: web::Json<RelationshipRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, npc_id, other_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let kind = req.kind.trim();
+    if kind.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Relationship kind must not be empty"));
+    }
+    if !(-100..=100).contains(&req.strength) {
+        return Err(actix_web::error::ErrorBadRequest("strength must be between -100 and 100"));
+    }
+    if npc_id == other_id {
+        return Err(actix_web::error::ErrorBadRequest("An NPC cannot have a relationship with themselves"));
+    }
+    let state = load_state(pool, world_id).await?;
+    find_npc(&state, npc_id)?;
+    find_npc(&state, other_id)?;
+    db::set_relationship(pool, npc_id, other_id, kind, req.strength).await.map_err(|e| write_error(e, "Relationship"))?;
+    Ok(HttpResponse::Ok().body("Relationship updated"))
+}
+
+async fn delete_npc_relationship(data: web::Data<AppState>, path: web::Path<(i32, i32, i32)>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, npc_id, other_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let state = load_state(pool, world_id).await?;
+    find_npc(&state, npc_id)?;
+    let removed = db::delete_relationship(pool, npc_id, other_id).await.map_err(|e| write_error(e, "Relationship"))?;
+    if !removed {
+        return Err(actix_web::error::ErrorNotFound(format!("NPC {} has no relationship with NPC {}", npc_id, other_id)));
+    }
+    Ok(HttpResponse::Ok().body("Relationship removed"))
+}
+
+async fn talk_to_npc(data: web::Data<AppState>, path: web::Path<(i32, i32)>, req: web::Json<TalkRequest>) -> Result<HttpResponse, Error> {
+    let pool = &data.pool;
+    let (world_id, npc_id) = path.into_inner();
+    require_world(pool, world_id).await?;
+
+    let message = req.message.trim();
+    if message.is_empty() || message.chars().count() > dialogue::MAX_MESSAGE_CHARS {
+        return Err(actix_web::error::ErrorBadRequest(format!("message must be 1 to {} characters", dialogue::MAX_MESSAGE_CHARS)));
+    }
+    let world_state = load_state(pool, world_id).await?;
+    let npc = find_npc(&world_state, npc_id)?;
+    let player = world_state.players.iter()
+        .find(|p| p.id == req.player_id)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("No player with id {} in world {}", req.player_id, world_id)))?;
+    if !npc.is_alive() {
+        return Err(actix_web::error::ErrorConflict(format!("{} is dead", npc.name)));
+    }
+    if npc.location_id != player.location_id {
+        return Err(actix_web::error::ErrorConflict(format!("{} is not where {} is", npc.name, player.name)));
+    }
+
+    let context = world_context(&data, &world_state, Some(player.id), &format!("{} {}", npc.name, message)).await?;
+    let prompt = dialogue::prompt(pool, &world_state, &context, npc, player, message)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch memories of NPC {}: {}", npc_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch NPC memories")
         })?;
+    let reply = narrate_or(data.narrative.as_ref(), PromptKind::Dialogue, &prompt, || format!("{} looks at you and says nothing.", npc.name)).await;
 
-    let choices_text = grok_json["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("1. Explore ruins.\n2. Negotiate peace.\n3. Attack bandits.")
-        .split('\n')
-        .enumerate()
-        .map(|(i, desc)| BranchChoice {
-            id: (i + 1) as i32,
-            description: desc.trim().to_string(),
-        })
-        .collect::<Vec<BranchChoice>>();
+    let memory = dialogue::remember(pool, npc.id, player, message, &reply)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to store conversation with NPC {}: {}", npc_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to store conversation")
+        })?;
+    quests::record(pool, &world_state, &[player], quests::Deed::Talked(npc.id)).await.map_err(|e| {
+        log::error!("Failed to record conversation with NPC {} for quests: {}", npc_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to update quests")
+    })?;
+    settle_quests(pool, world_id, "Player").await?;
+    Ok(HttpResponse::Ok().json(memory))
+}
+
+async fn get_npc_memories(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
+    let (world_id, npc_id) = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+    let world_state = load_state(&data.pool, world_id).await?;
+    find_npc(&world_state, npc_id)?;
+    let memories = dialogue::memories(&data.pool, npc_id, i64::MAX)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch memories of NPC {}: {}", npc_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch NPC memories")
+        })?;
+    Ok(HttpResponse::Ok().json(memories))
+}
+
+async fn tick_world(data: web::Data<AppState>, path: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+    let report = simulation::tick(&data.pool, world_id).await.map_err(|e| match e {
+        GameError::Invalid(e) => actix_web::error::ErrorBadRequest(e),
+        GameError::Database(e) => {
+            log::error!("Failed to tick world {}: {}", world_id, e);
+            actix_web::error::ErrorInternalServerError("Failed to advance the world")
+        }
+    })?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+async fn search_events(data: web::Data<AppState>, path: web::Path<i32>, query: web::Query<SearchQuery>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+    let kind = query.kind.as_deref();
+    if let Some(kind) = kind.filter(|k| !search::KINDS.contains(k)) {
+        return Err(actix_web::error::ErrorBadRequest(format!("Unknown kind '{}', expected one of {}", kind, search::KINDS.join(", "))));
+    }
+    let terms = search::fts_query(&query.q, search::Match::All)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("q needs at least one word to search for"))?;
+    let hits = search::search(&data.pool, world_id, &terms, kind, query.limit.unwrap_or(20).clamp(1, 100))
+        .await
+        .map_err(|e| {
+            log::error!("Failed to search world {} for '{}': {}", world_id, query.q, e);
+            actix_web::error::ErrorInternalServerError("Failed to search events")
+        })?;
+    Ok(HttpResponse::Ok().json(hits))
+}
 
-    Ok(HttpResponse::Ok().json(choices_text))
+async fn get_story_graph(data: web::Data<AppState>, path: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let world_id = path.into_inner();
+    require_world(&data.pool, world_id).await?;
+    let graph = story::graph(&data.pool, world_id).await.map_err(story_error)?;
+    Ok(HttpResponse::Ok().json(graph))
 }
 
-async fn get_event_image(data: web::Data<AppState>, path: web::Path<i32>) -> Result<HttpResponse, Error> {
+async fn get_event_image(data: web::Data<AppState>, path: web::Path<(i32, i32)>) -> Result<HttpResponse, Error> {
     let pool = &data.pool;
-    let event_id = path.into_inner();
+    let (world_id, event_id) = path.into_inner();
+    require_world(pool, world_id).await?;
 
-    let image_data = sqlx::query("SELECT image_data FROM event_images WHERE event_id = ?")
+    let image_data = sqlx::query(
+        "SELECT i.image_data FROM event_images i JOIN events e ON e.id = i.event_id WHERE i.event_id = ? AND e.world_id = ?"
+    )
         .bind(event_id)
+        .bind(world_id)
         .fetch_one(pool)
         .await
-        .map_err(|e| {
-            log::error!("Failed to fetch image for event {}: {}", event_id, e);
-            actix_web::error::ErrorNotFound("Image not found")
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => actix_web::error::ErrorNotFound("Image not found"),
+            e => {
+                log::error!("Failed to fetch image for event {}: {}", event_id, e);
+                actix_web::error::ErrorInternalServerError("Failed to fetch image")
+            }
         })?
         .get::<Vec<u8>, _>("image_data");
 
     info!("Serving image for event {}: {} bytes", event_id, image_data.len());
 
-    if image_data.is_empty() || image_data.len() < 1000 {
+    if !imagery::is_png(&image_data) {
         log::error!("Invalid image data for event {}: {} bytes", event_id, image_data.len());
         return Err(actix_web::error::ErrorInternalServerError("Invalid image data"));
     }
@@ -432,13 +1397,41 @@ async fn get_event_image(data: web::Data<AppState>, path: web::Path<i32>) -> Res
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
-    let pool = sqlx::sqlite::SqlitePool::connect("sqlite://world.db").await.unwrap();
+    let options = SqliteConnectOptions::from_str("sqlite://world.db")
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?
+        .create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await.unwrap();
+    let schema_version = db::run_migrations(&pool).await.map_err(|e| std::io::Error::other(format!("Failed to migrate schema: {}", e)))?;
+    info!("Database schema at version {}", schema_version);
+    let items = Arc::new(Catalogue::load("data/items.json").map_err(std::io::Error::other)?);
+    let classes = Arc::new(Classes::load("data/classes.json").map_err(std::io::Error::other)?);
+    let arcs = Arc::new(StoryArcs::load("data/story_arcs.json").map_err(std::io::Error::other)?);
+    let seeds = Arc::new(db::load_world_seeds("data/world_seeds.json", &items, &classes, &arcs).map_err(std::io::Error::other)?);
+    let worlds = db::list_worlds(&pool).await.map_err(|e| std::io::Error::other(format!("Failed to list worlds: {}", e)))?;
+    if worlds.is_empty() {
+        let seed = seeds.get("default").ok_or_else(|| std::io::Error::other("data/world_seeds.json has no 'default' seed"))?;
+        let world = db::create_world(&pool, "Campaign", "default", seed, &classes)
+            .await
+            .map_err(|e| std::io::Error::other(format!("Failed to seed world: {}", e)))?;
+        info!("Created initial world {}", world.id);
+    }
+    let game_master = Arc::new(GameMaster::new(items.clone(), arcs));
+    if let Some(period) = simulation::interval_from_env() {
+        actix_web::rt::spawn(simulation::run_every(pool.clone(), period));
+    }
+    let choice_timeout_secs = std::env::var("CHOICE_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(300);
+    let choice_retries = std::env::var("BRANCH_CHOICE_RETRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(2);
+    let prompt_context = PromptContext::from_env();
     let client = Client::new();
+    let narrative = narrative::provider_from_env(&client)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let images = imagery::backend_from_env(&client)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
 
     HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin("http://127.0.0.1:8081")
-            .allowed_methods(vec!["GET", "HEAD", "OPTIONS", "POST"])
+            .allowed_methods(vec!["GET", "HEAD", "OPTIONS", "POST", "PUT", "DELETE"])
             .allowed_headers(vec![actix_web::http::header::CONTENT_TYPE, actix_web::http::header::AUTHORIZATION, actix_web::http::header::ACCEPT])
             .max_age(3600);
 
@@ -446,16 +1439,68 @@ async fn main() -> std::io::Result<()> {
             .wrap(cors)
             .app_data(web::Data::new(AppState {
                 pool: pool.clone(),
-                client: client.clone(),
+                narrative: narrative.clone(),
+                images: images.clone(),
+                game_master: game_master.clone(),
+                choice_timeout_secs,
+                choice_retries,
+                prompt_context,
+                seeds: seeds.clone(),
+                items: items.clone(),
+                classes: classes.clone(),
             }))
             .service(world_state_options)
             .service(web::resource("/health").route(web::get().to(health_check)).route(web::head().to(health_check)))
-            .service(web::resource("/world/state").route(web::get().to(get_world_state)).route(web::head().to(get_world_state)))
-            .service(web::resource("/state").route(web::post().to(update_state)))
-            .service(web::resource("/events").route(web::get().to(get_events)))
-            .service(web::resource("/story/event").route(web::post().to(generate_story_event)))
-            .service(web::resource("/branch/choices").route(web::get().to(get_branch_choices)))
-            .service(web::resource("/event/image/{id}").route(web::get().to(get_event_image)))
+            .service(web::resource("/worlds").route(web::get().to(list_worlds)).route(web::post().to(create_world)))
+            .service(
+                web::scope("/worlds/{world_id}")
+                    .service(web::resource("/state")
+                        .route(web::get().to(get_world_state))
+                        .route(web::head().to(get_world_state))
+                        .route(web::post().to(update_state)))
+                    .service(web::resource("/player/action").route(web::post().to(player_action)))
+                    .service(web::resource("/players").route(web::post().to(create_player)))
+                    .service(web::resource("/players/{player_id}").route(web::get().to(get_player)))
+                    .service(web::resource("/players/{player_id}/party").route(web::put().to(set_player_party)))
+                    .service(web::resource("/players/{player_id}/inventory").route(web::post().to(adjust_inventory)))
+                    .service(web::resource("/parties").route(web::post().to(create_party)))
+                    .service(web::resource("/factions/{faction_id}/relations/{other_id}").route(web::put().to(set_faction_relation)))
+                    .service(web::resource("/locations/{location_id}/routes/{other_id}")
+                        .route(web::put().to(set_route))
+                        .route(web::delete().to(delete_route)))
+                    .service(web::resource("/locations/{location_id}/influence/{faction_id}").route(web::put().to(set_influence)))
+                    .service(web::resource("/locations/{location_id}/shop").route(web::get().to(get_shop)))
+                    .service(web::resource("/locations/{location_id}/shop/buy").route(web::post().to(buy_item)))
+                    .service(web::resource("/locations/{location_id}/shop/sell").route(web::post().to(sell_item)))
+                    .service(web::resource("/travel").route(web::get().to(plan_travel)))
+                    .service(web::resource("/quests").route(web::get().to(list_quests)).route(web::post().to(create_quest)))
+                    .service(web::resource("/quests/generate").route(web::post().to(generate_quest)))
+                    .service(web::resource("/quests/{quest_id}").route(web::get().to(get_quest)))
+                    .service(web::resource("/quests/{quest_id}/abandon").route(web::post().to(abandon_quest)))
+                    .service(web::resource("/encounters").route(web::get().to(list_encounters)).route(web::post().to(create_encounter)))
+                    .service(web::resource("/encounters/{encounter_id}").route(web::get().to(get_encounter)))
+                    .service(web::resource("/encounters/{encounter_id}/act").route(web::post().to(act_in_encounter)))
+                    .service(web::resource("/npcs").route(web::post().to(create_npc)))
+                    .service(web::resource("/npcs/{npc_id}")
+                        .route(web::get().to(get_npc))
+                        .route(web::put().to(update_npc))
+                        .route(web::delete().to(delete_npc)))
+                    .service(web::resource("/npcs/{npc_id}/dispositions/{player_id}").route(web::put().to(set_npc_disposition)))
+                    .service(web::resource("/npcs/{npc_id}/relationships/{other_id}")
+                        .route(web::put().to(set_npc_relationship))
+                        .route(web::delete().to(delete_npc_relationship)))
+                    .service(web::resource("/npcs/{npc_id}/talk").route(web::post().to(talk_to_npc)))
+                    .service(web::resource("/npcs/{npc_id}/memories").route(web::get().to(get_npc_memories)))
+                    .service(web::resource("/tick").route(web::post().to(tick_world)))
+                    .service(web::resource("/events").route(web::get().to(get_events)))
+                    .service(web::resource("/events/search").route(web::get().to(search_events)))
+                    .service(web::resource("/story/event").route(web::post().to(generate_story_event)))
+                    .service(web::resource("/branch/choices").route(web::get().to(get_branch_choices)))
+                    .service(web::resource("/branch/choices/{choice_id}/resolve").route(web::post().to(resolve_branch_choice)))
+                    .service(web::resource("/story/graph").route(web::get().to(get_story_graph)))
+                    .service(web::resource("/story/summary").route(web::get().to(get_story_summary)))
+                    .service(web::resource("/event/image/{event_id}").route(web::get().to(get_event_image)))
+            )
     })
     .bind(("127.0.0.1", 8080))?
     .run()
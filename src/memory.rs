@@ -0,0 +1,89 @@
+//! The narrative provider is stateless, so each world keeps a short "story so far" that is
+//! rewritten after every narrated event and opens later prompts.
+
+use log::warn;
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use std::env;
+use crate::narrative::{NarrativeProvider, PromptKind};
+
+#[derive(Serialize, FromRow)]
+pub struct StorySummary {
+    pub world_id: i32,
+    pub summary: String,
+    pub last_event_id: Option<i32>,
+    pub updated_at: String,
+}
+
+/// Word limit the summary is asked for and cut to, from `STORY_SUMMARY_WORDS` (default 120).
+pub fn summary_words() -> usize {
+    env::var("STORY_SUMMARY_WORDS").ok().and_then(|v| v.parse().ok()).unwrap_or(120)
+}
+
+pub async fn summary(pool: &SqlitePool, world_id: i32) -> Result<Option<StorySummary>, sqlx::Error> {
+    sqlx::query_as::<_, StorySummary>("SELECT world_id, summary, last_event_id, updated_at FROM story_summaries WHERE world_id = ?")
+        .bind(world_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Keeps at most `limit` words, ending on the last full sentence when there is one.
+fn truncate_words(text: &str, limit: usize) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.len() <= limit {
+        return words.join(" ");
+    }
+    let cut = words[..limit].join(" ");
+    match cut.rfind(['.', '!', '?']) {
+        Some(end) => cut[..=end].to_string(),
+        None => cut,
+    }
+}
+
+/// Keeps the last `limit` words, for summaries that grew by appending.
+fn keep_latest(text: &str, limit: usize) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words[words.len().saturating_sub(limit)..].join(" ")
+}
+
+/// Folds `event` into the world's summary and stores the result. When the provider fails
+/// the event is appended to the old summary instead, so nothing is lost, only less compressed.
+pub async fn record(
+    pool: &SqlitePool,
+    provider: &dyn NarrativeProvider,
+    world_id: i32,
+    event_id: i32,
+    event: &str,
+) -> Result<StorySummary, sqlx::Error> {
+    let previous = summary(pool, world_id).await?.map(|s| s.summary).unwrap_or_default();
+    let limit = summary_words();
+    let story_so_far = if previous.is_empty() { String::new() } else { format!("Story so far:\n- {}\n", previous) };
+    let prompt = format!(
+        "You keep the running summary of a sci-fi campaign. Rewrite the story so far to include the new event, \
+         keeping names, allegiances and unresolved threads, in under {} words. Reply with the summary only.\n\
+         {}New event:\n- {}",
+        limit, story_so_far, event
+    );
+    let text = match provider.complete(PromptKind::Summary, &prompt).await {
+        Ok(text) if !text.trim().is_empty() => truncate_words(&text, limit),
+        Ok(_) => {
+            warn!("Narrative provider {} returned an empty summary, appending event instead", provider.name());
+            keep_latest(&format!("{} {}", previous, event), limit)
+        }
+        Err(e) => {
+            warn!("Narrative provider {} failed to summarise, appending event instead: {}", provider.name(), e);
+            keep_latest(&format!("{} {}", previous, event), limit)
+        }
+    };
+
+    sqlx::query_as::<_, StorySummary>(
+        "INSERT INTO story_summaries (world_id, summary, last_event_id, updated_at) VALUES (?, ?, ?, datetime('now'))
+         ON CONFLICT (world_id) DO UPDATE SET summary = excluded.summary, last_event_id = excluded.last_event_id, updated_at = excluded.updated_at
+         RETURNING world_id, summary, last_event_id, updated_at"
+    )
+    .bind(world_id)
+    .bind(text)
+    .bind(event_id)
+    .fetch_one(pool)
+    .await
+}
@@ -1,18 +1,158 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct StoryEvent {
-    pub id: i32,
-    pub narrative: String,
-    pub state_changes: serde_json::Value, // e.g., {"world.tension": 10, "locations.1.safety": -20}
-    pub created_at: String,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct BranchChoice {
-    pub id: i32,
-    pub event_id: i32,
-    pub description: String,
-    pub state_changes: serde_json::Value, // e.g., {"factions.1.power": 5}
-    pub is_default: bool,
-}
\ No newline at end of file
+use serde::{Deserialize, Serialize};
+use crate::state_changes::StateChanges;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoryEvent {
+    pub id: i32,
+    pub event_id: Option<i32>,         // the narrated row in `events`, if any
+    pub parent_choice_id: Option<i32>, // the branch choice that led here
+    pub narrative: String,
+    pub state_changes: StateChanges, // e.g., {"world.tension": 10, "locations.1.safety": -20}
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BranchChoice {
+    pub id: i32,
+    pub event_id: i32,
+    pub description: String,
+    pub state_changes: StateChanges, // e.g., {"factions.1.power": 5}
+    pub is_default: bool,
+    pub resolved_by: Option<String>, // "player" or "timeout" once taken
+    pub risk: Option<Risk>,          // unset for choices stored before risks were tracked
+    pub affected: Vec<String>,       // names of the entities the choice touches
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Risk {
+    Low,
+    Medium,
+    High,
+}
+
+impl Risk {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Risk::Low => "low",
+            Risk::Medium => "medium",
+            Risk::High => "high",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Risk> {
+        [Risk::Low, Risk::Medium, Risk::High].into_iter().find(|r| r.as_str() == raw)
+    }
+}
+
+/// A formal arrangement between two factions, on top of their numeric standing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Pact {
+    None,
+    Treaty,
+    Alliance,
+    War,
+}
+
+impl Pact {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Pact::None => "none",
+            Pact::Treaty => "treaty",
+            Pact::Alliance => "alliance",
+            Pact::War => "war",
+        }
+    }
+
+    /// One step closer after successful talks, if `standing` now allows it.
+    pub fn improve(self, standing: i32) -> Pact {
+        match self {
+            Pact::War if standing > -30 => Pact::None,
+            Pact::None if standing >= 40 => Pact::Treaty,
+            Pact::Treaty if standing >= 75 => Pact::Alliance,
+            pact => pact,
+        }
+    }
+
+    /// What is left after a betrayal: agreements are void, and bad enough blood means war.
+    pub fn sour(self, standing: i32) -> Pact {
+        if standing <= -60 || self == Pact::War { Pact::War } else { Pact::None }
+    }
+}
+
+/// What an item is for. Weapons and armour help in a fight; shops treat every kind alike.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Weapon,
+    Armor,
+    Consumable,
+    Tool,
+    Treasure,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum QuestStatus {
+    Active,
+    Completed,
+    Failed,
+}
+
+impl QuestStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QuestStatus::Active => "active",
+            QuestStatus::Completed => "completed",
+            QuestStatus::Failed => "failed",
+        }
+    }
+}
+
+/// What a quest objective asks for; its target is a location, faction or NPC id respectively.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ObjectiveKind {
+    /// Stand at a location.
+    Reach,
+    /// Fight a faction often enough, or see it broken.
+    Defeat,
+    /// Speak with an NPC.
+    Talk,
+}
+
+impl ObjectiveKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObjectiveKind::Reach => "reach",
+            ObjectiveKind::Defeat => "defeat",
+            ObjectiveKind::Talk => "talk",
+        }
+    }
+}
+
+/// How a combat encounter stands; only active ones take further rounds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum EncounterStatus {
+    Active,
+    /// Every foe fell.
+    Won,
+    /// Every player character fell.
+    Lost,
+    /// The player characters still standing got away.
+    Fled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Side {
+    Players,
+    Foes,
+}
@@ -0,0 +1,282 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use reqwest::Client;
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+
+/// What a prompt is asking for, so providers that cannot reason (the offline stub)
+/// still answer in the shape the caller expects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptKind {
+    StoryEvent,
+    BranchChoices,
+    /// Rolling "story so far"; facts to fold in are listed as `- ` lines.
+    Summary,
+    /// An NPC's spoken answer to a player character.
+    Dialogue,
+    /// A quest as JSON; possible objective targets are listed as `- <kind> <id>: <name>` lines.
+    Quest,
+    /// A fought round; what happened is listed blow by blow as `- ` lines.
+    Combat,
+}
+
+#[derive(Debug)]
+pub enum NarrativeError {
+    Request(String),
+    Status(u16, String),
+    Malformed(String),
+}
+
+impl fmt::Display for NarrativeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NarrativeError::Request(e) => write!(f, "request failed: {}", e),
+            NarrativeError::Status(status, body) => write!(f, "provider returned status {}: {}", status, body),
+            NarrativeError::Malformed(e) => write!(f, "malformed provider response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for NarrativeError {}
+
+#[async_trait]
+pub trait NarrativeProvider: Send + Sync {
+    fn name(&self) -> &str;
+    async fn complete(&self, kind: PromptKind, prompt: &str) -> Result<String, NarrativeError>;
+}
+
+/// Asks `provider` to complete `prompt`, falling back to `fallback` (and logging why) when it
+/// fails or has nothing to say, so callers never lose what they were narrating.
+pub async fn narrate_or(provider: &dyn NarrativeProvider, kind: PromptKind, prompt: &str, fallback: impl FnOnce() -> String) -> String {
+    match provider.complete(kind, prompt).await {
+        Ok(text) if !text.is_empty() => text,
+        Ok(_) => {
+            warn!("Narrative provider {} returned nothing for {:?}, using fallback", provider.name(), kind);
+            fallback()
+        }
+        Err(e) => {
+            warn!("Narrative provider {} failed for {:?}, using fallback: {}", provider.name(), kind, e);
+            fallback()
+        }
+    }
+}
+
+/// Any server speaking the OpenAI chat-completions protocol (llama.cpp, Ollama, vLLM, ...).
+pub struct OpenAiCompatibleProvider {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn new(client: Client, base_url: &str, api_key: Option<String>, model: &str) -> Self {
+        OpenAiCompatibleProvider {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model: model.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl NarrativeProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &str {
+        "openai-compatible"
+    }
+
+    async fn complete(&self, _kind: PromptKind, prompt: &str) -> Result<String, NarrativeError> {
+        let mut request = self.client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&serde_json::json!({
+                "model": self.model,
+                "messages": [{
+                    "role": "user",
+                    "content": prompt
+                }]
+            }));
+        if let Some(key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", key));
+        }
+
+        let resp = request.send().await.map_err(|e| NarrativeError::Request(e.to_string()))?;
+        let status = resp.status();
+        info!("{} ({}) response status: {}", self.name(), self.model, status);
+        if !status.is_success() {
+            let error_text = resp.text().await.unwrap_or("Unknown error".to_string());
+            return Err(NarrativeError::Status(status.as_u16(), error_text));
+        }
+
+        let json: serde_json::Value = resp.json().await.map_err(|e| NarrativeError::Malformed(e.to_string()))?;
+        json["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|s| s.trim().to_string())
+            .ok_or_else(|| NarrativeError::Malformed("missing choices[0].message.content".to_string()))
+    }
+}
+
+/// xAI's Grok endpoint; a thin preset over the OpenAI-compatible protocol.
+pub struct XaiProvider {
+    inner: OpenAiCompatibleProvider,
+}
+
+impl XaiProvider {
+    pub const BASE_URL: &'static str = "https://api.x.ai/v1";
+    pub const DEFAULT_MODEL: &'static str = "grok-3";
+
+    pub fn new(client: Client, api_key: String, model: &str) -> Self {
+        XaiProvider { inner: OpenAiCompatibleProvider::new(client, Self::BASE_URL, Some(api_key), model) }
+    }
+}
+
+#[async_trait]
+impl NarrativeProvider for XaiProvider {
+    fn name(&self) -> &str {
+        "xai"
+    }
+
+    async fn complete(&self, kind: PromptKind, prompt: &str) -> Result<String, NarrativeError> {
+        self.inner.complete(kind, prompt).await
+    }
+}
+
+/// Deterministic stand-in for offline play and CI: the same prompt always yields the same text.
+pub struct OfflineProvider;
+
+const OFFLINE_OMENS: [&str; 6] = [
+    "A derelict courier drifts into orbit, its hold sealed with an unfamiliar crest.",
+    "The relay towers fall silent for an hour, and no one will say who ordered it.",
+    "Refugees arrive at the gates carrying tales of lights moving beneath the ice.",
+    "A merchant convoy reports its navigator vanished between two jumps.",
+    "Old war drones reactivate in the outer fields, circling but not firing.",
+    "A sealed message addressed to the knight is found in the archive vault.",
+];
+
+const OFFLINE_REPLIES: [&str; 6] = [
+    "Keep your voice down. Not everyone here is a friend.",
+    "I have heard the same rumours you have, and I like them no better.",
+    "Ask me again when the relay towers are working.",
+    "You have done right by this place. I will not forget it.",
+    "I cannot help you with that, but I know someone who might.",
+    "Walk carefully beyond the walls. Things have changed out there.",
+];
+
+/// Description, risk and tension delta; world-level consequences are the only ones the
+/// stub can name without reading the world from the prompt.
+const OFFLINE_CHOICES: [(&str, &str, i32); 6] = [
+    ("Investigate the source quietly before word spreads.", "low", 0),
+    ("Rally the local guard and secure the area.", "medium", -5),
+    ("Seek counsel from the ruling council.", "low", -2),
+    ("Negotiate with whoever is responsible.", "medium", -8),
+    ("Set an ambush and wait for them to move.", "high", 10),
+    ("Ignore it and tend to the people's needs.", "low", 5),
+];
+
+/// Title and description for each objective kind, with `{target}` for the target's name.
+const OFFLINE_QUESTS: [(&str, &str, &str); 3] = [
+    ("reach", "The Road to {target}", "A courier never arrived from {target}. Someone should find out why."),
+    ("defeat", "Thin the Ranks of {target}", "{target} have grown too bold. Meet them in the field."),
+    ("talk", "Words with {target}", "{target} has asked for you by name, and will not say why."),
+];
+
+impl OfflineProvider {
+    const SUMMARY_SENTENCES: usize = 6;
+
+    /// A one-objective quest against one of the targets listed in `prompt`, or an empty
+    /// object when there are none.
+    fn quest(prompt: &str, seed: usize) -> serde_json::Value {
+        let targets: Vec<(&str, i32, &str)> = prompt.lines()
+            .filter_map(|line| {
+                let (kind_and_id, name) = line.strip_prefix("- ")?.split_once(": ")?;
+                let (kind, id) = kind_and_id.split_once(' ')?;
+                OFFLINE_QUESTS.iter().any(|(k, ..)| *k == kind).then_some((kind, id.parse().ok()?, name))
+            })
+            .collect();
+        let Some((kind, id, name)) = targets.get(seed % targets.len().max(1)) else {
+            return serde_json::json!({});
+        };
+        let (_, title, description) = OFFLINE_QUESTS.iter().find(|(k, ..)| k == kind).copied().unwrap_or(OFFLINE_QUESTS[0]);
+        serde_json::json!({
+            "title": title.replace("{target}", name),
+            "description": description.replace("{target}", name),
+            "objectives": [{ "kind": kind, "target_id": id, "count": if *kind == "defeat" { 2 } else { 1 } }],
+            "reward": { "reputation": 5, "coins": 20 + (seed % 4) as i32 * 10 },
+        })
+    }
+
+    fn seed(prompt: &str) -> u64 {
+        // FNV-1a: stable across runs and platforms, unlike the std hasher.
+        prompt.bytes().fold(0xcbf29ce484222325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+    }
+}
+
+#[async_trait]
+impl NarrativeProvider for OfflineProvider {
+    fn name(&self) -> &str {
+        "offline"
+    }
+
+    async fn complete(&self, kind: PromptKind, prompt: &str) -> Result<String, NarrativeError> {
+        let seed = Self::seed(prompt) as usize;
+        let text = match kind {
+            PromptKind::StoryEvent => OFFLINE_OMENS[seed % OFFLINE_OMENS.len()].to_string(),
+            PromptKind::Dialogue => OFFLINE_REPLIES[seed % OFFLINE_REPLIES.len()].to_string(),
+            PromptKind::Quest => Self::quest(prompt, seed).to_string(),
+            PromptKind::BranchChoices => {
+                let choices: Vec<_> = (0..3)
+                    .map(|i| OFFLINE_CHOICES[(seed + i * 2) % OFFLINE_CHOICES.len()])
+                    .map(|(description, risk, tension)| {
+                        let consequences = if tension == 0 { serde_json::json!({}) } else { serde_json::json!({ "world.tension": tension }) };
+                        serde_json::json!({ "description": description, "risk": risk, "consequences": consequences, "affected": [] })
+                    })
+                    .collect();
+                serde_json::Value::Array(choices).to_string()
+            }
+            // Retell the blows as they were listed, without the dice
+            PromptKind::Combat => prompt.lines()
+                .filter_map(|line| line.strip_prefix("- "))
+                .map(|line| format!("{}.", line.split(" [").next().unwrap_or(line)))
+                .collect::<Vec<_>>()
+                .join(" "),
+            // No reasoning to compress with, so keep the most recent facts verbatim
+            PromptKind::Summary => {
+                let facts = prompt.lines().filter_map(|line| line.strip_prefix("- ")).collect::<Vec<_>>().join(" ");
+                let sentences: Vec<&str> = facts.split_inclusive(['.', '!', '?']).map(str::trim).filter(|s| !s.is_empty()).collect();
+                sentences[sentences.len().saturating_sub(Self::SUMMARY_SENTENCES)..].join(" ")
+            }
+        };
+        Ok(text)
+    }
+}
+
+/// Builds the provider selected by `NARRATIVE_PROVIDER` (`xai`, `openai` or `offline`).
+///
+/// Without an explicit choice, xAI is used when `GROK_API_KEY` is set and the offline
+/// stub otherwise, so a fresh checkout runs without any keys.
+pub fn provider_from_env(client: &Client) -> Result<Arc<dyn NarrativeProvider>, String> {
+    let model = env::var("NARRATIVE_MODEL").ok();
+    let provider: Arc<dyn NarrativeProvider> = match env::var("NARRATIVE_PROVIDER").ok().as_deref() {
+        Some("xai") => {
+            let key = env::var("GROK_API_KEY").map_err(|_| "NARRATIVE_PROVIDER=xai requires GROK_API_KEY".to_string())?;
+            Arc::new(XaiProvider::new(client.clone(), key, model.as_deref().unwrap_or(XaiProvider::DEFAULT_MODEL)))
+        }
+        Some("openai") => {
+            let base_url = env::var("NARRATIVE_BASE_URL").unwrap_or("http://127.0.0.1:11434/v1".to_string());
+            let model = model.ok_or_else(|| "NARRATIVE_PROVIDER=openai requires NARRATIVE_MODEL".to_string())?;
+            Arc::new(OpenAiCompatibleProvider::new(client.clone(), &base_url, env::var("NARRATIVE_API_KEY").ok(), &model))
+        }
+        Some("offline") => Arc::new(OfflineProvider),
+        Some(other) => return Err(format!("Unknown NARRATIVE_PROVIDER '{}'", other)),
+        None => match env::var("GROK_API_KEY") {
+            Ok(key) => Arc::new(XaiProvider::new(client.clone(), key, model.as_deref().unwrap_or(XaiProvider::DEFAULT_MODEL))),
+            Err(_) => {
+                warn!("GROK_API_KEY not set and no NARRATIVE_PROVIDER configured, using offline narrative stub");
+                Arc::new(OfflineProvider)
+            }
+        },
+    };
+    info!("Using narrative provider: {}", provider.name());
+    Ok(provider)
+}
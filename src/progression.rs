@@ -0,0 +1,315 @@
+//! Character sheets: classes and backgrounds from `data/classes.json`, the attributes and
+//! skills that feed skill checks, and the experience that levels characters up and unlocks
+//! actions beyond the basic ones.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::fs;
+use crate::db::{self, Player, Sheet};
+use crate::dice::{Modifier, Outcome};
+
+/// The class characters get when none is given, and the one those from before classes became.
+pub const DEFAULT_CLASS: &str = "Adventurer";
+pub const MAX_LEVEL: i32 = 10;
+/// Attributes run from 1 to this; 10 is average and adds nothing to a check.
+pub const MAX_ATTRIBUTE: i32 = 20;
+/// Experience each holder gets for completing a quest.
+pub const QUEST_XP: i32 = 100;
+pub const SKILLS: &[&str] = &["combat", "stealth", "survival", "diplomacy", "deception", "leadership"];
+/// Actions beyond the basic ones and the level a character needs to take them. A party can
+/// take them once any member can.
+pub const UNLOCKS: &[(&str, i32)] = &[("rally", 2), ("sabotage", 3)];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    Might,
+    Agility,
+    Wits,
+    Presence,
+}
+
+impl Attribute {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Attribute::Might => "might",
+            Attribute::Agility => "agility",
+            Attribute::Wits => "wits",
+            Attribute::Presence => "presence",
+        }
+    }
+
+    fn of(self, sheet: &Sheet) -> i32 {
+        match self {
+            Attribute::Might => sheet.might,
+            Attribute::Agility => sheet.agility,
+            Attribute::Wits => sheet.wits,
+            Attribute::Presence => sheet.presence,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Attributes {
+    pub might: i32,
+    pub agility: i32,
+    pub wits: i32,
+    pub presence: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Class {
+    pub name: String,
+    pub description: String,
+    pub attributes: Attributes,
+    /// Hit points at level 1.
+    pub hp: i32,
+    pub skills: Vec<String>,
+}
+
+/// Where a character comes from, adding to what their class teaches them.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Background {
+    pub name: String,
+    pub description: String,
+    pub skills: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Classes {
+    pub classes: Vec<Class>,
+    pub backgrounds: Vec<Background>,
+}
+
+impl Classes {
+    pub fn load(path: &str) -> Result<Classes, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let classes: Classes = serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        let unknown_skill = |skills: &[String]| skills.iter().find(|s| !SKILLS.contains(&s.as_str())).cloned();
+        for (i, class) in classes.classes.iter().enumerate() {
+            if classes.classes[..i].iter().any(|other| other.name == class.name) {
+                return Err(format!("{}: class {} is listed twice", path, class.name));
+            }
+            let a = &class.attributes;
+            if [a.might, a.agility, a.wits, a.presence].iter().any(|v| !(1..=MAX_ATTRIBUTE).contains(v)) || class.hp <= 0 {
+                return Err(format!("{}: class {} needs attributes from 1 to {} and positive hp", path, class.name, MAX_ATTRIBUTE));
+            }
+            if let Some(skill) = unknown_skill(&class.skills) {
+                return Err(format!("{}: class {} teaches unknown skill '{}'", path, class.name, skill));
+            }
+        }
+        for (i, background) in classes.backgrounds.iter().enumerate() {
+            if classes.backgrounds[..i].iter().any(|other| other.name == background.name) {
+                return Err(format!("{}: background {} is listed twice", path, background.name));
+            }
+            if let Some(skill) = unknown_skill(&background.skills) {
+                return Err(format!("{}: background {} teaches unknown skill '{}'", path, background.name, skill));
+            }
+        }
+        if classes.class(DEFAULT_CLASS).is_none() {
+            return Err(format!("{}: the default class {} is missing", path, DEFAULT_CLASS));
+        }
+        Ok(classes)
+    }
+
+    pub fn class(&self, name: &str) -> Option<&Class> {
+        self.classes.iter().find(|c| c.name == name)
+    }
+
+    pub fn background(&self, name: &str) -> Option<&Background> {
+        self.backgrounds.iter().find(|b| b.name == name)
+    }
+
+    /// A fresh level 1 sheet, or why there cannot be one.
+    pub fn sheet(&self, class: &str, background: Option<&str>) -> Result<Sheet, String> {
+        let class = self.class(class).ok_or_else(|| format!("Unknown class '{}'", class))?;
+        let background = background
+            .map(|name| self.background(name).ok_or_else(|| format!("Unknown background '{}'", name)))
+            .transpose()?;
+        let mut skills = class.skills.clone();
+        for skill in background.map_or(&[][..], |b| &b.skills) {
+            if !skills.contains(skill) {
+                skills.push(skill.clone());
+            }
+        }
+        skills.sort();
+        let a = &class.attributes;
+        Ok(Sheet {
+            class: class.name.clone(),
+            background: background.map(|b| b.name.clone()),
+            might: a.might,
+            agility: a.agility,
+            wits: a.wits,
+            presence: a.presence,
+            hp: class.hp,
+            max_hp: class.hp,
+            xp: 0,
+            level: 1,
+            skills,
+        })
+    }
+}
+
+/// What an attribute adds to a check: +1 for every two points above 10, -1 for every two below.
+pub fn modifier(score: i32) -> i32 {
+    (score - 10).div_euclid(2)
+}
+
+/// Experience needed to reach `level`: 100 for level 2, 300 for level 3, 600 for level 4...
+pub fn xp_for_level(level: i32) -> i32 {
+    50 * level * (level - 1)
+}
+
+pub fn level_for(xp: i32) -> i32 {
+    (1..=MAX_LEVEL).take_while(|level| xp >= xp_for_level(*level)).last().unwrap_or(1)
+}
+
+/// Experience for one check: a little for trying, more for pulling it off.
+pub fn xp_for(outcome: Outcome) -> i32 {
+    match outcome {
+        Outcome::CriticalFailure | Outcome::Failure => 5,
+        Outcome::Success => 20,
+        Outcome::CriticalSuccess => 30,
+    }
+}
+
+/// What knowing the skill a check calls for adds to it, growing with experience.
+pub fn skill_bonus(level: i32) -> i32 {
+    2 + level / 4
+}
+
+/// The attribute and skill an action is checked with.
+fn governed_by(action: &str) -> Option<(Attribute, &'static str)> {
+    match action {
+        "move" => Some((Attribute::Agility, "survival")),
+        "help" | "rally" => Some((Attribute::Presence, "leadership")),
+        "fight" => Some((Attribute::Might, "combat")),
+        "negotiate" => Some((Attribute::Presence, "diplomacy")),
+        "betray" => Some((Attribute::Wits, "deception")),
+        "sabotage" => Some((Attribute::Agility, "stealth")),
+        _ => None,
+    }
+}
+
+/// The level needed to take `action`, for actions that have to be unlocked.
+pub fn unlocked_at(action: &str) -> Option<i32> {
+    UNLOCKS.iter().find(|(a, _)| *a == action).map(|(_, level)| *level)
+}
+
+/// What the actors' sheets add to a check for `action`: the best of them at the governing
+/// attribute, and the governing skill if any of them has it, worth more the more seasoned
+/// the most seasoned of them is.
+pub fn modifiers(action: &str, members: &[&Player]) -> Vec<Modifier> {
+    let Some((attribute, skill)) = governed_by(action) else { return Vec::new() };
+    let mut modifiers = Vec::new();
+    if let Some(best) = members.iter().map(|p| attribute.of(&p.sheet)).max() {
+        modifiers.push(Modifier::new(attribute.as_str(), modifier(best)));
+    }
+    if let Some(level) = members.iter().filter(|p| p.sheet.skills.iter().any(|s| s == skill)).map(|p| p.sheet.level).max() {
+        modifiers.push(Modifier::new(skill, skill_bonus(level)));
+    }
+    modifiers
+}
+
+/// Hit points gained on reaching a new level.
+fn hp_per_level(sheet: &Sheet) -> i32 {
+    (5 + modifier(sheet.might)).max(1)
+}
+
+/// Gives each of `members` `xp` experience, levelling up whoever has earned it (which heals
+/// them fully), and describes the level-ups for the caller to log.
+pub async fn award(pool: &SqlitePool, world_id: i32, members: &[&Player], xp: i32) -> Result<Vec<String>, sqlx::Error> {
+    let mut lines = Vec::new();
+    for member in members {
+        let Some(player) = db::get_player(pool, world_id, member.id).await? else { continue };
+        let sheet = &player.sheet;
+        let total = sheet.xp + xp;
+        let level = level_for(total).max(sheet.level);
+        let max_hp = sheet.max_hp + (level - sheet.level) * hp_per_level(sheet);
+        let hp = if level > sheet.level { max_hp } else { sheet.hp };
+        sqlx::query("UPDATE players SET xp = ?, level = ?, hp = ?, max_hp = ? WHERE id = ?")
+            .bind(total).bind(level).bind(hp).bind(max_hp).bind(player.id).execute(pool).await?;
+        if level > sheet.level {
+            lines.push(format!("{} reached level {}", player.name, level));
+            for (action, _) in UNLOCKS.iter().filter(|(_, at)| (sheet.level + 1..=level).contains(at)) {
+                lines.push(format!("{} can now {}", player.name, action));
+            }
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value as Json};
+
+    fn load(name: &str, classes: Json) -> Result<Classes, String> {
+        let path = std::env::temp_dir().join(format!("sci_fi_gm-{}-classes-{}.json", std::process::id(), name));
+        fs::write(&path, classes.to_string()).unwrap();
+        let loaded = Classes::load(path.to_str().unwrap());
+        fs::remove_file(&path).ok();
+        loaded
+    }
+
+    fn class(name: &str, might: i32, hp: i32, skills: &[&str]) -> Json {
+        json!({"name": name, "description": "...", "attributes": {"might": might, "agility": 10, "wits": 10, "presence": 10}, "hp": hp, "skills": skills})
+    }
+
+    fn background(name: &str, skills: &[&str]) -> Json {
+        json!({"name": name, "description": "...", "skills": skills})
+    }
+
+    fn rejected(name: &str, classes: Vec<Json>, backgrounds: Vec<Json>) -> String {
+        match load(name, json!({"classes": classes, "backgrounds": backgrounds})) {
+            Err(e) => e,
+            Ok(_) => panic!("{} should not load", name),
+        }
+    }
+
+    #[test]
+    fn attributes_add_a_point_for_every_two_above_ten() {
+        for (score, expected) in [(1, -5), (8, -1), (9, -1), (10, 0), (11, 0), (12, 1), (13, 1), (20, 5)] {
+            assert_eq!(modifier(score), expected, "score {}", score);
+        }
+    }
+
+    #[test]
+    fn levels_follow_experience_up_to_the_cap() {
+        assert_eq!((xp_for_level(1), xp_for_level(2), xp_for_level(3), xp_for_level(4)), (0, 100, 300, 600));
+        assert_eq!(level_for(0), 1);
+        assert_eq!(level_for(99), 1);
+        assert_eq!(level_for(100), 2);
+        assert_eq!(level_for(299), 2);
+        assert_eq!(level_for(300), 3);
+        assert_eq!(level_for(xp_for_level(MAX_LEVEL) - 1), MAX_LEVEL - 1);
+        assert_eq!(level_for(xp_for_level(MAX_LEVEL)), MAX_LEVEL);
+        assert_eq!(level_for(i32::MAX), MAX_LEVEL);
+        assert_eq!(level_for(-50), 1);
+    }
+
+    #[test]
+    fn loads_the_shipped_classes() {
+        let classes = Classes::load("data/classes.json").unwrap();
+        assert!(classes.class(DEFAULT_CLASS).is_some());
+        let sheet = classes.sheet("Soldier", Some("Noble")).unwrap();
+        assert_eq!((sheet.level, sheet.xp, sheet.hp), (1, 0, sheet.max_hp));
+        assert!(sheet.skills.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", sheet.skills);
+        assert!(sheet.skills.iter().any(|s| s == "leadership"));
+        assert!(classes.sheet("Wizard", None).err().is_some_and(|e| e.contains("Unknown class")));
+        assert!(classes.sheet(DEFAULT_CLASS, Some("Pirate")).err().is_some_and(|e| e.contains("Unknown background")));
+    }
+
+    #[test]
+    fn rejects_bad_classes() {
+        let adventurer = || class(DEFAULT_CLASS, 12, 10, &["survival"]);
+        assert!(load("good", json!({"classes": [adventurer()], "backgrounds": [background("Noble", &["leadership"])]})).is_ok());
+        assert!(rejected("twice", vec![adventurer(), adventurer()], vec![]).contains("listed twice"));
+        assert!(rejected("weak", vec![class(DEFAULT_CLASS, 0, 10, &[])], vec![]).contains("attributes from 1 to 20"));
+        assert!(rejected("strong", vec![class(DEFAULT_CLASS, MAX_ATTRIBUTE + 1, 10, &[])], vec![]).contains("attributes"));
+        assert!(rejected("frail", vec![class(DEFAULT_CLASS, 12, 0, &[])], vec![]).contains("positive hp"));
+        assert!(rejected("magic", vec![class(DEFAULT_CLASS, 12, 10, &["magic"])], vec![]).contains("unknown skill 'magic'"));
+        assert!(rejected("backgrounds", vec![adventurer()], vec![background("Noble", &[]), background("Noble", &[])]).contains("background Noble is listed twice"));
+        assert!(rejected("lore", vec![adventurer()], vec![background("Sage", &["lore"])]).contains("unknown skill 'lore'"));
+        assert!(rejected("defaultless", vec![class("Soldier", 14, 12, &["combat"])], vec![]).contains("default class"));
+    }
+}
@@ -0,0 +1,261 @@
+use log::warn;
+use std::env;
+use crate::db::{Location, Player, WorldState};
+use crate::models::{Pact, QuestStatus};
+use crate::travel;
+
+/// Condenses the world into the preamble of every narrative prompt, most relevant facts
+/// first, so the model's story stays consistent with the game without sending the whole
+/// state (which grows with every location and NPC).
+#[derive(Clone, Copy, Debug)]
+pub struct PromptContext {
+    pub token_budget: usize,
+    pub recent_events: usize,
+    pub related_events: usize,
+}
+
+/// What the world remembers, gathered for each prompt.
+#[derive(Default)]
+pub struct Recall {
+    pub story_so_far: Option<String>,
+    /// Newest first.
+    pub recent: Vec<String>,
+    /// Older events relevant to the prompt, most relevant first.
+    pub related: Vec<String>,
+}
+
+/// Rough token count. Four characters per token is close enough for English prose to keep
+/// prompts under the budget without shipping a tokenizer per provider.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+struct Budget {
+    lines: Vec<String>,
+    remaining: usize,
+}
+
+impl Budget {
+    /// Adds `line` if it still fits, returning whether it did.
+    fn push(&mut self, line: String) -> bool {
+        let cost = estimate_tokens(&line) + 1;
+        if cost > self.remaining {
+            return false;
+        }
+        self.remaining -= cost;
+        self.lines.push(line);
+        true
+    }
+
+    /// Adds `heading` and then one line per event while they fit.
+    fn push_events<'a>(&mut self, heading: &str, events: impl IntoIterator<Item = &'a String>) {
+        let mut events = events.into_iter().peekable();
+        if events.peek().is_none() || !self.push(heading.to_string()) {
+            return;
+        }
+        for event in events {
+            if !self.push(format!("- {}", event)) {
+                break;
+            }
+        }
+    }
+
+    /// Adds `prefix` followed by as many of `items` as fit, in order.
+    fn push_list(&mut self, prefix: &str, items: impl IntoIterator<Item = String>) {
+        let mut line = String::new();
+        for item in items {
+            let candidate = if line.is_empty() { format!("{}{}", prefix, item) } else { format!("{}; {}", line, item) };
+            if estimate_tokens(&candidate) + 1 > self.remaining {
+                break;
+            }
+            line = candidate;
+        }
+        if !line.is_empty() {
+            self.push(format!("{}.", line));
+        }
+    }
+}
+
+impl PromptContext {
+    const MIN_BUDGET: usize = 64;
+
+    /// Reads `PROMPT_TOKEN_BUDGET` (default 512), `PROMPT_RECENT_EVENTS` (default 5) and
+    /// `PROMPT_RELATED_EVENTS` (default 3).
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: usize| match env::var(name) {
+            Ok(raw) => raw.parse().unwrap_or_else(|_| {
+                warn!("Ignoring invalid {}='{}', using {}", name, raw, default);
+                default
+            }),
+            Err(_) => default,
+        };
+        PromptContext {
+            token_budget: read("PROMPT_TOKEN_BUDGET", 512).max(Self::MIN_BUDGET),
+            recent_events: read("PROMPT_RECENT_EVENTS", 5),
+            related_events: read("PROMPT_RELATED_EVENTS", 3),
+        }
+    }
+
+    /// Summarises `state` around the `focus` player (the first player when unset) together
+    /// with what the world recalls. Lower-priority sections are dropped rather than cut
+    /// mid-sentence once the budget runs out.
+    pub fn build(&self, state: &WorldState, focus: Option<i32>, recall: &Recall) -> String {
+        let mut budget = Budget { lines: Vec::new(), remaining: self.token_budget };
+        budget.push(format!(
+            "Campaign \"{}\": act {}, story phase {}, tension {}/100.",
+            state.world.name, state.world.act, state.world.story_phase, state.world.tension
+        ));
+
+        let focus = focus
+            .and_then(|id| state.players.iter().find(|p| p.id == id))
+            .or_else(|| state.players.first());
+        let Some(focus) = focus else {
+            return budget.lines.join("\n");
+        };
+        let companions: Vec<&Player> = state.players.iter()
+            .filter(|p| p.id != focus.id && p.party_id.is_some() && p.party_id == focus.party_id)
+            .collect();
+        let party = focus.party_id.and_then(|id| state.parties.iter().find(|p| p.id == id));
+        let sheet = &focus.sheet;
+        let mut who = format!(
+            "{}, level {} {} ({}/{} HP, reputation {}, {} coins)",
+            focus.name, sheet.level, sheet.class, sheet.hp, sheet.max_hp, focus.reputation, focus.coins
+        );
+        if let Some(party) = party {
+            who = format!("{} of the {}", who, party.name);
+        }
+        if !companions.is_empty() {
+            let names: Vec<String> = companions.iter().map(|p| format!("{} (level {} {}, reputation {})", p.name, p.sheet.level, p.sheet.class, p.reputation)).collect();
+            who = format!("{}, travelling with {}", who, names.join(", "));
+        }
+        budget.push(format!("Protagonist: {}.", who));
+        budget.push_list("Carrying: ", focus.inventory.iter().map(|i| format!("{} x {}", i.quantity, i.item)));
+        budget.push_list(
+            "Quests: ",
+            state.quests.iter()
+                .filter(|q| q.status == QuestStatus::Active && q.is_held_by(focus))
+                .map(|q| format!("{} ({})", q.title, q.objectives.iter().map(|o| o.describe(state)).collect::<Vec<_>>().join(", "))),
+        );
+
+        let faction_name = |id: i32| state.factions.iter().find(|f| f.id == id).map_or("?", |f| f.name.as_str());
+        let held = |location: &Location| location.controlled_by.map(|id| format!(", held by the {}", faction_name(id))).unwrap_or_default();
+        if let Some(here) = state.locations.iter().find(|l| l.id == focus.location_id) {
+            budget.push(format!("Current location: {} (prosperity {}/100, safety {}/100){}.", here.name, here.prosperity, here.safety, held(here)));
+            budget.push_list(
+                "People here: ",
+                state.npcs.iter()
+                    .filter(|n| n.location_id == here.id)
+                    .map(|n| match n.faction_id.and_then(|id| state.factions.iter().find(|f| f.id == id)) {
+                        Some(faction) => format!("{} ({} of the {}, {})", n.name, n.role, faction.name, n.status),
+                        None => format!("{} ({}, {})", n.name, n.role, n.status),
+                    }),
+            );
+            budget.push_list(
+                "Roads from here: ",
+                travel::neighbours(state, here.id).filter_map(|(id, route)| {
+                    let to = state.locations.iter().find(|l| l.id == id)?;
+                    Some(format!("{} ({} hours, danger {})", to.name, route.travel_hours, route.danger))
+                }),
+            );
+        }
+
+        if let Some(story) = recall.story_so_far.as_deref().filter(|s| !s.is_empty()) {
+            budget.push(format!("Story so far: {}", story));
+        }
+
+        let mut factions: Vec<_> = state.factions.iter().collect();
+        factions.sort_by_key(|f| std::cmp::Reverse(f.power));
+        budget.push_list("Factions: ", factions.iter().map(|f| format!("{} is {} (power {})", f.name, f.relation, f.power)));
+        budget.push_list(
+            "Between factions: ",
+            state.faction_relations.iter()
+                .filter(|r| r.pact != Pact::None)
+                .map(|r| format!("{} and {}: {} (standing {})", faction_name(r.faction_id), faction_name(r.other_id), r.pact.as_str(), r.standing)),
+        );
+
+        budget.push_events("Recent events, newest first:", recall.recent.iter().take(self.recent_events));
+        budget.push_events("Related earlier events:", recall.related.iter().take(self.related_events));
+
+        budget.push_list(
+            "Elsewhere: ",
+            state.locations.iter()
+                .filter(|l| l.id != focus.location_id)
+                .map(|l| format!("{} (prosperity {}, safety {}{})", l.name, l.prosperity, l.safety, held(l))),
+        );
+        budget.push_list(
+            "Other players: ",
+            state.players.iter()
+                .filter(|p| p.id != focus.id && !companions.iter().any(|c| c.id == p.id))
+                .map(|p| p.name.clone()),
+        );
+        budget.lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn context(token_budget: usize) -> PromptContext {
+        PromptContext { token_budget, recent_events: 5, related_events: 3 }
+    }
+
+    fn recall() -> Recall {
+        Recall {
+            story_so_far: Some("The Knight arrived in the Capital to find the Bandits bolder than ever.".to_string()),
+            recent: (1..=8).map(|i| format!("Recent event number {} in the Capital", i)).collect(),
+            related: vec!["Long ago the Bandits burned Willowbrook".to_string()],
+        }
+    }
+
+    /// The default campaign with a second character, the Scout, waiting in Willowbrook.
+    async fn world() -> WorldState {
+        let pool = db::memory_pool().await;
+        let world = db::seed_test_world(&pool, "default").await;
+        let mut state = db::get_world_state(&pool, world.id).await.unwrap();
+        let mut scout = state.players[0].clone();
+        scout.id = 2;
+        scout.name = "The Scout".to_string();
+        scout.location_id = state.locations[1].id;
+        state.players.push(scout);
+        state
+    }
+
+    #[tokio::test]
+    async fn drops_lower_priority_sections_whole() {
+        let state = world().await;
+        let full = context(10_000).build(&state, None, &recall());
+        for section in ["Campaign \"Test\"", "Protagonist: The Knight", "Current location: Capital", "Story so far:", "Factions:", "Recent events, newest first:", "Related earlier events:", "Elsewhere: Willowbrook", "Other players: The Scout"] {
+            assert!(full.contains(section), "missing {} in\n{}", section, full);
+        }
+        assert_eq!(full.matches("Recent event number").count(), 5);
+
+        let first_lines: Vec<&str> = full.lines().take_while(|l| !l.starts_with("Story so far")).collect();
+        // Everything before the story so far, and a few tokens too few for anything after it
+        let budget: usize = first_lines.iter().map(|l| estimate_tokens(l) + 1).sum::<usize>() + 3;
+        let small = context(budget).build(&state, None, &recall());
+        assert_eq!(small.lines().collect::<Vec<_>>(), first_lines);
+        for line in small.lines() {
+            assert!(full.lines().any(|l| l == line), "{} was cut short", line);
+        }
+        for dropped in ["Story so far:", "Recent events", "Elsewhere:", "Other players:"] {
+            assert!(!small.contains(dropped), "{} should not fit in\n{}", dropped, small);
+        }
+    }
+
+    #[tokio::test]
+    async fn centres_on_the_first_player_unless_told_otherwise() {
+        let state = world().await;
+        let about = |focus| context(10_000).build(&state, focus, &Recall::default());
+        assert!(about(None).contains("Protagonist: The Knight"));
+        assert!(about(Some(99)).contains("Protagonist: The Knight"));
+        let scout = about(Some(2));
+        assert!(scout.contains("Protagonist: The Scout"));
+        assert!(scout.contains("Current location: Willowbrook"));
+        assert!(scout.contains("Other players: The Knight"));
+
+        let empty = WorldState { players: Vec::new(), ..state };
+        assert_eq!(context(10_000).build(&empty, None, &Recall::default()).lines().count(), 1);
+    }
+}
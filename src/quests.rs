@@ -0,0 +1,318 @@
+//! Quests: goals with objectives that fill in as the world moves. Fights and conversations
+//! are counted as they happen ([`record`]); everything else is read off the world state
+//! whenever something may have changed ([`settle`]), which also pays out rewards and fails
+//! quests that can no longer be done.
+//!
+//! The narrative provider proposes quests as JSON in the shape of [`NewQuest`]:
+//!
+//! ```json
+//! {"title": "Ashes on the Road", "description": "Find out who burned the caravan.",
+//!  "objectives": [{"kind": "reach", "target_id": 2}, {"kind": "defeat", "target_id": 2, "count": 2}],
+//!  "reward": {"reputation": 10, "coins": 40, "item": "Lantern"}}
+//! ```
+
+use serde_json::{json, Value as Json};
+use sqlx::SqlitePool;
+use crate::choices;
+use crate::db::{self, NewQuest, Objective, Player, Quest, Reward, WorldState};
+use crate::game_master::GameError;
+use crate::items::Catalogue;
+use crate::models::{ObjectiveKind, QuestStatus};
+use crate::progression;
+use crate::state_changes::StateChanges;
+
+pub const MAX_OBJECTIVES: usize = 4;
+/// Most fights or conversations one objective may ask for.
+pub const MAX_COUNT: i32 = 10;
+pub const MAX_REWARD_REPUTATION: i32 = 25;
+pub const MAX_REWARD_COINS: i32 = 200;
+const MAX_TITLE_CHARS: usize = 80;
+const MAX_DESCRIPTION_CHARS: usize = 400;
+
+/// Something a character did that quests may be waiting for.
+#[derive(Clone, Copy, Debug)]
+pub enum Deed {
+    /// Fought the faction with this id.
+    Fought(i32),
+    /// Spoke with the NPC with this id.
+    Talked(i32),
+}
+
+impl Objective {
+    pub fn done(&self) -> bool {
+        self.progress >= self.required
+    }
+
+    /// E.g. "defeat the Bandits (1/3)".
+    pub fn describe(&self, state: &WorldState) -> String {
+        let target = target_name(state, self.kind, self.target_id).unwrap_or("something long gone");
+        let what = match self.kind {
+            ObjectiveKind::Reach => format!("reach {}", target),
+            ObjectiveKind::Defeat => format!("defeat the {}", target),
+            ObjectiveKind::Talk => format!("talk to {}", target),
+        };
+        match (self.done(), self.required) {
+            (true, _) => format!("{} (done)", what),
+            (false, 1) => what,
+            (false, required) => format!("{} ({}/{})", what, self.progress, required),
+        }
+    }
+}
+
+impl Quest {
+    /// Whether `player` is on this quest, alone or with their party.
+    pub fn is_held_by(&self, player: &Player) -> bool {
+        self.player_id == Some(player.id) || (self.party_id.is_some() && self.party_id == player.party_id)
+    }
+}
+
+fn target_name(state: &WorldState, kind: ObjectiveKind, id: i32) -> Option<&str> {
+    match kind {
+        ObjectiveKind::Reach => state.locations.iter().find(|l| l.id == id).map(|l| l.name.as_str()),
+        ObjectiveKind::Defeat => state.factions.iter().find(|f| f.id == id).map(|f| f.name.as_str()),
+        ObjectiveKind::Talk => state.npcs.iter().find(|n| n.id == id).map(|n| n.name.as_str()),
+    }
+}
+
+/// The characters on `quest` and the name to log them under.
+pub fn holders<'a>(state: &'a WorldState, quest: &Quest) -> (String, Vec<&'a Player>) {
+    let holders: Vec<&Player> = state.players.iter().filter(|p| quest.is_held_by(p)).collect();
+    let name = match quest.party_id.and_then(|id| state.parties.iter().find(|p| p.id == id)) {
+        Some(party) => party.name.clone(),
+        None => holders.first().map_or_else(|| "Nobody".to_string(), |p| p.name.clone()),
+    };
+    (name, holders)
+}
+
+/// Checks a quest for `cast` against `state` and the item catalogue before it is stored,
+/// returning it tidied up.
+pub fn check(quest: &NewQuest, state: &WorldState, cast: &[&Player], catalogue: &Catalogue) -> Result<NewQuest, String> {
+    let title = quest.title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err(format!("title must be 1 to {} characters", MAX_TITLE_CHARS));
+    }
+    if quest.description.trim().is_empty() || quest.description.chars().count() > MAX_DESCRIPTION_CHARS {
+        return Err(format!("'{}': description must be 1 to {} characters", title, MAX_DESCRIPTION_CHARS));
+    }
+    if !(1..=MAX_OBJECTIVES).contains(&quest.objectives.len()) {
+        return Err(format!("'{}': needs 1 to {} objectives", title, MAX_OBJECTIVES));
+    }
+    for objective in &quest.objectives {
+        let kind = objective.kind.as_str();
+        if target_name(state, objective.kind, objective.target_id).is_none() {
+            return Err(format!("'{}': {} target {} does not exist in this world", title, kind, objective.target_id));
+        }
+        if objective.kind == ObjectiveKind::Talk && state.npcs.iter().any(|n| n.id == objective.target_id && !n.is_alive()) {
+            return Err(format!("'{}': NPC {} cannot be talked to", title, objective.target_id));
+        }
+        if let Some(player) = cast.iter().find(|p| objective.kind == ObjectiveKind::Reach && p.location_id == objective.target_id) {
+            return Err(format!("'{}': {} is already at location {}", title, player.name, objective.target_id));
+        }
+        let max = if objective.kind == ObjectiveKind::Reach { 1 } else { MAX_COUNT };
+        if !(1..=max).contains(&objective.count) {
+            return Err(format!("'{}': {} objectives take a count from 1 to {}", title, kind, max));
+        }
+    }
+    let reward = &quest.reward;
+    if !(0..=MAX_REWARD_REPUTATION).contains(&reward.reputation) || !(0..=MAX_REWARD_COINS).contains(&reward.coins) {
+        return Err(format!("'{}': rewards are 0 to {} reputation and 0 to {} coins", title, MAX_REWARD_REPUTATION, MAX_REWARD_COINS));
+    }
+    if let Some(item) = reward.item.as_deref().filter(|i| catalogue.get(i).is_none()) {
+        return Err(format!("'{}': unknown reward item '{}'", title, item));
+    }
+    Ok(NewQuest { title: title.to_string(), description: quest.description.trim().to_string(), ..quest.clone() })
+}
+
+/// Instructions appended to the quest prompt, listing every target the model may use.
+/// Targets are given one per line as `- <kind> <id>: <name>`.
+pub fn reply_format(state: &WorldState, catalogue: &Catalogue) -> String {
+    let targets = state.locations.iter().map(|l| format!("- reach {}: {}", l.id, l.name))
+        .chain(state.factions.iter().map(|f| format!("- defeat {}: {}", f.id, f.name)))
+        .chain(state.npcs.iter().filter(|n| n.is_alive()).map(|n| format!("- talk {}: {}", n.id, n.name)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "Reply with only a JSON object and no other text, with \"title\" (under 8 words), \"description\" \
+         (one or two sentences), \"objectives\" (1 to {max_objectives} objects with \"kind\" (\"reach\", \"defeat\" \
+         or \"talk\"), \"target_id\" and, for defeat and talk, \"count\" from 1 to {max_count}) and \"reward\" \
+         (\"reputation\" from 0 to {max_reputation}, \"coins\" from 0 to {max_coins} and optionally one \"item\" by name).\n\
+         Item names: {items}.\n\
+         Objective targets:\n{targets}",
+        max_objectives = MAX_OBJECTIVES,
+        max_count = MAX_COUNT,
+        max_reputation = MAX_REWARD_REPUTATION,
+        max_coins = MAX_REWARD_COINS,
+        items = catalogue.names().collect::<Vec<_>>().join(", "),
+        targets = targets,
+    )
+}
+
+/// Parses and validates a provider reply proposing a quest for `cast`.
+pub fn parse_reply(reply: &str, state: &WorldState, cast: &[&Player], catalogue: &Catalogue) -> Result<NewQuest, String> {
+    let json = choices::extract_json(reply).ok_or("reply contains no JSON")?;
+    let quest: NewQuest = serde_json::from_str(json).map_err(|e| format!("reply is not a quest: {}", e))?;
+    check(&quest, state, cast, catalogue)
+}
+
+/// A stock quest for `cast` when the narrative provider has none: break the strongest
+/// hostile faction, or failing that, look in on the least safe place they are not at.
+pub fn fallback(state: &WorldState, cast: &[&Player]) -> Option<NewQuest> {
+    let reward = Reward { reputation: 10, coins: 40, item: None };
+    if let Some(faction) = state.factions.iter().filter(|f| f.relation == "Hostile").max_by_key(|f| f.power) {
+        return Some(NewQuest {
+            title: format!("Break the {}", faction.name),
+            description: format!("The {} grow bolder by the day. Meet them in the field until they think twice.", faction.name),
+            objectives: vec![db::NewObjective { kind: ObjectiveKind::Defeat, target_id: faction.id, count: 3 }],
+            reward,
+        });
+    }
+    let location = state.locations.iter().filter(|l| !cast.iter().any(|p| p.location_id == l.id)).min_by_key(|l| l.safety)?;
+    Some(NewQuest {
+        title: format!("Trouble at {}", location.name),
+        description: format!("Word from {} is thin and worrying. Go and see for yourselves.", location.name),
+        objectives: vec![db::NewObjective { kind: ObjectiveKind::Reach, target_id: location.id, count: 1 }],
+        reward,
+    })
+}
+
+/// Counts `deed`, done by `doers`, towards the quests they hold.
+pub async fn record(pool: &SqlitePool, state: &WorldState, doers: &[&Player], deed: Deed) -> Result<(), sqlx::Error> {
+    let (kind, target_id) = match deed {
+        Deed::Fought(faction_id) => (ObjectiveKind::Defeat, faction_id),
+        Deed::Talked(npc_id) => (ObjectiveKind::Talk, npc_id),
+    };
+    let quests = state.quests.iter().filter(|q| q.status == QuestStatus::Active && doers.iter().any(|p| q.is_held_by(p)));
+    for objective in quests.flat_map(|q| &q.objectives).filter(|o| o.kind == kind && o.target_id == target_id && !o.done()) {
+        db::advance_objective(pool, objective.id, 1).await?;
+    }
+    Ok(())
+}
+
+/// Why `objective` can no longer be done, if it cannot.
+fn blocked(state: &WorldState, objective: &Objective) -> Option<String> {
+    let Some(target) = target_name(state, objective.kind, objective.target_id) else {
+        return Some(format!("its {} target is gone", objective.kind.as_str()));
+    };
+    let npc = state.npcs.iter().find(|n| n.id == objective.target_id && objective.kind == ObjectiveKind::Talk);
+    npc.filter(|n| !n.is_alive()).map(|n| format!("{} is {}", target, n.status.to_lowercase()))
+}
+
+/// Whether the world as it stands fulfils `objective` for `holders`.
+fn fulfilled(state: &WorldState, holders: &[&Player], objective: &Objective) -> bool {
+    match objective.kind {
+        ObjectiveKind::Reach => holders.iter().any(|p| p.location_id == objective.target_id),
+        // A faction without any power left is beaten, whoever broke it
+        ObjectiveKind::Defeat => state.factions.iter().any(|f| f.id == objective.target_id && f.power == 0),
+        ObjectiveKind::Talk => false,
+    }
+}
+
+/// Brings every active quest in `world_id` up to date with the world: ticks off objectives
+/// the state fulfils, fails quests that cannot be finished any more and completes and
+/// rewards the rest once all their objectives are done. Logs and returns what happened.
+pub async fn settle(pool: &SqlitePool, world_id: i32, caused_by: &str) -> Result<Vec<String>, GameError> {
+    let state = db::get_world_state(pool, world_id).await?;
+    let mut happenings = Vec::new();
+    for quest in state.quests.iter().filter(|q| q.status == QuestStatus::Active) {
+        let (name, holders) = holders(&state, quest);
+        if holders.is_empty() {
+            continue;
+        }
+        let open: Vec<&Objective> = quest.objectives.iter().filter(|o| !o.done()).collect();
+        if let Some(reason) = open.iter().find_map(|o| blocked(&state, o)) {
+            if db::finish_quest(pool, quest.id, QuestStatus::Failed).await? {
+                happenings.push(format!("{} failed the quest '{}': {}", name, quest.title, reason));
+            }
+            continue;
+        }
+        let mut finished = true;
+        for objective in open {
+            if fulfilled(&state, &holders, objective) {
+                db::advance_objective(pool, objective.id, objective.required).await?;
+            } else {
+                finished = false;
+            }
+        }
+        if finished && db::finish_quest(pool, quest.id, QuestStatus::Completed).await? {
+            reward(pool, world_id, &holders, &quest.reward).await?;
+            happenings.push(format!("{} completed the quest '{}'", name, quest.title));
+            happenings.extend(progression::award(pool, world_id, &holders, progression::QUEST_XP).await?);
+        }
+    }
+    for happening in &happenings {
+        db::log_event(pool, world_id, happening, caused_by).await?;
+    }
+    Ok(happenings)
+}
+
+/// Pays `reward` out to each of `holders`.
+async fn reward(pool: &SqlitePool, world_id: i32, holders: &[&Player], reward: &Reward) -> Result<(), GameError> {
+    let mut changes = Vec::new();
+    for player in holders {
+        if reward.reputation != 0 {
+            changes.push(json!({ "path": format!("players.{}.reputation", player.id), "add": reward.reputation }));
+        }
+        if reward.coins != 0 {
+            changes.push(json!({ "path": format!("players.{}.coins", player.id), "add": reward.coins }));
+        }
+        if let Some(item) = &reward.item {
+            changes.push(json!({ "path": format!("players.{}.items.{}", player.id, item), "add": 1 }));
+        }
+    }
+    StateChanges::parse(&Json::Array(changes))?.apply(pool, world_id).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default campaign, with the Knight in the Capital (1).
+    async fn world() -> (WorldState, Catalogue) {
+        let pool = db::memory_pool().await;
+        let world = db::seed_test_world(&pool, "default").await;
+        (db::get_world_state(&pool, world.id).await.unwrap(), Catalogue::load("data/items.json").unwrap())
+    }
+
+    fn quest(objectives: Json, reward: Json) -> NewQuest {
+        serde_json::from_value(json!({"title": "  Ashes on the Road ", "description": "Find out who burned the caravan.", "objectives": objectives, "reward": reward})).unwrap()
+    }
+
+    #[tokio::test]
+    async fn accepts_a_sound_quest() {
+        let (state, catalogue) = world().await;
+        let cast = [&state.players[0]];
+        let npc = state.npcs[0].id;
+        let proposed = quest(
+            json!([{"kind": "reach", "target_id": 2}, {"kind": "defeat", "target_id": 2, "count": MAX_COUNT}, {"kind": "talk", "target_id": npc}]),
+            json!({"reputation": MAX_REWARD_REPUTATION, "coins": MAX_REWARD_COINS, "item": "Lantern"}),
+        );
+        let checked = check(&proposed, &state, &cast, &catalogue).unwrap();
+        assert_eq!(checked.title, "Ashes on the Road");
+        assert_eq!(checked.objectives.iter().map(|o| o.count).collect::<Vec<_>>(), [1, MAX_COUNT, 1]);
+    }
+
+    #[tokio::test]
+    async fn rejects_quests_that_cannot_be_done() {
+        let (mut state, catalogue) = world().await;
+        let npc = state.npcs[0].id;
+        state.npcs[0].status = "Dead".to_string();
+        let knight = state.players[0].clone();
+        let cast = [&knight];
+        let rejected = |objectives: Json, reward: Json| {
+            check(&quest(objectives, reward), &state, &cast, &catalogue).err().expect("quest should be rejected")
+        };
+        let reach = json!([{"kind": "reach", "target_id": 2}]);
+
+        assert!(rejected(json!([{"kind": "reach", "target_id": knight.location_id}]), json!({})).contains("already at location"));
+        assert!(rejected(json!([{"kind": "reach", "target_id": 2, "count": 2}]), json!({})).contains("count from 1 to 1"));
+        assert!(rejected(json!([{"kind": "defeat", "target_id": 2, "count": 0}]), json!({})).contains("count from 1 to 10"));
+        assert!(rejected(json!([{"kind": "defeat", "target_id": 2, "count": MAX_COUNT + 1}]), json!({})).contains("count from 1 to 10"));
+        assert!(rejected(json!([{"kind": "talk", "target_id": npc}]), json!({})).contains("cannot be talked to"));
+        assert!(rejected(json!([{"kind": "defeat", "target_id": 99}]), json!({})).contains("does not exist"));
+        assert!(rejected(json!([]), json!({})).contains("objectives"));
+        assert!(rejected(reach.clone(), json!({"item": "Holy Grail"})).contains("unknown reward item 'Holy Grail'"));
+        assert!(rejected(reach.clone(), json!({"coins": MAX_REWARD_COINS + 1})).contains("rewards"));
+        assert!(rejected(reach, json!({"reputation": -5})).contains("rewards"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Keyword retrieval over a world's history, backed by the `search_index` FTS5 table that
+//! triggers keep in step with events, the game master's log, NPC conversations, locations
+//! and NPCs.
+
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+
+/// What a hit points at: `source_id` is an id in `events`, `event_log`, `npc_memories`,
+/// `locations` or `npcs` respectively.
+pub const KINDS: [&str; 5] = ["event", "log", "talk", "location", "npc"];
+
+/// Words too common to say anything about relevance.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "have", "in", "into", "is", "it",
+    "its", "of", "on", "or", "that", "the", "their", "them", "they", "this", "to", "was", "were", "with",
+];
+
+#[derive(Serialize, FromRow)]
+pub struct SearchHit {
+    pub kind: String,
+    pub source_id: i32,
+    pub text: String,
+    /// The matching part of `text` with hits in [brackets].
+    pub snippet: String,
+    pub created_at: Option<String>,
+    /// Higher is more relevant.
+    pub score: f64,
+}
+
+#[derive(Clone, Copy)]
+pub enum Match {
+    /// Every word must appear, for lookups.
+    All,
+    /// Any word may appear, ranked by how well, for retrieval.
+    Any,
+}
+
+/// Turns free text into an FTS5 query of quoted words, so user input can never be read as
+/// query syntax. `None` when no searchable word is left.
+pub fn fts_query(text: &str, mode: Match) -> Option<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()).map(str::to_lowercase) {
+        if word.is_empty() || STOPWORDS.contains(&word.as_str()) || words.contains(&word) {
+            continue;
+        }
+        words.push(word);
+    }
+    if words.is_empty() {
+        return None;
+    }
+    let quoted: Vec<String> = words.iter().map(|w| format!("\"{}\"", w)).collect();
+    Some(quoted.join(match mode {
+        Match::All => " ",
+        Match::Any => " OR ",
+    }))
+}
+
+/// Searches everything indexed for `world_id`, optionally only one of [`KINDS`], best first.
+pub async fn search(pool: &SqlitePool, world_id: i32, query: &str, kind: Option<&str>, limit: i64) -> Result<Vec<SearchHit>, sqlx::Error> {
+    sqlx::query_as::<_, SearchHit>(
+        "SELECT kind, source_id, text, snippet(search_index, 0, '[', ']', '…', 12) AS snippet,
+                created_at, -bm25(search_index) AS score
+         FROM search_index
+         WHERE search_index MATCH ? AND world_id = ? AND (? IS NULL OR kind = ?)
+         ORDER BY bm25(search_index), created_at DESC
+         LIMIT ?"
+    )
+    .bind(query)
+    .bind(world_id)
+    .bind(kind)
+    .bind(kind)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Descriptions of the past events in `world_id` most relevant to `topic`, leaving out those
+/// in `skip` (typically the recent events a prompt already carries).
+pub async fn related_events(pool: &SqlitePool, world_id: i32, topic: &str, skip: &[String], limit: usize) -> Result<Vec<String>, sqlx::Error> {
+    let Some(query) = fts_query(topic, Match::Any).filter(|_| limit > 0) else {
+        return Ok(Vec::new());
+    };
+    let found: Vec<String> = sqlx::query_scalar(
+        "SELECT text FROM search_index
+         WHERE search_index MATCH ? AND world_id = ? AND kind IN ('event', 'log', 'talk')
+         ORDER BY bm25(search_index), created_at DESC
+         LIMIT ?"
+    )
+    .bind(query)
+    .bind(world_id)
+    .bind((limit + skip.len()) as i64)
+    .fetch_all(pool)
+    .await?;
+    let mut related: Vec<String> = Vec::new();
+    for text in found {
+        if related.len() < limit && !skip.contains(&text) && !related.contains(&text) {
+            related.push(text);
+        }
+    }
+    Ok(related)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_every_word() {
+        assert_eq!(fts_query("Red Silas", Match::All).as_deref(), Some("\"red\" \"silas\""));
+        assert_eq!(fts_query("Red Silas", Match::Any).as_deref(), Some("\"red\" OR \"silas\""));
+    }
+
+    #[test]
+    fn strips_quotes_and_operators() {
+        assert_eq!(fts_query("\"king\" alric\"", Match::All).as_deref(), Some("\"king\" \"alric\""));
+        assert_eq!(fts_query("sil* -bandits ^raid (road):", Match::All).as_deref(), Some("\"sil\" \"bandits\" \"raid\" \"road\""));
+        assert_eq!(fts_query("king's", Match::All).as_deref(), Some("\"king\" \"s\""));
+    }
+
+    #[test]
+    fn keywords_are_searched_as_plain_words() {
+        assert_eq!(fts_query("king NEAR bandits", Match::All).as_deref(), Some("\"king\" \"near\" \"bandits\""));
+        assert_eq!(fts_query("king OR bandits NOT silas AND raid", Match::All).as_deref(), Some("\"king\" \"bandits\" \"not\" \"silas\" \"raid\""));
+    }
+
+    #[test]
+    fn nothing_searchable_is_none() {
+        assert_eq!(fts_query("", Match::Any), None);
+        assert_eq!(fts_query("the and of it", Match::Any), None);
+        assert_eq!(fts_query("\"*\" -- ()", Match::All), None);
+    }
+
+    #[test]
+    fn repeated_words_count_once() {
+        assert_eq!(fts_query("Raid raid RAID on the road, raid!", Match::Any).as_deref(), Some("\"raid\" OR \"road\""));
+    }
+}
@@ -0,0 +1,175 @@
+//! The world moving on by itself between player actions. Each tick, factions act on their
+//! nature and gain ground doing it, NPCs travel along the roads, and locations drift toward
+//! the safety the balance of faction power allows. Happenings are written to the event log
+//! like anything else the world does.
+
+use log::{error, info, warn};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use serde_json::{json, Value as Json};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use crate::db::{self, Location, WorldState};
+use crate::game_master::GameError;
+use crate::quests;
+use crate::state_changes::StateChanges;
+use crate::territory;
+use crate::travel;
+
+/// Chance per tick that an NPC sets off for another location.
+const NPC_TRAVEL_CHANCE: f64 = 0.25;
+/// Share of the gap to its target a location closes each tick (at least one point).
+const DRIFT_RATE: i32 = 10;
+
+#[derive(Serialize)]
+pub struct TickReport {
+    pub world_id: i32,
+    /// What the event log gained, in order.
+    pub happenings: Vec<String>,
+    /// Number of state changes applied, including unlogged drift.
+    pub changes: usize,
+}
+
+/// How far `from` moves toward `to` in one tick.
+fn drift(from: i32, to: i32) -> i32 {
+    let gap = to - from;
+    if gap == 0 { 0 } else { gap.signum() * (gap.abs() / DRIFT_RATE).max(1) }
+}
+
+fn weakest<'a>(locations: &'a [Location], rng: &mut impl Rng) -> Option<&'a Location> {
+    let lowest = locations.iter().map(|l| l.safety).min()?;
+    locations.iter().filter(|l| l.safety == lowest).collect::<Vec<_>>().choose(rng).copied()
+}
+
+/// Decides what happens this tick from a snapshot of the world, as log lines and state
+/// changes. Kept apart from the database so the random rolls never span an await.
+fn plan(state: &WorldState, rng: &mut impl Rng) -> (Vec<String>, Vec<Json>) {
+    let mut happenings = Vec::new();
+    let mut changes = Vec::new();
+    let mut active_at = HashMap::new();
+
+    // Factions act with a chance equal to their power
+    for faction in &state.factions {
+        if !rng.gen_bool(f64::from(faction.power.clamp(0, 100)) / 100.0) {
+            continue;
+        }
+        match faction.relation.as_str() {
+            "Hostile" => {
+                let Some(target) = weakest(&state.locations, rng) else { continue };
+                changes.push(json!({ "path": format!("locations.{}.safety", target.id), "add": -5 }));
+                changes.push(json!({ "path": format!("locations.{}.prosperity", target.id), "add": -5 }));
+                changes.push(json!({ "path": format!("factions.{}.power", faction.id), "add": 2 }));
+                changes.push(json!({ "path": format!("locations.{}.influence.{}", target.id, faction.id), "add": 5 }));
+                if let Some(holder) = target.controlled_by.filter(|id| *id != faction.id) {
+                    changes.push(json!({ "path": format!("locations.{}.influence.{}", target.id, holder), "add": -5 }));
+                }
+                happenings.push(format!("{} raided {}", faction.name, target.name));
+                active_at.insert(faction.id, target.id);
+            }
+            "Friendly" => {
+                let Some(target) = weakest(&state.locations, rng) else { continue };
+                changes.push(json!({ "path": format!("locations.{}.safety", target.id), "add": 5 }));
+                changes.push(json!({ "path": format!("locations.{}.influence.{}", target.id, faction.id), "add": 5 }));
+                happenings.push(format!("{} patrolled {}", faction.name, target.name));
+                active_at.insert(faction.id, target.id);
+            }
+            _ => {
+                let Some(target) = state.locations.iter().max_by_key(|l| l.prosperity) else { continue };
+                changes.push(json!({ "path": format!("locations.{}.prosperity", target.id), "add": 3 }));
+                changes.push(json!({ "path": format!("factions.{}.power", faction.id), "add": 1 }));
+                changes.push(json!({ "path": format!("locations.{}.influence.{}", target.id, faction.id), "add": 3 }));
+                happenings.push(format!("{} traded in {}", faction.name, target.name));
+                active_at.insert(faction.id, target.id);
+            }
+        }
+    }
+
+    // NPCs wander one road at a time, members of a faction toward wherever it was just active
+    for npc in state.npcs.iter().filter(|n| n.status == "Alive") {
+        if !rng.gen_bool(NPC_TRAVEL_CHANCE) {
+            continue;
+        }
+        let nearby: Vec<&Location> = travel::neighbours(state, npc.location_id)
+            .filter_map(|(id, _)| state.locations.iter().find(|l| l.id == id))
+            .collect();
+        let rally = npc.faction_id
+            .and_then(|id| active_at.get(&id))
+            .and_then(|id| travel::next_stop(state, npc.location_id, *id))
+            .and_then(|id| nearby.iter().find(|l| l.id == id));
+        let Some(to) = rally.or_else(|| nearby.choose(rng)) else { continue };
+        let Some(from) = state.locations.iter().find(|l| l.id == npc.location_id) else { continue };
+        changes.push(json!({ "path": format!("npcs.{}.location_id", npc.id), "set": to.id }));
+        happenings.push(format!("{} travelled from {} to {}", npc.name, from.name, to.name));
+    }
+
+    // Safety follows the balance of friendly and hostile power; prosperity follows safety
+    let power = |relation: &str| state.factions.iter().filter(|f| f.relation == relation).map(|f| f.power).sum::<i32>();
+    let target_safety = (50 + (power("Friendly") - power("Hostile")) / 2).clamp(0, 100);
+    for location in &state.locations {
+        let safety = drift(location.safety, target_safety);
+        let prosperity = drift(location.prosperity, location.safety);
+        if safety != 0 {
+            changes.push(json!({ "path": format!("locations.{}.safety", location.id), "add": safety }));
+        }
+        if prosperity != 0 {
+            changes.push(json!({ "path": format!("locations.{}.prosperity", location.id), "add": prosperity }));
+        }
+    }
+
+    (happenings, changes)
+}
+
+/// Advances `world_id` by one tick.
+pub async fn tick(pool: &SqlitePool, world_id: i32) -> Result<TickReport, GameError> {
+    let state = db::get_world_state(pool, world_id).await?;
+    let (mut happenings, changes) = plan(&state, &mut rand::thread_rng());
+    let changes = StateChanges::parse(&Json::Array(changes))?;
+    let applied = changes.apply(pool, world_id).await?;
+    for happening in &happenings {
+        db::log_event(pool, world_id, happening, "Simulation").await?;
+    }
+    happenings.extend(territory::log_shifts(pool, world_id, &state, "Simulation").await?);
+    happenings.extend(quests::settle(pool, world_id, "Simulation").await?);
+    info!("World {} ticked: {} happenings, {} changes", world_id, happenings.len(), applied.len());
+    Ok(TickReport { world_id, happenings, changes: applied.len() })
+}
+
+/// The automatic tick interval from `SIMULATION_TICK_SECS`; unset or 0 leaves ticking to
+/// `POST /worlds/{world_id}/tick`.
+pub fn interval_from_env() -> Option<Duration> {
+    let raw = env::var("SIMULATION_TICK_SECS").ok()?;
+    match raw.parse::<u64>() {
+        Ok(0) => None,
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            warn!("Ignoring invalid SIMULATION_TICK_SECS='{}', not ticking automatically", raw);
+            None
+        }
+    }
+}
+
+/// Ticks every world once per `period`, forever.
+pub async fn run_every(pool: SqlitePool, period: Duration) {
+    info!("Ticking all worlds every {}s", period.as_secs());
+    let mut interval = tokio::time::interval(period);
+    // The first tick fires immediately; let the server come up first
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let worlds = match db::list_worlds(&pool).await {
+            Ok(worlds) => worlds,
+            Err(e) => {
+                error!("Failed to list worlds to tick: {}", e);
+                continue;
+            }
+        };
+        for world in worlds {
+            if let Err(e) = tick(&pool, world.id).await {
+                error!("Failed to tick world {}: {}", world.id, e);
+            }
+        }
+    }
+}
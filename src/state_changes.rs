@@ -0,0 +1,743 @@
+//! Typed consequences for story events and branch choices.
+//!
+//! Two JSON spellings are accepted. The shorthand object maps a path to a delta
+//! (numbers) or a new value (strings):
+//!
+//! ```json
+//! {"world.tension": 10, "locations.1.safety": -20, "players.1.reputation": 5, "npcs.1.status": "Dead"}
+//! ```
+//!
+//! A faction's influence over a location is addressed through both, as
+//! `locations.<location id>.influence.<faction id>`, and how many of an item a player
+//! carries as `players.<player id>.items.<item name>`.
+//!
+//! The full form is a list of operations, each optionally clamped or guarded:
+//!
+//! ```json
+//! [
+//!   {"path": "factions.2.power", "add": 15, "clamp": [0, 60]},
+//!   {"path": "world.story_phase", "set": "Climax", "if": {"path": "world.tension", "gte": 90}},
+//!   {"path": "locations.1.prosperity", "clamp": [20, 80]}
+//! ]
+//! ```
+//!
+//! Paths and values are checked against [`FIELDS`] when parsed; entity existence (within
+//! the target world) is checked when applied. Numeric results are always kept inside the
+//! column's bounds.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as Json};
+use sqlx::query::Query;
+use sqlx::sqlite::SqliteArguments;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use std::fmt;
+use crate::items::{MAX_COINS, MAX_STACK};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Entity {
+    World,
+    Player(i64),
+    Location(i64),
+    Faction(i64),
+    Npc(i64),
+    /// A faction's influence over a location: (location id, faction id).
+    Influence(i64, i64),
+    /// How many of an item a player carries: (player id, item name).
+    Item(i64, String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FieldKind {
+    Int { min: i64, max: i64 },
+    Text { allowed: &'static [&'static str] },
+    /// Id of a row in another table.
+    Ref { table: &'static str },
+}
+
+struct FieldSpec {
+    entity: &'static str,
+    column: &'static str,
+    kind: FieldKind,
+}
+
+/// The phases a story can be in; story arcs are made of these.
+pub const STORY_PHASES: &[&str] = &["Build-Up", "Conflict", "Climax", "Resolution"];
+
+/// Every field a state change may touch. Table and column names are only ever taken
+/// from here, so they are safe to splice into SQL.
+const FIELDS: &[FieldSpec] = &[
+    FieldSpec { entity: "world", column: "tension", kind: FieldKind::Int { min: 0, max: 100 } },
+    FieldSpec { entity: "world", column: "story_phase", kind: FieldKind::Text { allowed: STORY_PHASES } },
+    FieldSpec { entity: "world", column: "act", kind: FieldKind::Int { min: 1, max: 99 } },
+    FieldSpec { entity: "players", column: "reputation", kind: FieldKind::Int { min: -100, max: 100 } },
+    FieldSpec { entity: "players", column: "location_id", kind: FieldKind::Ref { table: "locations" } },
+    FieldSpec { entity: "locations", column: "prosperity", kind: FieldKind::Int { min: 0, max: 100 } },
+    FieldSpec { entity: "locations", column: "safety", kind: FieldKind::Int { min: 0, max: 100 } },
+    FieldSpec { entity: "factions", column: "power", kind: FieldKind::Int { min: 0, max: 100 } },
+    FieldSpec { entity: "factions", column: "relation", kind: FieldKind::Text { allowed: &["Friendly", "Neutral", "Hostile"] } },
+    FieldSpec { entity: "npcs", column: "status", kind: FieldKind::Text { allowed: &["Alive", "Dead", "Missing", "Captured"] } },
+    FieldSpec { entity: "npcs", column: "location_id", kind: FieldKind::Ref { table: "locations" } },
+    FieldSpec { entity: "location_influence", column: "influence", kind: FieldKind::Int { min: 0, max: 100 } },
+    FieldSpec { entity: "players", column: "coins", kind: FieldKind::Int { min: 0, max: MAX_COINS } },
+    FieldSpec { entity: "inventory", column: "quantity", kind: FieldKind::Int { min: 0, max: MAX_STACK } },
+];
+
+#[derive(Clone)]
+pub struct Path {
+    pub entity: Entity,
+    field: &'static FieldSpec,
+}
+
+impl Path {
+    pub fn parse(raw: &str) -> Result<Path, StateChangeError> {
+        let parts: Vec<&str> = raw.split('.').collect();
+        let (entity, column) = match parts.as_slice() {
+            ["world", column] => (Entity::World, *column),
+            ["locations", location, "influence", faction] => {
+                let id = |raw_id: &str| raw_id.parse::<i64>().map_err(|_| StateChangeError::Invalid(format!("'{}': '{}' is not an id", raw, raw_id)));
+                (Entity::Influence(id(location)?, id(faction)?), "influence")
+            }
+            ["players", player, "items", item] => {
+                let id: i64 = player.parse().map_err(|_| StateChangeError::Invalid(format!("'{}': '{}' is not an id", raw, player)))?;
+                if item.trim().is_empty() {
+                    return Err(StateChangeError::Invalid(format!("'{}' names no item", raw)));
+                }
+                (Entity::Item(id, item.to_string()), "quantity")
+            }
+            [table, id, column] => {
+                let id: i64 = id.parse().map_err(|_| StateChangeError::Invalid(format!("'{}': '{}' is not an id", raw, id)))?;
+                let entity = match *table {
+                    "players" => Entity::Player(id),
+                    "locations" => Entity::Location(id),
+                    "factions" => Entity::Faction(id),
+                    "npcs" => Entity::Npc(id),
+                    _ => return Err(StateChangeError::Invalid(format!("'{}': unknown entity '{}'", raw, table))),
+                };
+                (entity, *column)
+            }
+            _ => return Err(StateChangeError::Invalid(format!("'{}' is not a valid path", raw))),
+        };
+        let table = Self::table_of(&entity);
+        let field = FIELDS
+            .iter()
+            .find(|f| f.entity == table && f.column == column)
+            .ok_or_else(|| StateChangeError::Invalid(format!("'{}': {} has no changeable field '{}'", raw, table, column)))?;
+        Ok(Path { entity, field })
+    }
+
+    fn table_of(entity: &Entity) -> &'static str {
+        match entity {
+            Entity::World => "world",
+            Entity::Player(_) => "players",
+            Entity::Location(_) => "locations",
+            Entity::Faction(_) => "factions",
+            Entity::Npc(_) => "npcs",
+            Entity::Influence(..) => "location_influence",
+            Entity::Item(..) => "inventory",
+        }
+    }
+
+    /// The row this path addresses within `world_id`, as a WHERE clause and its binds.
+    /// Entity ids are global, so the world check keeps one campaign from touching another.
+    fn locate(&self, world_id: i32) -> (&'static str, Vec<Value>) {
+        let world = Value::Int(world_id as i64);
+        match &self.entity {
+            Entity::World => ("id = ?", vec![world]),
+            Entity::Player(id) | Entity::Location(id) | Entity::Faction(id) | Entity::Npc(id) => ("id = ? AND world_id = ?", vec![Value::Int(*id), world]),
+            Entity::Influence(location, faction) => (
+                "location_id = ? AND faction_id = ? AND location_id IN (SELECT id FROM locations WHERE world_id = ?)",
+                vec![Value::Int(*location), Value::Int(*faction), world],
+            ),
+            Entity::Item(player, item) => (
+                "player_id = ? AND item = ? AND player_id IN (SELECT id FROM players WHERE world_id = ?)",
+                vec![Value::Int(*player), Value::Text(item.clone()), world],
+            ),
+        }
+    }
+
+    async fn read(&self, tx: &mut Transaction<'_, Sqlite>, world_id: i32) -> Result<Value, StateChangeError> {
+        if let Entity::Influence(location, faction) = self.entity {
+            // No row yet is no influence, as long as both sides belong to the world
+            let influence: Option<i64> = sqlx::query_scalar(
+                "SELECT COALESCE((SELECT influence FROM location_influence WHERE location_id = l.id AND faction_id = f.id), 0)
+                 FROM locations l JOIN factions f ON f.world_id = l.world_id WHERE l.id = ? AND f.id = ? AND l.world_id = ?"
+            )
+            .bind(location)
+            .bind(faction)
+            .bind(world_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+            return influence.map(Value::Int).ok_or_else(|| StateChangeError::UnknownEntity(self.to_string()));
+        }
+        if let Entity::Item(player, item) = &self.entity {
+            // Not carrying any is carrying none, as long as the player is in the world
+            let quantity: Option<i64> = sqlx::query_scalar(
+                "SELECT COALESCE((SELECT quantity FROM inventory WHERE player_id = p.id AND item = ?), 0) FROM players p WHERE p.id = ? AND p.world_id = ?"
+            )
+            .bind(item)
+            .bind(player)
+            .bind(world_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+            return quantity.map(Value::Int).ok_or_else(|| StateChangeError::UnknownEntity(self.to_string()));
+        }
+        let (filter, binds) = self.locate(world_id);
+        let sql = format!("SELECT {} FROM {} WHERE {}", self.field.column, self.field.entity, filter);
+        let row = binds
+            .into_iter()
+            .fold(sqlx::query(&sql), |query, bind| bind.bind_to(query))
+            .fetch_optional(&mut **tx)
+            .await?
+            .ok_or_else(|| StateChangeError::UnknownEntity(self.to_string()))?;
+        Ok(match self.field.kind {
+            FieldKind::Text { .. } => Value::Text(row.get(0)),
+            FieldKind::Int { .. } | FieldKind::Ref { .. } => Value::Int(row.get(0)),
+        })
+    }
+
+    async fn write(&self, tx: &mut Transaction<'_, Sqlite>, world_id: i32, value: &Value) -> Result<(), StateChangeError> {
+        if let (FieldKind::Ref { table }, Value::Int(id)) = (self.field.kind, value) {
+            let exists = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = ? AND world_id = ?", table))
+                .bind(id)
+                .bind(world_id)
+                .fetch_optional(&mut **tx)
+                .await?;
+            if exists.is_none() {
+                return Err(StateChangeError::UnknownEntity(format!("{}.{}", table, id)));
+            }
+        }
+        if let (Entity::Influence(location, faction), Value::Int(influence)) = (&self.entity, value) {
+            sqlx::query(
+                "INSERT INTO location_influence (location_id, faction_id, influence) VALUES (?, ?, ?)
+                 ON CONFLICT (location_id, faction_id) DO UPDATE SET influence = excluded.influence"
+            )
+            .bind(location)
+            .bind(faction)
+            .bind(influence)
+            .execute(&mut **tx)
+            .await?;
+            return Ok(());
+        }
+        if let (Entity::Item(player, item), Value::Int(quantity)) = (&self.entity, value) {
+            let query = if *quantity == 0 {
+                sqlx::query("DELETE FROM inventory WHERE player_id = ? AND item = ?").bind(player).bind(item)
+            } else {
+                sqlx::query(
+                    "INSERT INTO inventory (player_id, item, quantity) VALUES (?, ?, ?)
+                     ON CONFLICT (player_id, item) DO UPDATE SET quantity = excluded.quantity"
+                )
+                .bind(player)
+                .bind(item)
+                .bind(quantity)
+            };
+            query.execute(&mut **tx).await?;
+            return Ok(());
+        }
+        let (filter, binds) = self.locate(world_id);
+        let sql = format!("UPDATE {} SET {} = ? WHERE {}", self.field.entity, self.field.column, filter);
+        let query = value.clone().bind_to(sqlx::query(&sql));
+        binds.into_iter().fold(query, |query, bind| bind.bind_to(query)).execute(&mut **tx).await?;
+        Ok(())
+    }
+
+    /// Checks that `value` may be stored in this field.
+    fn validate(&self, value: &Value) -> Result<(), StateChangeError> {
+        match (self.field.kind, value) {
+            (FieldKind::Int { min, max }, Value::Int(v)) if (min..=max).contains(v) => Ok(()),
+            (FieldKind::Int { min, max }, Value::Int(v)) => {
+                Err(StateChangeError::Invalid(format!("{}: {} is outside {}..={}", self, v, min, max)))
+            }
+            (FieldKind::Ref { .. }, Value::Int(_)) => Ok(()),
+            (FieldKind::Text { allowed }, Value::Text(v)) if allowed.contains(&v.as_str()) => Ok(()),
+            (FieldKind::Text { allowed }, Value::Text(v)) => {
+                Err(StateChangeError::Invalid(format!("{}: '{}' is not one of {:?}", self, v, allowed)))
+            }
+            _ => Err(StateChangeError::Invalid(format!("{}: wrong value type {}", self, value))),
+        }
+    }
+
+    fn bounds(&self) -> Option<(i64, i64)> {
+        match self.field.kind {
+            FieldKind::Int { min, max } => Some((min, max)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entity {
+            Entity::World => write!(f, "{}.{}", self.field.entity, self.field.column),
+            Entity::Player(id) | Entity::Location(id) | Entity::Faction(id) | Entity::Npc(id) => write!(f, "{}.{}.{}", self.field.entity, id, self.field.column),
+            Entity::Influence(location, faction) => write!(f, "locations.{}.influence.{}", location, faction),
+            Entity::Item(player, item) => write!(f, "players.{}.items.{}", player, item),
+        }
+    }
+}
+
+impl fmt::Debug for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Path({})", self)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Int(i64),
+    Text(String),
+}
+
+impl Value {
+    fn from_json(raw: &Json) -> Result<Value, StateChangeError> {
+        match raw {
+            Json::Number(n) => n.as_i64().map(Value::Int).ok_or_else(|| StateChangeError::Invalid(format!("{} is not an integer", n))),
+            Json::String(s) => Ok(Value::Text(s.clone())),
+            other => Err(StateChangeError::Invalid(format!("{} is not a number or string", other))),
+        }
+    }
+
+    fn bind_to<'q>(self, query: Query<'q, Sqlite, SqliteArguments<'q>>) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+        match self {
+            Value::Int(v) => query.bind(v),
+            Value::Text(v) => query.bind(v),
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        match self {
+            Value::Int(v) => json!(v),
+            Value::Text(v) => json!(v),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Text(v) => write!(f, "'{}'", v),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Op {
+    Set(Value),
+    Add { delta: i64, clamp: Option<(i64, i64)> },
+    Clamp(i64, i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("eq", Comparison::Eq),
+        ("ne", Comparison::Ne),
+        ("gt", Comparison::Gt),
+        ("gte", Comparison::Gte),
+        ("lt", Comparison::Lt),
+        ("lte", Comparison::Lte),
+    ];
+
+    fn key(self) -> &'static str {
+        Self::ALL.iter().find(|(_, c)| *c == self).map(|(k, _)| *k).unwrap_or("eq")
+    }
+
+    fn holds(self, left: &Value, right: &Value) -> bool {
+        match (self, left, right) {
+            (Comparison::Eq, l, r) => l == r,
+            (Comparison::Ne, l, r) => l != r,
+            (Comparison::Gt, Value::Int(l), Value::Int(r)) => l > r,
+            (Comparison::Gte, Value::Int(l), Value::Int(r)) => l >= r,
+            (Comparison::Lt, Value::Int(l), Value::Int(r)) => l < r,
+            (Comparison::Lte, Value::Int(l), Value::Int(r)) => l <= r,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "Json")]
+pub struct Condition {
+    pub path: Path,
+    pub comparison: Comparison,
+    pub value: Value,
+}
+
+impl Condition {
+    fn parse(raw: &Json) -> Result<Condition, StateChangeError> {
+        let obj = raw.as_object().ok_or_else(|| StateChangeError::Invalid(format!("condition {} must be an object", raw)))?;
+        let path = Path::parse(obj.get("path").and_then(Json::as_str).ok_or_else(|| StateChangeError::Invalid(format!("condition {} has no path", raw)))?)?;
+        let mut found = Comparison::ALL.iter().filter_map(|(key, c)| obj.get(*key).map(|v| (*c, v)));
+        let (comparison, value) = found.next().ok_or_else(|| StateChangeError::Invalid(format!("condition {} has no comparison", raw)))?;
+        if found.next().is_some() {
+            return Err(StateChangeError::Invalid(format!("condition {} has more than one comparison", raw)));
+        }
+        let value = Value::from_json(value)?;
+        if matches!(value, Value::Text(_)) && !matches!(comparison, Comparison::Eq | Comparison::Ne) {
+            return Err(StateChangeError::Invalid(format!("condition {}: text can only be compared with eq/ne", raw)));
+        }
+        let comparable = matches!(
+            (path.field.kind, &value),
+            (FieldKind::Text { .. }, Value::Text(_)) | (FieldKind::Int { .. } | FieldKind::Ref { .. }, Value::Int(_))
+        );
+        if !comparable {
+            return Err(StateChangeError::Invalid(format!("condition on {} compares against {}", path, value)));
+        }
+        Ok(Condition { path, comparison, value })
+    }
+
+    pub async fn holds(&self, tx: &mut Transaction<'_, Sqlite>, world_id: i32) -> Result<bool, StateChangeError> {
+        let current = self.path.read(tx, world_id).await?;
+        Ok(self.comparison.holds(&current, &self.value))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StateChange {
+    pub path: Path,
+    pub op: Op,
+    pub condition: Option<Condition>,
+}
+
+fn parse_range(raw: &Json) -> Result<(i64, i64), StateChangeError> {
+    match raw.as_array().map(|a| a.as_slice()) {
+        Some([min, max]) => match (min.as_i64(), max.as_i64()) {
+            (Some(min), Some(max)) if min <= max => Ok((min, max)),
+            _ => Err(StateChangeError::Invalid(format!("clamp {} must be [min, max] with min <= max", raw))),
+        },
+        _ => Err(StateChangeError::Invalid(format!("clamp {} must be [min, max]", raw))),
+    }
+}
+
+impl StateChange {
+    fn parse(raw: &Json) -> Result<StateChange, StateChangeError> {
+        let obj = raw.as_object().ok_or_else(|| StateChangeError::Invalid(format!("change {} must be an object", raw)))?;
+        let path = Path::parse(obj.get("path").and_then(Json::as_str).ok_or_else(|| StateChangeError::Invalid(format!("change {} has no path", raw)))?)?;
+        let clamp = obj.get("clamp").map(parse_range).transpose()?;
+        let op = match (obj.get("set"), obj.get("add"), clamp) {
+            (Some(value), None, None) => Op::Set(Value::from_json(value)?),
+            (None, Some(delta), clamp) => Op::Add {
+                delta: delta.as_i64().ok_or_else(|| StateChangeError::Invalid(format!("{}: add needs an integer", path)))?,
+                clamp,
+            },
+            (None, None, Some((min, max))) => Op::Clamp(min, max),
+            _ => return Err(StateChangeError::Invalid(format!("change {} needs exactly one of set, add or clamp", raw))),
+        };
+        let condition = obj.get("if").map(Condition::parse).transpose()?;
+        let change = StateChange { path, op, condition };
+        change.validate()?;
+        Ok(change)
+    }
+
+    fn validate(&self) -> Result<(), StateChangeError> {
+        match &self.op {
+            Op::Set(value) => self.path.validate(value),
+            Op::Add { clamp, .. } => {
+                if self.path.bounds().is_none() {
+                    return Err(StateChangeError::Invalid(format!("{}: add only applies to numeric fields", self.path)));
+                }
+                match clamp {
+                    Some((min, max)) => self.path.validate(&Value::Int(*min)).and(self.path.validate(&Value::Int(*max))),
+                    None => Ok(()),
+                }
+            }
+            Op::Clamp(min, max) => {
+                if self.path.bounds().is_none() {
+                    return Err(StateChangeError::Invalid(format!("{}: clamp only applies to numeric fields", self.path)));
+                }
+                self.path.validate(&Value::Int(*min)).and(self.path.validate(&Value::Int(*max)))
+            }
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let mut obj = Map::new();
+        obj.insert("path".to_string(), json!(self.path.to_string()));
+        match &self.op {
+            Op::Set(value) => {
+                obj.insert("set".to_string(), value.to_json());
+            }
+            Op::Add { delta, clamp } => {
+                obj.insert("add".to_string(), json!(delta));
+                if let Some((min, max)) = clamp {
+                    obj.insert("clamp".to_string(), json!([min, max]));
+                }
+            }
+            Op::Clamp(min, max) => {
+                obj.insert("clamp".to_string(), json!([min, max]));
+            }
+        }
+        if let Some(condition) = &self.condition {
+            obj.insert(
+                "if".to_string(),
+                json!({ "path": condition.path.to_string(), condition.comparison.key(): condition.value.to_json() }),
+            );
+        }
+        Json::Object(obj)
+    }
+
+    /// Applies the change inside `tx`, returning `None` when its condition did not hold.
+    async fn apply(&self, tx: &mut Transaction<'_, Sqlite>, world_id: i32) -> Result<Option<AppliedChange>, StateChangeError> {
+        if let Some(condition) = &self.condition {
+            if !condition.holds(tx, world_id).await? {
+                return Ok(None);
+            }
+        }
+        let before = self.path.read(tx, world_id).await?;
+        let after = match (&self.op, &before) {
+            (Op::Set(value), _) => value.clone(),
+            (Op::Add { delta, clamp }, Value::Int(current)) => {
+                let (min, max) = clamp.or(self.path.bounds()).unwrap_or((i64::MIN, i64::MAX));
+                Value::Int(current.saturating_add(*delta).clamp(min, max))
+            }
+            (Op::Clamp(min, max), Value::Int(current)) => Value::Int((*current).clamp(*min, *max)),
+            _ => return Err(StateChangeError::Invalid(format!("{} is not numeric", self.path))),
+        };
+        // An explicit clamp may be wider than the column allows; the schema always wins.
+        let after = match (after, self.path.bounds()) {
+            (Value::Int(v), Some((min, max))) => Value::Int(v.clamp(min, max)),
+            (other, _) => other,
+        };
+        if after != before {
+            self.path.write(tx, world_id, &after).await?;
+        }
+        Ok(Some(AppliedChange { path: self.path.to_string(), before, after }))
+    }
+}
+
+/// A validated, ordered list of state changes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(try_from = "Json", into = "Json")]
+pub struct StateChanges(pub Vec<StateChange>);
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AppliedChange {
+    pub path: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+impl StateChanges {
+    pub fn parse(raw: &Json) -> Result<StateChanges, StateChangeError> {
+        match raw {
+            Json::Null => Ok(StateChanges::default()),
+            Json::Array(items) => items.iter().map(StateChange::parse).collect::<Result<_, _>>().map(StateChanges),
+            Json::Object(shorthand) => shorthand
+                .iter()
+                .map(|(path, value)| {
+                    let op = if value.is_number() { json!({ "path": path, "add": value }) } else { json!({ "path": path, "set": value }) };
+                    StateChange::parse(&op)
+                })
+                .collect::<Result<_, _>>()
+                .map(StateChanges),
+            other => Err(StateChangeError::Invalid(format!("state changes must be an object or a list, got {}", other))),
+        }
+    }
+
+    /// Every path the changes touch or test, in order.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.0.iter().flat_map(|change| std::iter::once(&change.path).chain(change.condition.as_ref().map(|c| &c.path)))
+    }
+
+    /// Applies every change to `world_id` atomically: either all of them land or none do.
+    pub async fn apply(&self, pool: &SqlitePool, world_id: i32) -> Result<Vec<AppliedChange>, StateChangeError> {
+        let mut tx = pool.begin().await?;
+        let applied = self.apply_in(&mut tx, world_id).await?;
+        tx.commit().await?;
+        Ok(applied)
+    }
+
+    /// Applies the changes as part of a caller-owned transaction.
+    pub async fn apply_in(&self, tx: &mut Transaction<'_, Sqlite>, world_id: i32) -> Result<Vec<AppliedChange>, StateChangeError> {
+        let mut applied = Vec::new();
+        for change in &self.0 {
+            if let Some(result) = change.apply(tx, world_id).await? {
+                applied.push(result);
+            }
+        }
+        Ok(applied)
+    }
+}
+
+impl TryFrom<Json> for Condition {
+    type Error = StateChangeError;
+
+    fn try_from(raw: Json) -> Result<Self, Self::Error> {
+        Condition::parse(&raw)
+    }
+}
+
+impl TryFrom<Json> for StateChanges {
+    type Error = StateChangeError;
+
+    fn try_from(raw: Json) -> Result<Self, Self::Error> {
+        StateChanges::parse(&raw)
+    }
+}
+
+impl From<StateChanges> for Json {
+    fn from(changes: StateChanges) -> Json {
+        Json::Array(changes.0.iter().map(StateChange::to_json).collect())
+    }
+}
+
+#[derive(Debug)]
+pub enum StateChangeError {
+    Invalid(String),
+    UnknownEntity(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for StateChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateChangeError::Invalid(e) => write!(f, "invalid state change: {}", e),
+            StateChangeError::UnknownEntity(path) => write!(f, "no such entity: {}", path),
+            StateChangeError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StateChangeError {}
+
+impl From<sqlx::Error> for StateChangeError {
+    fn from(e: sqlx::Error) -> Self {
+        StateChangeError::Database(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: Json) -> Result<StateChanges, StateChangeError> {
+        StateChanges::parse(&raw)
+    }
+
+    fn invalid(raw: Json) -> String {
+        match parse(raw.clone()) {
+            Err(StateChangeError::Invalid(e)) => e,
+            Err(other) => panic!("{} failed with {} instead of being invalid", raw, other),
+            Ok(_) => panic!("{} should not parse", raw),
+        }
+    }
+
+    #[test]
+    fn parses_paths_for_every_entity() {
+        for raw in ["world.tension", "players.1.reputation", "locations.2.safety", "factions.3.power", "npcs.4.status", "locations.1.influence.2", "players.1.items.Short Sword"] {
+            let path = Path::parse(raw).unwrap_or_else(|e| panic!("{}: {}", raw, e));
+            assert_eq!(path.to_string(), raw);
+        }
+        assert_eq!(Path::parse("players.7.location_id").unwrap().entity, Entity::Player(7));
+        assert_eq!(Path::parse("locations.1.influence.2").unwrap().entity, Entity::Influence(1, 2));
+        assert_eq!(Path::parse("players.7.items.Rope").unwrap().entity, Entity::Item(7, "Rope".to_string()));
+    }
+
+    #[test]
+    fn rejects_unknown_paths() {
+        for raw in ["world", "world.gold", "dragons.1.hp", "players.x.reputation", "players.1.name", "player.reputation", "players.1.2.reputation", ""] {
+            assert!(matches!(Path::parse(raw), Err(StateChangeError::Invalid(_))), "{} should not parse", raw);
+        }
+    }
+
+    #[test]
+    fn shorthand_adds_numbers_and_sets_text() {
+        let changes = parse(json!({"world.tension": 10, "npcs.1.status": "Dead"})).unwrap();
+        assert_eq!(changes.0.len(), 2);
+        for change in &changes.0 {
+            match (change.path.to_string().as_str(), &change.op) {
+                ("world.tension", Op::Add { delta: 10, clamp: None }) => {}
+                ("npcs.1.status", Op::Set(Value::Text(status))) => assert_eq!(status, "Dead"),
+                (path, op) => panic!("unexpected {} {:?}", path, op),
+            }
+        }
+        assert!(parse(Json::Null).unwrap().0.is_empty());
+        assert!(invalid(json!(5)).contains("object or a list"));
+    }
+
+    #[test]
+    fn rejects_bad_ops() {
+        invalid(json!([{"path": "world.tension"}]));
+        invalid(json!([{"path": "world.tension", "set": 5, "add": 5}]));
+        invalid(json!([{"path": "world.tension", "add": 1.5}]));
+        invalid(json!([{"path": "world.tension", "set": true}]));
+        invalid(json!([{"path": "world.tension", "clamp": [50, 10]}]));
+        invalid(json!([{"path": "world.tension", "clamp": [10]}]));
+        invalid(json!([{"add": 5}]));
+        assert!(invalid(json!([{"path": "npcs.1.status", "add": 1}])).contains("numeric"));
+        assert!(invalid(json!([{"path": "world.story_phase", "clamp": [0, 1]}])).contains("numeric"));
+        assert!(invalid(json!([{"path": "world.tension", "set": "high"}])).contains("wrong value type"));
+    }
+
+    #[test]
+    fn rejects_values_outside_the_column() {
+        assert!(invalid(json!([{"path": "world.tension", "set": 150}])).contains("outside"));
+        invalid(json!([{"path": "players.1.reputation", "set": -101}]));
+        invalid(json!([{"path": "world.tension", "add": 5, "clamp": [0, 200]}]));
+        invalid(json!([{"path": "locations.1.safety", "clamp": [-10, 50]}]));
+        assert!(parse(json!([{"path": "world.tension", "set": 100}, {"path": "factions.1.power", "add": 500}])).is_ok());
+    }
+
+    #[test]
+    fn text_fields_only_take_their_allowed_values() {
+        for phase in STORY_PHASES {
+            assert!(parse(json!({"world.story_phase": phase})).is_ok(), "{} should be a story phase", phase);
+        }
+        assert!(invalid(json!({"world.story_phase": "Epilogue"})).contains("not one of"));
+        for status in ["Alive", "Dead", "Missing", "Captured"] {
+            assert!(parse(json!({"npcs.1.status": status})).is_ok());
+        }
+        invalid(json!({"npcs.1.status": "Undead"}));
+        invalid(json!({"factions.1.relation": "Wary"}));
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let changes = parse(json!([{"path": "world.story_phase", "set": "Climax", "if": {"path": "world.tension", "gte": 90}}])).unwrap();
+        let condition = changes.0[0].condition.as_ref().unwrap();
+        assert_eq!(condition.comparison, Comparison::Gte);
+        assert_eq!(condition.value, Value::Int(90));
+        assert!(parse(json!([{"path": "world.tension", "add": 5, "if": {"path": "npcs.1.status", "ne": "Dead"}}])).is_ok());
+        assert_eq!(changes.paths().map(|p| p.to_string()).collect::<Vec<_>>(), ["world.story_phase", "world.tension"]);
+    }
+
+    #[test]
+    fn rejects_bad_conditions() {
+        assert!(invalid(json!([{"path": "world.tension", "add": 5, "if": {"path": "world.tension"}}])).contains("no comparison"));
+        assert!(invalid(json!([{"path": "world.tension", "add": 5, "if": {"path": "world.tension", "gt": 1, "lt": 9}}])).contains("more than one"));
+        assert!(invalid(json!([{"path": "world.tension", "add": 5, "if": {"path": "npcs.1.status", "gt": "Dead"}}])).contains("eq/ne"));
+        assert!(invalid(json!([{"path": "world.tension", "add": 5, "if": {"path": "world.tension", "eq": "high"}}])).contains("compares against"));
+        invalid(json!([{"path": "world.tension", "add": 5, "if": {"eq": 1}}]));
+        invalid(json!([{"path": "world.tension", "add": 5, "if": "always"}]));
+        assert!(serde_json::from_value::<Condition>(json!({"path": "world.act", "lt": 3})).is_ok());
+        assert!(serde_json::from_value::<Condition>(json!({"path": "world.act", "lt": "three"})).is_err());
+    }
+
+    #[test]
+    fn comparisons_only_order_numbers() {
+        assert!(Comparison::Lte.holds(&Value::Int(3), &Value::Int(3)));
+        assert!(!Comparison::Gt.holds(&Value::Int(3), &Value::Int(3)));
+        assert!(Comparison::Ne.holds(&Value::Text("Alive".into()), &Value::Text("Dead".into())));
+        assert!(!Comparison::Gt.holds(&Value::Text("b".into()), &Value::Text("a".into())));
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let raw = json!([
+            {"path": "factions.2.power", "add": 15, "clamp": [0, 60]},
+            {"path": "world.story_phase", "set": "Climax", "if": {"path": "world.tension", "gte": 90}},
+            {"path": "locations.1.prosperity", "clamp": [20, 80]}
+        ]);
+        assert_eq!(Json::from(parse(raw.clone()).unwrap()), raw);
+    }
+}
@@ -0,0 +1,296 @@
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use std::collections::HashMap;
+use std::fmt;
+use crate::choices::ChoiceDraft;
+use crate::models::{BranchChoice, Risk, StoryEvent};
+use crate::state_changes::{AppliedChange, StateChangeError, StateChanges};
+
+#[derive(Serialize)]
+pub struct Resolution {
+    pub choice: BranchChoice,
+    pub applied: Vec<AppliedChange>,
+}
+
+#[derive(Serialize)]
+pub struct StoryNode {
+    #[serde(flatten)]
+    pub event: StoryEvent,
+    pub choices: Vec<ChoiceNode>,
+}
+
+#[derive(Serialize)]
+pub struct ChoiceNode {
+    #[serde(flatten)]
+    pub choice: BranchChoice,
+    /// Events that followed from taking this choice.
+    pub next: Vec<StoryNode>,
+}
+
+#[derive(Debug)]
+pub enum StoryError {
+    NotFound(String),
+    Conflict(String),
+    Invalid(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for StoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoryError::NotFound(e) | StoryError::Conflict(e) | StoryError::Invalid(e) => write!(f, "{}", e),
+            StoryError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoryError {}
+
+impl From<sqlx::Error> for StoryError {
+    fn from(e: sqlx::Error) -> Self {
+        StoryError::Database(e)
+    }
+}
+
+impl From<StateChangeError> for StoryError {
+    fn from(e: StateChangeError) -> Self {
+        match e {
+            StateChangeError::Database(e) => StoryError::Database(e),
+            other => StoryError::Invalid(other.to_string()),
+        }
+    }
+}
+
+fn parse_changes(raw: &str) -> Result<StateChanges, StoryError> {
+    let json: serde_json::Value = serde_json::from_str(raw).map_err(|e| StoryError::Invalid(format!("stored state changes are not JSON: {}", e)))?;
+    Ok(StateChanges::parse(&json)?)
+}
+
+fn event_from_row(row: &SqliteRow) -> Result<StoryEvent, StoryError> {
+    Ok(StoryEvent {
+        id: row.get("id"),
+        event_id: row.get("event_id"),
+        parent_choice_id: row.get("parent_choice_id"),
+        narrative: row.get("narrative"),
+        state_changes: parse_changes(row.get("state_changes"))?,
+        created_at: row.get("created_at"),
+    })
+}
+
+fn choice_from_row(row: &SqliteRow) -> Result<BranchChoice, StoryError> {
+    Ok(BranchChoice {
+        id: row.get("id"),
+        event_id: row.get("event_id"),
+        description: row.get("description"),
+        state_changes: parse_changes(row.get("state_changes"))?,
+        is_default: row.get("is_default"),
+        resolved_by: row.get("resolved_by"),
+        risk: row.get::<Option<&str>, _>("risk").and_then(Risk::parse),
+        affected: serde_json::from_str(row.get("affected"))
+            .map_err(|e| StoryError::Invalid(format!("stored affected list is not JSON: {}", e)))?,
+    })
+}
+
+const EVENT_COLUMNS: &str = "id, event_id, parent_choice_id, narrative, state_changes, CAST(created_at AS TEXT) AS created_at";
+const CHOICE_COLUMNS: &str = "id, event_id, description, state_changes, is_default, resolved_by, risk, affected";
+
+/// Adds a node to the story graph, hanging it off the most recently taken choice that
+/// has not led anywhere yet.
+pub async fn record_event(pool: &SqlitePool, world_id: i32, event_id: Option<i32>, narrative: &str) -> Result<StoryEvent, StoryError> {
+    let parent_choice_id: Option<i32> = sqlx::query_scalar(
+        "SELECT c.id FROM branch_choices c JOIN story_events e ON e.id = c.event_id
+         WHERE e.world_id = ?
+           AND c.resolved_by IS NOT NULL
+           AND NOT EXISTS (SELECT 1 FROM story_events s WHERE s.parent_choice_id = c.id)
+         ORDER BY c.resolved_at DESC, c.id DESC LIMIT 1"
+    )
+    .bind(world_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let row = sqlx::query(&format!(
+        "INSERT INTO story_events (world_id, event_id, parent_choice_id, narrative, state_changes) VALUES (?, ?, ?, ?, '[]') RETURNING {}",
+        EVENT_COLUMNS
+    ))
+    .bind(world_id)
+    .bind(event_id)
+    .bind(parent_choice_id)
+    .bind(narrative)
+    .fetch_one(pool)
+    .await?;
+    event_from_row(&row)
+}
+
+pub async fn latest_event(pool: &SqlitePool, world_id: i32) -> Result<Option<StoryEvent>, StoryError> {
+    let row = sqlx::query(&format!("SELECT {} FROM story_events WHERE world_id = ? ORDER BY id DESC LIMIT 1", EVENT_COLUMNS))
+        .bind(world_id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(event_from_row).transpose()
+}
+
+pub async fn choices_for(pool: &SqlitePool, story_event_id: i32) -> Result<Vec<BranchChoice>, StoryError> {
+    sqlx::query(&format!("SELECT {} FROM branch_choices WHERE event_id = ? ORDER BY id", CHOICE_COLUMNS))
+        .bind(story_event_id)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(choice_from_row)
+        .collect()
+}
+
+/// Stores the options offered after an event. The first one is the default taken when
+/// nobody decides within `timeout_secs`.
+pub async fn store_choices(
+    pool: &SqlitePool,
+    story_event_id: i32,
+    choices: &[ChoiceDraft],
+    timeout_secs: i64,
+) -> Result<Vec<BranchChoice>, StoryError> {
+    let mut tx = pool.begin().await?;
+    let mut stored = Vec::new();
+    for (i, choice) in choices.iter().enumerate() {
+        let json = serde_json::to_string(&choice.state_changes).map_err(|e| StoryError::Invalid(e.to_string()))?;
+        let affected = serde_json::to_string(&choice.affected).map_err(|e| StoryError::Invalid(e.to_string()))?;
+        let row = sqlx::query(&format!(
+            "INSERT INTO branch_choices (event_id, description, state_changes, is_default, risk, affected) VALUES (?, ?, ?, ?, ?, ?) RETURNING {}",
+            CHOICE_COLUMNS
+        ))
+        .bind(story_event_id)
+        .bind(&choice.description)
+        .bind(json)
+        .bind(i == 0)
+        .bind(choice.risk.as_str())
+        .bind(affected)
+        .fetch_one(&mut *tx)
+        .await?;
+        stored.push(choice_from_row(&row)?);
+    }
+    sqlx::query("UPDATE story_events SET choices_expire_at = datetime('now', ?) WHERE id = ?")
+        .bind(format!("+{} seconds", timeout_secs))
+        .bind(story_event_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(stored)
+}
+
+async fn resolve_in(tx: &mut Transaction<'_, Sqlite>, world_id: i32, choice: BranchChoice, resolved_by: &str) -> Result<Resolution, StoryError> {
+    let applied = choice.state_changes.apply_in(tx, world_id).await?;
+    sqlx::query("UPDATE branch_choices SET resolved_by = ?, resolved_at = datetime('now') WHERE id = ?")
+        .bind(resolved_by)
+        .bind(choice.id)
+        .execute(&mut **tx)
+        .await?;
+    let caused_by = if resolved_by == "player" { "Player" } else { "System" };
+    sqlx::query("INSERT INTO event_log (world_id, timestamp, description, caused_by) VALUES (?, datetime('now'), ?, ?)")
+        .bind(world_id)
+        .bind(format!("Story branch taken: {}", choice.description))
+        .bind(caused_by)
+        .execute(&mut **tx)
+        .await?;
+    Ok(Resolution { choice: BranchChoice { resolved_by: Some(resolved_by.to_string()), ..choice }, applied })
+}
+
+/// Takes the given choice, or the event's default one if its decision window has passed.
+pub async fn resolve_choice(pool: &SqlitePool, world_id: i32, choice_id: i32) -> Result<Resolution, StoryError> {
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM branch_choices WHERE id = ? AND event_id IN (SELECT id FROM story_events WHERE world_id = ?)",
+        CHOICE_COLUMNS
+    ))
+    .bind(choice_id)
+    .bind(world_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| StoryError::NotFound(format!("Branch choice {} not found", choice_id)))?;
+    let choice = choice_from_row(&row)?;
+
+    let taken: Option<String> = sqlx::query_scalar("SELECT description FROM branch_choices WHERE event_id = ? AND resolved_by IS NOT NULL")
+        .bind(choice.event_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if let Some(taken) = taken {
+        return Err(StoryError::Conflict(format!("Story event {} was already resolved with '{}'", choice.event_id, taken)));
+    }
+
+    let expired: bool = sqlx::query_scalar("SELECT COALESCE(choices_expire_at < datetime('now'), 0) FROM story_events WHERE id = ?")
+        .bind(choice.event_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    let resolution = if expired && !choice.is_default {
+        let row = sqlx::query(&format!("SELECT {} FROM branch_choices WHERE event_id = ? AND is_default = 1", CHOICE_COLUMNS))
+            .bind(choice.event_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        resolve_in(&mut tx, world_id, choice_from_row(&row)?, "timeout").await?
+    } else {
+        resolve_in(&mut tx, world_id, choice, if expired { "timeout" } else { "player" }).await?
+    };
+    tx.commit().await?;
+    Ok(resolution)
+}
+
+/// Applies the default choice of every event whose decision window has closed.
+pub async fn resolve_expired(pool: &SqlitePool, world_id: i32) -> Result<Vec<Resolution>, StoryError> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM branch_choices c
+         WHERE c.is_default = 1
+           AND c.event_id IN (SELECT id FROM story_events WHERE world_id = ? AND choices_expire_at < datetime('now'))
+           AND NOT EXISTS (SELECT 1 FROM branch_choices o WHERE o.event_id = c.event_id AND o.resolved_by IS NOT NULL)",
+        CHOICE_COLUMNS
+    ))
+    .bind(world_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut resolutions = Vec::new();
+    for row in rows {
+        let mut tx = pool.begin().await?;
+        resolutions.push(resolve_in(&mut tx, world_id, choice_from_row(&row)?, "timeout").await?);
+        tx.commit().await?;
+    }
+    Ok(resolutions)
+}
+
+/// The whole story as a forest: root events, their choices, and what followed each choice.
+pub async fn graph(pool: &SqlitePool, world_id: i32) -> Result<Vec<StoryNode>, StoryError> {
+    let events = sqlx::query(&format!("SELECT {} FROM story_events WHERE world_id = ? ORDER BY id", EVENT_COLUMNS))
+        .bind(world_id)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(event_from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut choices_by_event: HashMap<i32, Vec<BranchChoice>> = HashMap::new();
+    let choice_rows = sqlx::query(&format!(
+        "SELECT {} FROM branch_choices WHERE event_id IN (SELECT id FROM story_events WHERE world_id = ?) ORDER BY id",
+        CHOICE_COLUMNS
+    ))
+    .bind(world_id)
+    .fetch_all(pool)
+    .await?;
+    for row in choice_rows {
+        let choice = choice_from_row(&row)?;
+        choices_by_event.entry(choice.event_id).or_default().push(choice);
+    }
+    let mut events_by_parent: HashMap<Option<i32>, Vec<StoryEvent>> = HashMap::new();
+    for event in events {
+        events_by_parent.entry(event.parent_choice_id).or_default().push(event);
+    }
+
+    fn build(parent: Option<i32>, events: &mut HashMap<Option<i32>, Vec<StoryEvent>>, choices: &mut HashMap<i32, Vec<BranchChoice>>) -> Vec<StoryNode> {
+        events.remove(&parent).unwrap_or_default()
+            .into_iter()
+            .map(|event| {
+                let choices = choices.remove(&event.id).unwrap_or_default()
+                    .into_iter()
+                    .map(|choice| ChoiceNode { next: build(Some(choice.id), events, choices), choice })
+                    .collect::<Vec<_>>();
+                StoryNode { event, choices }
+            })
+            .collect()
+    }
+    Ok(build(None, &mut events_by_parent, &mut choices_by_event))
+}
@@ -0,0 +1,44 @@
+//! Who holds which ground. Factions build influence over locations from 0 to 100, and
+//! control follows influence, so territory changes hands as fighting, help and raids shift it.
+
+use sqlx::SqlitePool;
+use crate::db::{self, Influence, WorldState};
+
+/// Influence a faction needs before it can hold a location at all.
+pub const CONTROL_THRESHOLD: i32 = 30;
+
+/// The faction holding a location: the one with the most influence there, as long as it
+/// has at least [`CONTROL_THRESHOLD`] and nobody matches it.
+pub fn controller(influence: &[Influence]) -> Option<i32> {
+    let top = influence.iter().max_by_key(|i| i.influence)?;
+    let contested = influence.iter().any(|i| i.faction_id != top.faction_id && i.influence == top.influence);
+    (top.influence >= CONTROL_THRESHOLD && !contested).then_some(top.faction_id)
+}
+
+/// Describes every location whose controller differs between `before` and `after`.
+pub fn shifts(before: &WorldState, after: &WorldState) -> Vec<String> {
+    let name = |id: i32| {
+        after.factions.iter().chain(&before.factions).find(|f| f.id == id).map_or("An unknown faction", |f| f.name.as_str())
+    };
+    after.locations.iter()
+        .filter_map(|location| {
+            let was = before.locations.iter().find(|l| l.id == location.id)?.controlled_by;
+            match (was, location.controlled_by) {
+                (was, now) if was == now => None,
+                (_, Some(now)) => Some(format!("{} took control of {}", name(now), location.name)),
+                (Some(was), None) => Some(format!("{} lost its hold on {}", name(was), location.name)),
+                (None, None) => None,
+            }
+        })
+        .collect()
+}
+
+/// Logs every location that changed hands since `before`, returning the log lines.
+pub async fn log_shifts(pool: &SqlitePool, world_id: i32, before: &WorldState, caused_by: &str) -> Result<Vec<String>, sqlx::Error> {
+    let after = db::get_world_state(pool, world_id).await?;
+    let shifts = shifts(before, &after);
+    for shift in &shifts {
+        db::log_event(pool, world_id, shift, caused_by).await?;
+    }
+    Ok(shifts)
+}
@@ -0,0 +1,191 @@
+//! Getting from place to place over the route graph: the quickest way there, and what may
+//! lie in wait on each road along it.
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use crate::db::{Faction, Route, WorldState};
+
+/// However bad the road, some travellers get through.
+const MAX_ENCOUNTER_CHANCE: i32 = 75;
+
+#[derive(Serialize)]
+pub struct Leg {
+    pub from_id: i32,
+    pub to_id: i32,
+    pub travel_hours: i32,
+    pub danger: i32,
+    /// Percent chance of running into trouble on this leg.
+    pub encounter_chance: i32,
+}
+
+#[derive(Serialize)]
+pub struct Journey {
+    pub from_id: i32,
+    pub to_id: i32,
+    pub hours: i32,
+    pub legs: Vec<Leg>,
+}
+
+/// The locations one road away from `location_id`, with the road there.
+pub fn neighbours(state: &WorldState, location_id: i32) -> impl Iterator<Item = (i32, &Route)> {
+    state.routes.iter().filter_map(move |r| match location_id {
+        id if id == r.location_id => Some((r.other_id, r)),
+        id if id == r.other_id => Some((r.location_id, r)),
+        _ => None,
+    })
+}
+
+/// The quickest way from `from` to `to`, or `None` when no road leads there.
+pub fn find_path(state: &WorldState, from: i32, to: i32) -> Option<Journey> {
+    let mut best = HashMap::from([(from, 0)]);
+    let mut came_from: HashMap<i32, i32> = HashMap::new();
+    let mut queue = BinaryHeap::from([Reverse((0, from))]);
+    while let Some(Reverse((hours, here))) = queue.pop() {
+        if here == to {
+            break;
+        }
+        if hours > best[&here] {
+            continue;
+        }
+        for (next, route) in neighbours(state, here) {
+            let arrival = hours + route.travel_hours;
+            if best.get(&next).is_none_or(|known| arrival < *known) {
+                best.insert(next, arrival);
+                came_from.insert(next, here);
+                queue.push(Reverse((arrival, next)));
+            }
+        }
+    }
+
+    let hours = *best.get(&to)?;
+    let mut stops = vec![to];
+    while let Some(previous) = came_from.get(stops.last()?) {
+        stops.push(*previous);
+    }
+    stops.reverse();
+    let legs = stops.windows(2)
+        .filter_map(|pair| state.route_between(pair[0], pair[1]).map(|route| Leg {
+            from_id: pair[0],
+            to_id: pair[1],
+            travel_hours: route.travel_hours,
+            danger: route.danger,
+            encounter_chance: encounter_chance(state, route),
+        }))
+        .collect();
+    Some(Journey { from_id: from, to_id: to, hours, legs })
+}
+
+/// The next stop on the quickest way from `from` to `to`.
+pub fn next_stop(state: &WorldState, from: i32, to: i32) -> Option<i32> {
+    find_path(state, from, to)?.legs.first().map(|leg| leg.to_id)
+}
+
+/// Factions holding either end of `route`.
+fn factions_around<'a>(state: &'a WorldState, route: &Route) -> impl Iterator<Item = &'a Faction> {
+    let ends = [route.location_id, route.other_id];
+    state.factions.iter().filter(move |f| {
+        state.locations.iter().any(|l| ends.contains(&l.id) && l.controlled_by == Some(f.id))
+    })
+}
+
+/// Half the road's danger, raised by the power of hostile factions holding its ends and
+/// lowered by friendly ones, in percent.
+pub fn encounter_chance(state: &WorldState, route: &Route) -> i32 {
+    let sway: i32 = factions_around(state, route)
+        .map(|f| match f.relation.as_str() {
+            "Hostile" => f.power / 10,
+            "Friendly" => -f.power / 10,
+            _ => 0,
+        })
+        .sum();
+    (route.danger / 2 + sway).clamp(0, MAX_ENCOUNTER_CHANCE)
+}
+
+/// Rolls for trouble leg by leg, returning the leg where the travellers are stopped and the
+/// hostile faction behind it (the strongest around, else any), if any.
+pub fn roll_encounter<'a, 'j>(state: &'a WorldState, journey: &'j Journey, rng: &mut impl Rng) -> Option<(&'j Leg, Option<&'a Faction>)> {
+    let leg = journey.legs.iter().find(|leg| rng.gen_range(0..100) < leg.encounter_chance)?;
+    let route = state.route_between(leg.from_id, leg.to_id)?;
+    let hostile: Vec<&Faction> = state.factions.iter().filter(|f| f.relation == "Hostile").collect();
+    let ambusher = factions_around(state, route)
+        .filter(|f| f.relation == "Hostile")
+        .max_by_key(|f| f.power)
+        .or_else(|| hostile.choose(rng).copied());
+    Some((leg, ambusher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Location, World};
+
+    fn location(id: i32, controlled_by: Option<i32>) -> Location {
+        Location { id, name: format!("Location {}", id), prosperity: 50, safety: 50, influence: Vec::new(), controlled_by }
+    }
+
+    fn route(a: i32, b: i32, travel_hours: i32, danger: i32) -> Route {
+        Route { location_id: a.min(b), other_id: a.max(b), travel_hours, danger }
+    }
+
+    fn faction(id: i32, power: i32, relation: &str) -> Faction {
+        Faction { id, name: format!("Faction {}", id), power, relation: relation.to_string() }
+    }
+
+    /// A slow road from 1 to 2, a quicker way round through 3, and 4 cut off from everything.
+    fn world() -> WorldState {
+        WorldState {
+            locations: (1..=4).map(|id| location(id, None)).collect(),
+            factions: Vec::new(),
+            npcs: Vec::new(),
+            players: Vec::new(),
+            parties: Vec::new(),
+            faction_relations: Vec::new(),
+            routes: vec![route(1, 2, 20, 10), route(1, 3, 4, 40), route(3, 2, 5, 60)],
+            quests: Vec::new(),
+            world: World { id: 1, name: "Test".to_string(), seed: "default".to_string(), tension: 0, story_phase: "Build-Up".to_string(), arc: "default".to_string(), act: 1 },
+        }
+    }
+
+    #[test]
+    fn takes_the_quickest_way_round_over_a_slow_direct_road() {
+        let state = world();
+        let journey = find_path(&state, 1, 2).unwrap();
+        assert_eq!(journey.hours, 9);
+        let stops: Vec<(i32, i32)> = journey.legs.iter().map(|leg| (leg.from_id, leg.to_id)).collect();
+        assert_eq!(stops, [(1, 3), (3, 2)]);
+        assert_eq!(journey.legs.iter().map(|leg| leg.travel_hours).sum::<i32>(), journey.hours);
+        assert_eq!(next_stop(&state, 1, 2), Some(3));
+        assert_eq!(find_path(&state, 2, 1).unwrap().hours, 9);
+    }
+
+    #[test]
+    fn finds_no_way_to_an_unreachable_place() {
+        let state = world();
+        assert!(find_path(&state, 1, 4).is_none());
+        assert!(find_path(&state, 4, 1).is_none());
+        assert_eq!(next_stop(&state, 1, 4), None);
+    }
+
+    #[test]
+    fn staying_put_takes_no_time() {
+        let journey = find_path(&world(), 2, 2).unwrap();
+        assert_eq!(journey.hours, 0);
+        assert!(journey.legs.is_empty());
+        assert_eq!(next_stop(&world(), 2, 2), None);
+    }
+
+    #[test]
+    fn encounter_chance_stays_within_bounds() {
+        let mut state = world();
+        assert_eq!(encounter_chance(&state, &route(1, 3, 4, 40)), 20);
+        state.factions = vec![faction(1, 80, "Hostile"), faction(2, 90, "Friendly")];
+        state.locations[0].controlled_by = Some(1);
+        assert_eq!(encounter_chance(&state, &route(1, 3, 4, 40)), 28);
+        assert_eq!(encounter_chance(&state, &route(1, 3, 4, 180)), MAX_ENCOUNTER_CHANCE);
+        state.locations[0].controlled_by = Some(2);
+        assert_eq!(encounter_chance(&state, &route(1, 3, 4, 10)), 0);
+    }
+}